@@ -1,30 +1,8 @@
 #!/usr/bin/env bash
 
-TETROMINOS=(
-	I
-	J
-	L
-	O
-	S
-	T
-	Z
-)
-
-BASE_JSON='{"tick": 0, "payload": {"totems": []}}'
-
 function generate_problem() {
-	local size="$1" shape
-	local json="$BASE_JSON"
-	while [ "$size" -gt 0 ]; do
-		shape=$((RANDOM % ${#TETROMINOS[@]}))
-		json="$(
-			jq -c --arg shape "${TETROMINOS[$shape]}" \
-				'.payload.totems |= . + [{"shape": $shape}]' \
-				<<< "$json"
-		)"
-		size=$((size - 1))
-	done
-	printf '%s\n' "$json"
+	local size="$1"
+	cargo run --quiet --release --bin generate -- --totems "$size"
 }
 
 function server_loop() {
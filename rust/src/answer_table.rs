@@ -0,0 +1,165 @@
+// Precomputed answers for every possible bag of the small levels, so that they are answered by a
+// lookup instead of a search. Generated offline by 'bin/precompute_answers.rs', with the search of
+// 'certificates': answers are optimal, and proven so unless the search ran out of nodes.
+//
+// By default, the table covers levels 1 to 5 (up to 16 totems): 77,861 bags in total (7 + 28 +
+// 210 + 3003 + 74,613). Bags of up to 8 totems are solved live instantly anyway, the table is
+// mostly worth it for the 16 totems bags, about half of which get proven optimal. It takes ~15
+// minutes to generate, and is too big to be committed (~16MB), like the rectangle inventory.
+// Each totem is stored compactly as (shape, rotation, x, y), where (x, y) is the offset of the
+// rotation in 'ShapeVariant::get_rotations'.
+
+use crate::{
+    game_interface::{Totem, TotemAnswer, TotemBag, TOTEM_COUNT},
+    shape_info::ShapeVariant,
+    subset_sum::{hash_bag, TotemBagHash},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+};
+
+// Last level (0-indexed) of the table, by default.
+pub const MAX_TABLE_LEVEL: usize = 4;
+
+pub struct TableAnswer {
+    pub totems: Vec<TotemAnswer>,
+    // If the answer is proven optimal.
+    pub proven: bool,
+}
+
+// (shape, rotation, x, y)
+type CompactTotem = (usize, usize, usize, usize);
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    bag: [usize; TOTEM_COUNT],
+    proven: bool,
+    totems: Vec<CompactTotem>,
+}
+
+fn compact(totem: &TotemAnswer) -> CompactTotem {
+    let x = totem.coordinates.iter().map(|p| p.0).min().unwrap();
+    let y = totem.coordinates.iter().map(|p| p.1).min().unwrap();
+    let rotation = ShapeVariant::find_rotation(&totem.shape, &totem.coordinates)
+        .expect("Totem coordinates don't match its shape");
+    (totem.shape as usize, rotation, x, y)
+}
+
+fn expand(totem: &CompactTotem) -> io::Result<TotemAnswer> {
+    let &(shape, rotation, x, y) = totem;
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid totem {:?}", totem));
+    if shape >= TOTEM_COUNT {
+        return Err(invalid());
+    }
+    let shape = Totem::from(shape);
+    let variant = ShapeVariant::get_rotations(&shape).get(rotation).ok_or_else(invalid)?;
+    Ok(TotemAnswer::new(shape, variant.coords).offset_by(x, y))
+}
+
+// Every bag of 'num_totems' totems.
+pub fn all_bags(num_totems: usize) -> Vec<TotemBag> {
+    fn fill(bag: &mut TotemBag, index: usize, left: usize, bags: &mut Vec<TotemBag>) {
+        if index == TOTEM_COUNT - 1 {
+            bag[index] = left;
+            bags.push(bag.clone());
+            return;
+        }
+        for count in 0..=left {
+            bag[index] = count;
+            fill(bag, index + 1, left - count, bags);
+        }
+    }
+    let mut bags = Vec::new();
+    fill(&mut TotemBag::new(), 0, num_totems, &mut bags);
+    bags
+}
+
+#[derive(Default)]
+pub struct AnswerTable {
+    answers: HashMap<TotemBagHash, TableAnswer>,
+}
+
+impl AnswerTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let entries: Vec<Entry> = serde_json::from_reader(BufReader::new(File::open(path)?)).map_err(io::Error::from)?;
+        let mut table = AnswerTable::new();
+        for entry in entries {
+            let totems = entry.totems.iter().map(expand).collect::<io::Result<_>>()?;
+            table.insert(&TotemBag(entry.bag), totems, entry.proven);
+        }
+        Ok(table)
+    }
+
+    // Entries are saved in a stable order (by bag), so that regenerating the table gives a small diff.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut entries: Vec<Entry> = self.answers.values().map(|answer| {
+            let bag = TotemBag::from_iter(answer.totems.iter().map(|totem| totem.shape));
+            Entry { bag: bag.0, proven: answer.proven, totems: answer.totems.iter().map(compact).collect() }
+        }).collect();
+        entries.sort_by_key(|entry| (entry.bag.iter().sum::<usize>(), entry.bag));
+        serde_json::to_writer(BufWriter::new(File::create(path)?), &entries).map_err(io::Error::from)
+    }
+
+    pub fn insert(&mut self, bag: &TotemBag, totems: Vec<TotemAnswer>, proven: bool) {
+        self.answers.insert(hash_bag(bag), TableAnswer { totems, proven });
+    }
+
+    pub fn get(&self, bag: &TotemBag) -> Option<&TableAnswer> {
+        self.answers.get(&hash_bag(bag))
+    }
+
+    pub fn len(&self) -> usize {
+        self.answers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.answers.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        certificates::{optimal_answer, DEFAULT_MAX_NODES},
+        game_interface::Answer,
+        instances::question_from_bag,
+        scoring::answer_score,
+    };
+
+    #[test]
+    fn optimal_answers_roundtrip() {
+        assert_eq!(all_bags(2).len(), 28);
+        assert_eq!(all_bags(4).len(), 210);
+
+        let mut table = AnswerTable::new();
+        for bag in all_bags(2) {
+            let (totems, proven) = optimal_answer(&bag, DEFAULT_MAX_NODES).unwrap();
+            assert!(proven);
+            table.insert(&bag, totems, proven);
+        }
+        let path = std::env::temp_dir().join(format!("answer_table_test_{}.json", std::process::id()));
+        table.save(&path).unwrap();
+        let table = AnswerTable::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(table.len(), 28);
+        for bag in all_bags(2) {
+            let answer = table.get(&bag).unwrap();
+            let score = answer_score(&question_from_bag(&bag), &Answer::new(answer.totems.clone()));
+            assert!(score.is_ok(), "{:?}", bag);
+        }
+        // Two 'O's: 2x4 and 4x2 are the best (a 3x3 can't fit them).
+        let score = answer_score(&question_from_bag(&TotemBag([0, 0, 0, 2, 0, 0, 0])),
+                                 &Answer::new(table.get(&TotemBag([0, 0, 0, 2, 0, 0, 0])).unwrap().totems.clone()));
+        assert_eq!(score, Ok(6f32));
+    }
+}
@@ -0,0 +1,104 @@
+// Calibrates the difficulty model used by the hybrid solver (see 'difficulty.rs') from the JSON
+// reports of 'evaluate', e.g.:
+//   cargo run --release --bin evaluate -- --level 9 --trials 500 --output json > level_9.json
+//   cargo run --release --bin calibrate_difficulty -- level_9.json
+// Reports of the same dimensions are calibrated together. Models of other dimensions already in
+// the model file are kept.
+// Note that the statistics printed include the bags that are known to be impossible (odd 'T'
+// shapes), which the model does not learn from.
+
+extern crate application;
+
+use application::{
+    difficulty::{DifficultyModel, Observation},
+    game_interface::{TotemBag, TOTEM_COUNT},
+    hybrid_solver::DIFFICULTY_MODEL_PATH,
+};
+use clap::{Arg, App};
+use serde::Deserialize;
+use std::{collections::BTreeMap, fs, path::Path};
+
+// Parts of an 'evaluate' report that we need.
+#[derive(Deserialize)]
+struct Summary {
+    width: usize,
+    height: usize,
+}
+
+#[derive(Deserialize)]
+struct TrialResult {
+    bag: [usize; TOTEM_COUNT],
+    success: bool,
+    seconds: f64,
+}
+
+#[derive(Deserialize)]
+struct Report {
+    summary: Summary,
+    results: Vec<TrialResult>,
+}
+
+fn load_report(path: &str) -> Result<Report, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    serde_json::from_str(&text).map_err(|e| format!("{} is not a (non A/B) evaluate report: {}", path, e))
+}
+
+// Brier score (mean squared error of the predicted probabilities) and mean predicted seconds.
+fn in_sample_error(model: &DifficultyModel, width: usize, height: usize, observations: &[Observation]) -> (f64, f64) {
+    let (mut brier, mut seconds) = (0f64, 0f64);
+    for observation in observations {
+        let prediction = model.predict(width, height, &observation.bag).unwrap();
+        let target = if observation.success { 1f64 } else { 0f64 };
+        brier += (prediction.success_probability - target).powi(2);
+        seconds += prediction.expected_seconds;
+    }
+    let n = observations.len() as f64;
+    (brier / n, seconds / n)
+}
+
+fn main() {
+    let matches = App::new("Coveo 2022 Inscription Difficulty Calibration")
+                          .arg(Arg::with_name("reports")
+                               .value_name("REPORT")
+                               .help("JSON reports of 'evaluate --output json'")
+                               .required(true)
+                               .multiple(true))
+                          .arg(Arg::with_name("model")
+                               .value_name("PATH")
+                               .long("model")
+                               .help("Model file to update")
+                               .default_value(DIFFICULTY_MODEL_PATH))
+                          .get_matches();
+    let model_path = Path::new(matches.value_of("model").unwrap());
+    let mut model = if model_path.exists() {
+        DifficultyModel::load(model_path).expect("Could not load the existing model")
+    } else {
+        DifficultyModel::new()
+    };
+
+    let mut observations: BTreeMap<(usize, usize), Vec<Observation>> = BTreeMap::new();
+    for path in matches.values_of("reports").unwrap() {
+        let report = load_report(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        let dims = (report.summary.width, report.summary.height);
+        observations.entry(dims).or_default().extend(report.results.into_iter().map(|result| Observation {
+            bag: TotemBag(result.bag),
+            success: result.success,
+            seconds: result.seconds,
+        }));
+    }
+
+    for ((width, height), observations) in &observations {
+        model.calibrate(*width, *height, observations);
+        let successes = observations.iter().filter(|o| o.success).count();
+        let mean_seconds = observations.iter().map(|o| o.seconds).sum::<f64>() / observations.len() as f64;
+        let (brier, predicted_seconds) = in_sample_error(&model, *width, *height, observations);
+        println!("{}x{}: {} observations, {:.1}% packed, {:.3}s mean. Brier score: {:.4}, {:.3}s mean predicted.",
+                 width, height, observations.len(), successes as f64 * 100f64 / observations.len() as f64,
+                 mean_seconds, brier, predicted_seconds);
+    }
+    model.save(model_path).expect("Could not save the model");
+    println!("Saved model to {}.", model_path.display());
+}
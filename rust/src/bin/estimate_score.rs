@@ -0,0 +1,210 @@
+// Tool that estimates the distribution of our total score over a full round of 10 levels, instead
+// of looping until we get lucky like 'perfect_score'.
+// Each level is sampled independently (like 'evaluate'), then the per-level score distributions
+// are combined, assuming levels are independent. This gives the expected score, its percentiles
+// and the probability of a perfect score (and the expected number of runs to get one).
+
+extern crate application;
+
+use application::{
+    cli::{is_positive_integer, is_valid_seed},
+    game_interface::Answer,
+    hybrid_solver::HybridSolver,
+    instances::{self, InstanceGenerator, ShapeDistribution},
+    logging,
+    scoring::{answer_score, OptimalDimensions},
+    stats::{binomial_confidence_interval, ScoreDistribution},
+};
+use clap::{Arg, App};
+use rand::Rng;
+use serde::Serialize;
+use std::time::Instant;
+
+const NUM_LEVELS: usize = 10;
+const PERCENTILES: [f64; 7] = [0.01, 0.05, 0.25, 0.5, 0.75, 0.95, 0.99];
+
+#[derive(Serialize)]
+struct LevelEstimate {
+    level: usize,  // 1-indexed
+    num_totems: usize,
+    optimal_score: f32,
+    samples: u64,
+    optimal: u64,
+    p_optimal: f64,
+    ci_lower: f64,
+    ci_upper: f64,
+    mean_score: f64,
+    // Invalid answers or answers over the time limit, which get a score of 0.
+    failures: u64,
+    mean_seconds: f64,
+    #[serde(skip)]
+    scores: Vec<f64>,
+}
+
+#[derive(Serialize)]
+struct RoundEstimate {
+    optimal_score: f64,
+    expected_score: f64,
+    // (percentile, score)
+    percentiles: Vec<(f64, f64)>,
+    p_perfect: f64,
+    // Products of the per-level 95% interval bounds. This is conservative: the real interval of
+    // the product is tighter.
+    p_perfect_lower: f64,
+    p_perfect_upper: f64,
+    expected_runs_to_perfect: f64,
+}
+
+#[derive(Serialize)]
+struct Report {
+    levels: Vec<LevelEstimate>,
+    round: RoundEstimate,
+}
+
+// Solves 'samples' random instances of a level (0-indexed) and records the score of each.
+fn estimate_level(solver: &HybridSolver, optimal_dims: &OptimalDimensions, level: usize, samples: u64,
+                  generator: &InstanceGenerator, seed: u64, time_limit: f64) -> LevelEstimate {
+    let mut rng = instances::seeded_rng(seed, level as u64);
+    let optimal_score = optimal_dims.level_optimal_score(level);
+    let (mut optimal, mut failures, mut total_seconds) = (0, 0, 0f64);
+    let mut scores = Vec::with_capacity(samples as usize);
+    for _ in 0..samples {
+        let question = generator.level(level, &mut rng);
+        let start_time = Instant::now();
+        let answer = Answer::new(solver.full_solve(&question.get_totem_bag(), level));
+        let seconds = start_time.elapsed().as_secs_f64();
+        total_seconds += seconds;
+        let score = match answer_score(&question, &answer) {
+            Ok(score) if seconds < time_limit => score,
+            _ => {
+                failures += 1;
+                0f32
+            }
+        };
+        if (optimal_score - score).abs() <= 1.0e-5 {
+            optimal += 1;
+        }
+        scores.push(score as f64);
+    }
+    let (ci_lower, ci_upper) = binomial_confidence_interval(optimal, samples);
+    LevelEstimate {
+        level: level + 1,
+        num_totems: 1 << level,
+        optimal_score,
+        samples, optimal,
+        p_optimal: optimal as f64 / samples as f64,
+        ci_lower, ci_upper,
+        mean_score: scores.iter().sum::<f64>() / samples as f64,
+        failures,
+        mean_seconds: total_seconds / samples as f64,
+        scores,
+    }
+}
+
+fn estimate_round(levels: &[LevelEstimate]) -> RoundEstimate {
+    let round = levels.iter()
+        .map(|level| ScoreDistribution::from_samples(&level.scores))
+        .reduce(|round, level| round.sum(&level))
+        .unwrap();
+    let optimal_score: f64 = levels.iter().map(|level| level.optimal_score as f64).sum();
+    let p_perfect = round.probability_at_least(optimal_score);
+    RoundEstimate {
+        optimal_score,
+        expected_score: round.mean(),
+        percentiles: PERCENTILES.iter().map(|q| (*q, round.percentile(*q))).collect(),
+        p_perfect,
+        p_perfect_lower: levels.iter().map(|level| level.ci_lower).product(),
+        p_perfect_upper: levels.iter().map(|level| level.ci_upper).product(),
+        expected_runs_to_perfect: 1f64 / p_perfect,
+    }
+}
+
+fn print_report(report: &Report) {
+    println!("| Level  (# totems) | Optimal score | Probability (95% interval)  | Mean score | Failures | Mean time |");
+    println!("| ----------------- | ------------- | --------------------------- | ---------- | -------- | --------- |");
+    for level in &report.levels {
+        println!("| {:<2} ({:>3} totems)   | {:>13.1} | {:>5.1}%  [{:>5.1}%, {:>5.1}%]    | {:>10.1} | {:>8} | {:>8.3}s |",
+                 level.level, level.num_totems, level.optimal_score, level.p_optimal * 100f64,
+                 level.ci_lower * 100f64, level.ci_upper * 100f64, level.mean_score, level.failures,
+                 level.mean_seconds);
+    }
+    let round = &report.round;
+    println!();
+    println!("Optimal round score: {:.2}", round.optimal_score);
+    println!("Expected round score: {:.2}", round.expected_score);
+    for (q, score) in &round.percentiles {
+        println!("  p{:<2} = {:.2}", (q * 100f64).round(), score);
+    }
+    println!("Perfect score probability: {:.3}%  (conservative 95% interval [{:.3}%, {:.3}%])",
+             round.p_perfect * 100f64, round.p_perfect_lower * 100f64, round.p_perfect_upper * 100f64);
+    if round.p_perfect > 0f64 {
+        println!("Expected runs to a perfect score: {:.0}", round.expected_runs_to_perfect);
+    } else {
+        println!("No perfect score possible with these samples, some level was never optimal.");
+    }
+}
+
+fn is_valid_time_limit(time_limit: String) -> Result<(), String> {
+    match time_limit.parse::<f64>() {
+        Ok(time_limit) if time_limit > 0f64 => Ok(()),
+        _ => Err(String::from("time limit must be a positive number of seconds")),
+    }
+}
+
+fn main() {
+    logging::init(tracing::Level::WARN);
+    let matches = App::new("Coveo 2022 Inscription Score Estimation")
+                          .arg(Arg::with_name("samples")
+                               .value_name("SAMPLES")
+                               .long("samples")
+                               .help("Number of instances to solve per level")
+                               .validator(is_positive_integer)
+                               .default_value("200"))
+                          .arg(Arg::with_name("seed")
+                               .value_name("SEED")
+                               .long("seed")
+                               .help("Seed used to generate the instances. Random if not set.")
+                               .validator(is_valid_seed))
+                          .arg(Arg::with_name("time_limit")
+                               .value_name("SECONDS")
+                               .long("time-limit")
+                               .help("Answers that take at least this long get a score of 0, like on the server.")
+                               .validator(is_valid_time_limit)
+                               .default_value("1"))
+                          .arg(Arg::with_name("only_even_t_shapes")
+                               .long("only-even-t-shapes")
+                               .help("If puzzle instances with an odd number of 'T' shapes should not be generated \
+                                      (since they can't perfectly pack a rectangle).")
+                               .takes_value(false))
+                          .arg(Arg::with_name("output")
+                               .value_name("FORMAT")
+                               .long("output")
+                               .help("Output format")
+                               .possible_values(&["text", "json"])
+                               .default_value("text"))
+                          .get_matches();
+    let samples: u64 = matches.value_of("samples").unwrap().parse().unwrap();
+    let seed: u64 = matches.value_of("seed").map_or_else(|| rand::thread_rng().gen(), |seed| seed.parse().unwrap());
+    let time_limit: f64 = matches.value_of("time_limit").unwrap().parse().unwrap();
+    let generator = InstanceGenerator::with_options(
+        ShapeDistribution::Uniform, /*allow_odd_t_shapes=*/!matches.is_present("only_even_t_shapes"));
+    let text_output = matches.value_of("output").unwrap() == "text";
+
+    let optimal_dims = OptimalDimensions::new();
+    let mut solver = HybridSolver::with_options(/*multithreading=*/true);
+    // Estimate the solver itself, not how many of the sampled bags were seen before.
+    solver.set_solution_cache(None);
+    let levels: Vec<LevelEstimate> = (0..NUM_LEVELS).map(|level| {
+        if text_output {
+            println!("Sampling level {}...", level + 1);
+        }
+        estimate_level(&solver, &optimal_dims, level, samples, &generator, seed, time_limit)
+    }).collect();
+    let round = estimate_round(&levels);
+    let report = Report { levels, round };
+    if text_output {
+        print_report(&report);
+    } else {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    }
+}
@@ -1,107 +1,472 @@
 // Evaluate the perfect packing probability of a solver at a given level.
 // Useful to compare methods and to do performance profiling.
+// With '--output json' or '--output csv', results are written to stdout in a machine-readable
+// format, to script comparisons and plot results.
+//
+// With '--compare', runs an A/B comparison instead: both solvers get the exact same instances,
+// and we report paired wins/losses with a McNemar test, stopping once the difference is significant.
+//
+// With '--certify', failed attempts are checked for a proof that the bag can't be packed in the
+// dimensions at all, or for a packing that the solver missed (see 'certificates'). Failures are
+// then reported in buckets: proven infeasible, solver misses, unproven (the search ran out of
+// nodes) and timed out.
 
 extern crate application;
 
 use application::{
-    game_interface::{Question, Totem, TotemQuestion, TOTEMS},
-    hybrid_solver,
+    certificates::{self, Feasibility, DEFAULT_MAX_NODES},
+    cli::{is_positive_integer, is_valid_alpha, is_valid_bag, is_valid_dims, is_valid_level, is_valid_seed,
+          is_valid_shape_weights, parse_dims},
+    logging,
+    game_interface::{Answer, TotemBag},
+    instances::{self, InstanceGenerator, ShapeDistribution},
+    planted,
+    render::{self, RenderOptions},
     solver::Solver,
+    solver_config::ConfiguredSolver,
     scoring::{score, OptimalDimensions},
+    stats::{binomial_confidence_interval, mcnemar_p_value, mean_with_margin},
+    trace::{observed_attempt, TraceRecorder},
 };
 use clap::{Arg, App};
-use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::Serialize;
+use std::{
+    path::PathBuf,
+    sync::{atomic::{AtomicU64, Ordering}, mpsc},
+    thread,
+    time::Instant,
+};
+use tracing::warn;
 
 // Imports only for visualization, when enabled.
 #[cfg(feature = "visualize")]
-use application::{
-    game_interface::Answer,
-    solver,
-};
+use application::solver;
 
-type SelectedSolver = hybrid_solver::HybridSolver;
-
-// Returns 95% confidence interval for the success probability given a given amount of 'successes'
-// over a given amount of 'trials'.
-// https://en.wikipedia.org/wiki/Binomial_proportion_confidence_interval
-// Using the Wilson score interval.
-fn binomial_confidence_interval(successes: u64, trials: u64) -> (f64, f64) {
-    let n = trials as f64;
-    let p_hat = successes as f64 / n;
-    let z: f64 = 1.96;  // alpha = 1 - 0.95 = 0.05 for 95% confidence, 1-alpha/2=0.975 => z=1.96
-    // Give names to variables for the general form of a +- b * c.sqrt(), to make it (a bit) easier to follow.
-    let a = (p_hat + z * z / (2f64 * n)) / (1f64 + z * z / n);
-    let b = z / (1f64 + z * z / n);
-    let c = p_hat * (1f64 - p_hat) / n + z * z / (4f64 * n * n);
-    let lower = a - b * c.sqrt();
-    (if lower >= 0f64 { lower } else { 0f64 }, a + b * c.sqrt())
-}
-
-// Generates random instances of the given level and reports optimal packing probabilities.
-// If `allow_odd_t_shapes` is set to false, no instances with an odd number of `T` shapes will be generated,
-// since optimally packing a rectangle with an odd number of `T` shapes is impossible (see the
-// README for more details).
-fn debug_packing_probability(level: usize, solver: &SelectedSolver, allow_odd_t_shapes: bool) {
-    let num_totems = 1 << level;
-    let mut rng = rand::thread_rng();
-    let mut total_runs = 0;
-    let mut perfect_packs = 0;
-    let mut perfect_pack_seconds = 0f64;
-    let mut last_time: std::time::Instant = std::time::Instant::now();
-    let start_time = std::time::Instant::now();
-
-    let optimal_dims = OptimalDimensions::new();
-    let (w, h) = optimal_dims.level_dims(level).first().unwrap();
-    println!("Searching for perfect packs for level {}, with {} totems. Need to pack {}x{} for a score of {}.",
-             level + 1, num_totems, *w, *h, score(num_totems, *w, *h));
-    loop {
-        
-        let question = Question {
-            totems: (0..num_totems).map(
-                |_| TotemQuestion { shape: *TOTEMS.choose(&mut rng).unwrap() }).collect()
-        };
-        let bag = question.get_totem_bag();
-        if !allow_odd_t_shapes && bag[Totem::T] % 2 == 1 {  // Odd 'T's can't be solved, regenerate.
-            continue;
+// Minimum number of paired trials before an A/B comparison can stop early.
+const MIN_AB_TRIALS: u64 = 20;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+struct Config {
+    level: usize,  // 0-indexed
+    width: usize,
+    height: usize,
+    // None to run forever (or until significant, for A/B comparisons).
+    trials: Option<u64>,
+    seed: u64,
+    threads: usize,
+    // Solutions that take longer than this are counted as failures. Note that solvers are not
+    // interrupted, they run to completion.
+    timeout: Option<f64>,
+    // Significance level at which A/B comparisons stop.
+    alpha: f64,
+    output: OutputFormat,
+    generator: InstanceGenerator,
+    // If set, every trial packs this bag instead of a generated one.
+    bag: Option<TotemBag>,
+    // If set, trials pack planted instances (see 'planted'), which are known to have a perfect packing.
+    planted: bool,
+    // If set, failed attempts look for a proof that the bag can't be packed.
+    certify: bool,
+    // If set, solutions are rendered to SVG files in this directory.
+    render_dir: Option<PathBuf>,
+    // If set, the search of each attempt is recorded to a JSON trace in this directory (see 'trace').
+    // Note that this makes solvers run single threaded, and slower.
+    trace_dir: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct Attempt {
+    success: bool,
+    timed_out: bool,
+    seconds: f64,
+    // Strategy that the solver plans for these dims (see 'ConfiguredSolver::strategy'). The answer
+    // may come from elsewhere, e.g. the hybrid solver's constructive floor.
+    planned_strategy: &'static str,
+    // If no packing was found, and there is none (only checked with '--certify').
+    proven_infeasible: bool,
+    // If no packing was found, but there is one: planted instances, or one found by the search of
+    // '--certify'.
+    proven_feasible: bool,
+}
+
+#[derive(Serialize)]
+struct TrialResult {
+    trial: u64,
+    bag: [usize; 7],
+    #[serde(flatten)]
+    attempt: Attempt,
+}
+
+#[derive(Serialize)]
+struct PairedResult {
+    trial: u64,
+    bag: [usize; 7],
+    a: Attempt,
+    b: Attempt,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    solver: String,
+    level: usize,
+    width: usize,
+    height: usize,
+    seed: u64,
+    trials: u64,
+    successes: u64,
+    success_rate: f64,
+    ci_lower: f64,
+    ci_upper: f64,
+    seconds_per_trial: f64,
+    // Failures by bucket, only with '--certify': proven infeasible, proven feasible (solver misses),
+    // neither (the search ran out of nodes), and timed out.
+    proven_infeasible: Option<u64>,
+    misses: Option<u64>,
+    unproven: Option<u64>,
+    timeouts: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Report<S: Serialize, R: Serialize> {
+    summary: S,
+    results: Vec<R>,
+}
+
+// Running statistics of an A/B comparison.
+#[derive(Serialize, Default)]
+struct PairedSummary {
+    solver_a: String,
+    solver_b: String,
+    level: usize,
+    width: usize,
+    height: usize,
+    seed: u64,
+    trials: u64,
+    a_successes: u64,
+    b_successes: u64,
+    // Instances solved by A but not B, and vice versa.
+    a_wins: u64,
+    b_wins: u64,
+    p_value: f64,
+    significant: bool,
+    // Mean of (B time - A time) per instance, in seconds, with its 95% confidence half-width.
+    mean_time_difference: f64,
+    time_difference_margin: f64,
+    #[serde(skip)]
+    time_differences: Vec<f64>,
+}
+
+impl PairedSummary {
+    fn add(&mut self, result: &PairedResult) {
+        self.trials += 1;
+        self.a_successes += result.a.success as u64;
+        self.b_successes += result.b.success as u64;
+        self.a_wins += (result.a.success && !result.b.success) as u64;
+        self.b_wins += (!result.a.success && result.b.success) as u64;
+        self.p_value = mcnemar_p_value(self.a_wins, self.b_wins);
+        self.time_differences.push(result.b.seconds - result.a.seconds);
+        let (mean, margin) = mean_with_margin(&self.time_differences);
+        self.mean_time_difference = mean;
+        self.time_difference_margin = margin;
+    }
+
+    fn print(&self) {
+        let (a_lower, a_upper) = binomial_confidence_interval(self.a_successes, self.trials);
+        let (b_lower, b_upper) = binomial_confidence_interval(self.b_successes, self.trials);
+        println!("{} trials   A: {} [{:.1}%, {:.1}%]   B: {} [{:.1}%, {:.1}%]   only A: {}   only B: {}   \
+                  p={:.4}   B-A time: {:+.3}s (+-{:.3}s)",
+                 self.trials, self.a_successes, a_lower * 100f64, a_upper * 100f64,
+                 self.b_successes, b_lower * 100f64, b_upper * 100f64, self.a_wins, self.b_wins,
+                 self.p_value, self.mean_time_difference, self.time_difference_margin);
+    }
+}
+
+// Generates the instance of a given trial. Each trial has its own RNG stream, so that the instances
+// only depend on the seed, whatever the number of threads.
+fn trial_bag(config: &Config, trial: u64) -> TotemBag {
+    let mut rng = instances::seeded_rng(config.seed, trial);
+    match &config.bag {
+        Some(bag) => bag.clone(),
+        None if config.planted => planted::generate(config.width, config.height, &mut rng)
+            .expect("Could not generate a planted instance").bag,
+        None => config.generator.bag(1 << config.level, &mut rng),
+    }
+}
+
+// 'name' identifies the attempt in rendered files.
+fn attempt(solver: &ConfiguredSolver, config: &Config, bag: &TotemBag, name: &str) -> Attempt {
+    let attempt_time = Instant::now();
+    let mut recorder = config.trace_dir.as_ref().map(|_| TraceRecorder::new());
+    let sln = match &mut recorder {
+        Some(recorder) => observed_attempt(solver, config.width, config.height, bag, recorder),
+        None => solver.try_solve(config.width, config.height, bag),
+    };
+    let seconds = attempt_time.elapsed().as_secs_f64();
+    if let (Some(dir), Some(recorder)) = (&config.trace_dir, &recorder) {
+        let path = dir.join(format!("{}.json", name));
+        if let Err(e) = recorder.save(&path) {
+            warn!(path = %path.display(), error = %e, "Could not save trace.");
+        }
+    }
+    let timed_out = config.timeout.is_some_and(|timeout| seconds > timeout);
+    #[cfg(feature = "visualize")]
+    if let Some(sln) = &sln {
+        solver::visualize(&Answer::new(sln.clone()));  // To visually make sure the solutions are valid.
+    }
+    if let (Some(dir), Some(sln)) = (&config.render_dir, &sln) {
+        let options = RenderOptions { min_dims: Some((config.width, config.height)), ..Default::default() };
+        let path = dir.join(format!("{}.svg", name));
+        if let Err(e) = render::save(&Answer::new(sln.clone()), &path, &options) {
+            warn!(path = %path.display(), error = %e, "Could not render solution.");
+        }
+    }
+    // Timeouts are a bucket of their own. Planted instances are known to have a packing.
+    let feasibility = match () {
+        _ if !config.certify || sln.is_some() || timed_out => None,
+        _ if config.planted => Some(Feasibility::Feasible),
+        _ => Some(certificates::feasibility(config.width, config.height, bag, DEFAULT_MAX_NODES, None)),
+    };
+    Attempt {
+        success: sln.is_some() && !timed_out,
+        timed_out,
+        seconds,
+        planned_strategy: solver.strategy(config.width, config.height, bag),
+        proven_infeasible: matches!(feasibility, Some(Feasibility::Infeasible(_))),
+        proven_feasible: feasibility == Some(Feasibility::Feasible),
+    }
+}
+
+// Runs 'run_trial' for trials 0, 1, 2... on 'config.threads' threads, passing the results to
+// 'on_result' as they come in (not necessarily in order). Stops after 'config.trials' trials, or
+// once 'on_result' returns false.
+fn run_trials<R: Send>(config: &Config, run_trial: impl Fn(u64) -> R + Sync, mut on_result: impl FnMut(R) -> bool) {
+    let next_trial = AtomicU64::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..config.threads {
+            let sender = sender.clone();
+            let (next_trial, run_trial) = (&next_trial, &run_trial);
+            scope.spawn(move || loop {
+                let trial = next_trial.fetch_add(1, Ordering::Relaxed);
+                if config.trials.is_some_and(|trials| trial >= trials) {
+                    break;
+                }
+                if sender.send(run_trial(trial)).is_err() {
+                    break;
+                }
+            });
         }
-        let attempt_time = std::time::Instant::now();
-        if let Some(_sln) = solver.try_solve(*w, *h, &bag) {
-            perfect_packs += 1;
-            perfect_pack_seconds += attempt_time.elapsed().as_secs_f64();
-            #[cfg(feature = "visualize")]
-            solver::visualize(&Answer { totems: _sln });  // To visually make sure the solutions are valid.
+        drop(sender);
+        for result in receiver.iter() {
+            if !on_result(result) {
+                break;
+            }
         }
+        // Trials in progress will fail to send their result, and stop.
+        drop(receiver);
+    });
+}
+
+// Times are the sum of the attempts' own times, so that they don't depend on the number of threads.
+fn print_progress(successes: u64, total_runs: u64, success_seconds: f64, total_seconds: f64) {
+    let seconds_per_run = total_seconds / (total_runs as f64);
+    let pack_ratio = (successes as f32) / (total_runs as f32);
+    let (lower_bound, upper_bound) = binomial_confidence_interval(successes, total_runs);
+    let secs_per_success = success_seconds / (successes as f64);
+    println!("{} / {} perfect packs (p={:.1}%   alpha=0.05 interval=[{:.1}%, {:.1}%]),   ~{:.2}s/it   ~{:.2}s/success its",
+             successes, total_runs, pack_ratio * 100f32, lower_bound * 100f64, upper_bound * 100f64,
+             seconds_per_run, secs_per_success);
+}
+
+fn bag_to_csv(bag: &[usize; 7]) -> String {
+    bag.iter().map(|n| n.to_string()).collect::<Vec<String>>().join(" ")
+}
+
+// Evaluates a single solver, and reports the results in the requested format.
+fn evaluate(solver: &ConfiguredSolver, spec: &str, config: &Config) {
+    let num_totems = 1 << config.level;
+    if config.output == OutputFormat::Text {
+        println!("Searching for perfect packs for level {}, with {} totems, using the {} solver. Need to pack {}x{} for a score of {}.",
+                 config.level + 1, num_totems, spec, config.width, config.height,
+                 score(num_totems, config.width, config.height));
+    } else if config.output == OutputFormat::Csv {
+        println!("trial,bag,success,timed_out,seconds,planned_strategy,proven_infeasible,proven_feasible");
+    }
+
+    let mut results = Vec::new();
+    let (mut successes, mut total_runs, mut success_seconds, mut total_seconds) = (0, 0, 0f64, 0f64);
+    let (mut proven_infeasible, mut misses, mut timeouts) = (0, 0, 0);
+    let mut last_time = Instant::now();
+    let run_trial = |trial| {
+        let bag = trial_bag(config, trial);
+        TrialResult { trial, bag: bag.0, attempt: attempt(solver, config, &bag, &format!("trial_{}", trial)) }
+    };
+    run_trials(config, run_trial, |result: TrialResult| {
         total_runs += 1;
-        if last_time.elapsed().as_secs_f64() > 0.5 {
-            let total_time = start_time.elapsed().as_secs_f64();
-            let pack_speed = (total_runs as f64) / total_time;
-            let pack_ratio = (perfect_packs as f32) / (total_runs as f32);
-            let (lower_bound, upper_bound) = binomial_confidence_interval(perfect_packs, total_runs);
-            let secs_per_success = perfect_pack_seconds / (perfect_packs as f64);
-            println!("{} / {} perfect packs (p={:.1}%   alpha=0.05 interval=[{:.1}%, {:.1}%]),   ~{:.2}s/it   ~{:.2}s/success its",
-                     perfect_packs, total_runs, pack_ratio * 100f32, lower_bound * 100f64, upper_bound * 100f64,
-                     1f64 / pack_speed, secs_per_success);
-            last_time = std::time::Instant::now();
+        total_seconds += result.attempt.seconds;
+        if result.attempt.success {
+            successes += 1;
+            success_seconds += result.attempt.seconds;
         }
+        proven_infeasible += result.attempt.proven_infeasible as u64;
+        misses += result.attempt.proven_feasible as u64;
+        timeouts += result.attempt.timed_out as u64;
+        match config.output {
+            OutputFormat::Text => {
+                if last_time.elapsed().as_secs_f64() > 0.5 {
+                    print_progress(successes, total_runs, success_seconds, total_seconds);
+                    last_time = Instant::now();
+                }
+            }
+            OutputFormat::Csv => {
+                let attempt = &result.attempt;
+                println!("{},{},{},{},{:.6},{},{},{}", result.trial, bag_to_csv(&result.bag), attempt.success,
+                         attempt.timed_out, attempt.seconds, attempt.planned_strategy, attempt.proven_infeasible,
+                         attempt.proven_feasible);
+            }
+            OutputFormat::Json => results.push(result),
+        }
+        true
+    });
+    let unproven = total_runs - successes - proven_infeasible - misses - timeouts;
+    if config.output == OutputFormat::Text && total_runs > 0 {
+        print_progress(successes, total_runs, success_seconds, total_seconds);
+        if config.certify {
+            println!("{} failures proven infeasible, {} solver misses, {} unproven, {} timed out.",
+                     proven_infeasible, misses, unproven, timeouts);
+        }
+    }
+
+    if config.output == OutputFormat::Json {
+        results.sort_by_key(|r| r.trial);
+        let (ci_lower, ci_upper) = binomial_confidence_interval(successes, total_runs);
+        let summary = Summary {
+            solver: spec.to_string(),
+            level: config.level + 1,
+            width: config.width,
+            height: config.height,
+            seed: config.seed,
+            trials: total_runs,
+            successes,
+            success_rate: successes as f64 / total_runs as f64,
+            ci_lower, ci_upper,
+            seconds_per_trial: total_seconds / total_runs as f64,
+            proven_infeasible: if config.certify { Some(proven_infeasible) } else { None },
+            misses: if config.certify { Some(misses) } else { None },
+            unproven: if config.certify { Some(unproven) } else { None },
+            timeouts: if config.certify { Some(timeouts) } else { None },
+        };
+        println!("{}", serde_json::to_string_pretty(&Report { summary, results }).unwrap());
     }
 }
 
-fn is_valid_level(level: String) -> Result<(), String> {
-    let level: usize = match level.parse() {
-        Ok(level) => level,
-        Err(_) => {
-            return Err(String::from("level must be a positive integer"));
-        }
+// Evaluates two solvers on the same instances, until the difference in success rate is
+// significant (or we run out of trials).
+// Note that checking for significance after each trial makes false positives more likely than
+// 'alpha', so it is best to keep it low.
+fn compare(solver_a: &ConfiguredSolver, spec_a: &str, solver_b: &ConfiguredSolver, spec_b: &str, config: &Config) {
+    let num_totems = 1 << config.level;
+    if config.output == OutputFormat::Text {
+        println!("Comparing A={} and B={} on level {}, with {} totems, packing in {}x{}.",
+                 spec_a, spec_b, config.level + 1, num_totems, config.width, config.height);
+    } else if config.output == OutputFormat::Csv {
+        println!("trial,bag,a_success,a_timed_out,a_seconds,a_planned_strategy,b_success,b_timed_out,b_seconds,b_planned_strategy");
+    }
+
+    let mut summary = PairedSummary {
+        solver_a: spec_a.to_string(),
+        solver_b: spec_b.to_string(),
+        level: config.level + 1,
+        width: config.width,
+        height: config.height,
+        seed: config.seed,
+        p_value: 1f64,
+        ..Default::default()
+    };
+    let mut results = Vec::new();
+    let mut last_time = Instant::now();
+    let run_trial = |trial: u64| {
+        let bag = trial_bag(config, trial);
+        // Alternate which solver goes first, in case running first has an impact (e.g. on caches).
+        let (a, b) = if trial % 2 == 0 {
+            let a = attempt(solver_a, config, &bag, &format!("trial_{}_a", trial));
+            (a, attempt(solver_b, config, &bag, &format!("trial_{}_b", trial)))
+        } else {
+            let b = attempt(solver_b, config, &bag, &format!("trial_{}_b", trial));
+            (attempt(solver_a, config, &bag, &format!("trial_{}_a", trial)), b)
+        };
+        PairedResult { trial, bag: bag.0, a, b }
     };
-    if level < 1 || level > 10 {
-        Err(String::from("level must be between 1 and 10, inclusively"))
-    } else {
-        Ok(())
+    run_trials(config, run_trial, |result: PairedResult| {
+        summary.add(&result);
+        match config.output {
+            OutputFormat::Text => {
+                if last_time.elapsed().as_secs_f64() > 0.5 {
+                    summary.print();
+                    last_time = Instant::now();
+                }
+            }
+            OutputFormat::Csv => {
+                let (a, b) = (&result.a, &result.b);
+                println!("{},{},{},{},{:.6},{},{},{},{:.6},{}", result.trial, bag_to_csv(&result.bag),
+                         a.success, a.timed_out, a.seconds, a.planned_strategy, b.success, b.timed_out, b.seconds,
+                         b.planned_strategy);
+            }
+            OutputFormat::Json => results.push(result),
+        }
+        summary.significant = summary.trials >= MIN_AB_TRIALS && summary.p_value < config.alpha;
+        !summary.significant
+    });
+
+    match config.output {
+        OutputFormat::Text => {
+            summary.print();
+            if summary.significant {
+                let better = if summary.a_wins > summary.b_wins { "A" } else { "B" };
+                println!("{} is significantly better (p={:.4} < {}).", better, summary.p_value, config.alpha);
+            } else {
+                println!("No significant difference (p={:.4}).", summary.p_value);
+            }
+        }
+        OutputFormat::Csv => {}
+        OutputFormat::Json => {
+            results.sort_by_key(|r| r.trial);
+            println!("{}", serde_json::to_string_pretty(&Report { summary, results }).unwrap());
+        }
     }
 }
 
+fn is_valid_timeout(timeout: String) -> Result<(), String> {
+    match timeout.parse::<f64>() {
+        Ok(timeout) if timeout > 0f64 => Ok(()),
+        _ => Err(String::from("timeout must be a positive number of seconds")),
+    }
+}
+
+fn load_solver(spec: &str, config: &Config) -> ConfiguredSolver {
+    // When running instances in parallel, the hybrid solver should not spawn its own threads.
+    let solver = ConfiguredSolver::from_spec(spec, /*multithreading=*/config.threads == 1).unwrap_or_else(|e| {
+        eprintln!("Invalid solver '{}': {}", spec, e);
+        std::process::exit(1);
+    });
+    let num_totems = 1 << config.level;
+    if solver.name() == "dlx" && config.width * config.height != num_totems * 4 {
+        eprintln!("The dlx solver only works for perfect packings ({}x{} can't fit exactly {} totems).",
+                  config.width, config.height, num_totems);
+        std::process::exit(1);
+    }
+    solver
+}
 
 fn main() {
+    logging::init(tracing::Level::WARN);
     let matches = App::new("Coveo 2022 Inscription Evaluation")
                           .arg(Arg::with_name("level")
                                .value_name("LEVEL")
@@ -115,12 +480,146 @@ fn main() {
                                       (since they can't perfectly pack a rectangle).\nBy default odd 'T' shapes are \
                                       generated, like in the original challenge.")
                                 .takes_value(false))
+                          .arg(Arg::with_name("shape_weights")
+                               .value_name("WEIGHTS")
+                               .long("shape-weights")
+                               .help("Relative weights of the shapes in generated instances, e.g. 'T=2,O=0.5' \
+                                      (shapes not listed have a weight of 1). Uniform by default, like the challenge.")
+                               .validator(is_valid_shape_weights))
+                          .arg(Arg::with_name("bag")
+                               .value_name("BAG")
+                               .long("bag")
+                               .help("Packs this bag on every trial instead of generating instances, given by the \
+                                      letters of its shapes (e.g. 'IJJLOSTZ') or counts (e.g. 'I=2,T=6'). It must \
+                                      have as many totems as the level.")
+                               .validator(is_valid_bag)
+                               .conflicts_with_all(&["only_even_t_shapes", "shape_weights"]))
+                          .arg(Arg::with_name("planted")
+                               .long("planted")
+                               .help("Generates planted instances instead, by randomly tiling the dimensions to pack \
+                                      in, so that a perfect packing is known to exist. Only for perfect packings.")
+                               .takes_value(false)
+                               .conflicts_with_all(&["only_even_t_shapes", "shape_weights", "bag"]))
+                          .arg(Arg::with_name("certify")
+                               .long("certify")
+                               .help("Checks failed attempts for a proof that the bag can't be packed in the \
+                                      dimensions, or for a packing, to report solver misses separately from \
+                                      infeasible and unproven bags. Slow on big levels, where the search rarely \
+                                      concludes.")
+                               .takes_value(false))
+                          .arg(Arg::with_name("solver")
+                               .value_name("SOLVER")
+                               .long("solver")
+                               .help("Solver to evaluate, optionally with options (see 'solver_config.rs'), \
+                                      e.g. 'greedy' or 'rect:free_space=skyline,rolls=10'")
+                               .default_value("hybrid"))
+                          .arg(Arg::with_name("compare")
+                               .value_name("SOLVER")
+                               .long("compare")
+                               .help("Second solver to compare against '--solver' (A/B mode) on the same instances"))
+                          .arg(Arg::with_name("alpha")
+                               .value_name("ALPHA")
+                               .long("alpha")
+                               .help("Significance level at which an A/B comparison stops")
+                               .validator(is_valid_alpha)
+                               .default_value("0.01"))
+                          .arg(Arg::with_name("trials")
+                               .value_name("TRIALS")
+                               .long("trials")
+                               .help("Number of instances to evaluate. Runs forever if not set (text output only), \
+                                     or until significant for A/B comparisons.")
+                               .validator(is_positive_integer)
+                               .required_ifs(&[("output", "json"), ("output", "csv")]))
+                          .arg(Arg::with_name("seed")
+                               .value_name("SEED")
+                               .long("seed")
+                               .help("Seed used to generate the instances. Random if not set. Note that the \
+                                      solvers themselves are not seeded.")
+                               .validator(is_valid_seed))
+                          .arg(Arg::with_name("dims")
+                               .value_name("WIDTHxHEIGHT")
+                               .long("dims")
+                               .help("Dimensions to pack in. Defaults to the best optimal dimensions of the level.")
+                               .validator(is_valid_dims))
+                          .arg(Arg::with_name("threads")
+                               .value_name("THREADS")
+                               .long("threads")
+                               .help("Number of instances to evaluate in parallel. When more than 1, the hybrid \
+                                      solver does not use multithreading itself.")
+                               .validator(is_positive_integer)
+                               .default_value("1"))
+                          .arg(Arg::with_name("timeout")
+                               .value_name("SECONDS")
+                               .long("timeout")
+                               .help("Solutions that take longer than this are counted as failures.")
+                               .validator(is_valid_timeout))
+                          .arg(Arg::with_name("output")
+                               .value_name("FORMAT")
+                               .long("output")
+                               .help("Output format")
+                               .possible_values(&["text", "json", "csv"])
+                               .default_value("text"))
+                          .arg(Arg::with_name("render_dir")
+                               .value_name("DIR")
+                               .long("render-dir")
+                               .help("Renders each solution found to an SVG file in this directory (trial_N.svg, or \
+                                      trial_N_a.svg and trial_N_b.svg when comparing)."))
+                          .arg(Arg::with_name("trace_dir")
+                               .value_name("DIR")
+                               .long("trace-dir")
+                               .help("Records the search of each attempt to a JSON trace in this directory, named \
+                                      like with --render-dir. Solvers run single threaded and slower when tracing."))
                           .get_matches();
     let level = matches.value_of("level").unwrap();
     let level: usize = level.parse().unwrap();
     let level = level - 1;  // Logic assumes that levels are 0-indexed.
-    let only_even_t_shapes: bool = matches.is_present("only_even_t_shapes");
+    let (width, height) = match matches.value_of("dims") {
+        Some(dims) => parse_dims(dims).unwrap(),
+        None => *OptimalDimensions::new().level_dims(level).first().unwrap(),
+    };
+    let config = Config {
+        level, width, height,
+        trials: matches.value_of("trials").map(|trials| trials.parse().unwrap()),
+        seed: matches.value_of("seed").map_or_else(|| rand::thread_rng().gen(), |seed| seed.parse().unwrap()),
+        threads: matches.value_of("threads").unwrap().parse().unwrap(),
+        timeout: matches.value_of("timeout").map(|timeout| timeout.parse().unwrap()),
+        alpha: matches.value_of("alpha").unwrap().parse().unwrap(),
+        output: match matches.value_of("output").unwrap() {
+            "json" => OutputFormat::Json,
+            "csv" => OutputFormat::Csv,
+            _ => OutputFormat::Text,
+        },
+        generator: InstanceGenerator::with_options(
+            ShapeDistribution::from_spec(matches.value_of("shape_weights").unwrap_or("")).unwrap(),
+            /*allow_odd_t_shapes=*/!matches.is_present("only_even_t_shapes")),
+        bag: matches.value_of("bag").map(|bag| instances::parse_bag(bag).unwrap()),
+        planted: matches.is_present("planted"),
+        certify: matches.is_present("certify"),
+        render_dir: matches.value_of("render_dir").map(PathBuf::from),
+        trace_dir: matches.value_of("trace_dir").map(PathBuf::from),
+    };
+    if let Some(bag) = &config.bag {
+        if bag.total() != 1 << level {
+            eprintln!("The bag has {} totems, but level {} has {}.", bag.total(), level + 1, 1 << level);
+            std::process::exit(1);
+        }
+    }
+    if config.planted && config.width * config.height != 4 << level {
+        eprintln!("Planted instances are perfect packings, but {}x{} can't fit exactly {} totems.",
+                  config.width, config.height, 1 << level);
+        std::process::exit(1);
+    }
+    for dir in config.render_dir.iter().chain(&config.trace_dir) {
+        std::fs::create_dir_all(dir).expect("Could not create the output directory");
+    }
 
-    let solver = SelectedSolver::with_options(/*multithreading=*/true, /*verbose=*/false);
-    debug_packing_probability(level, &solver, /*allow_odd_t_shapes=*/!only_even_t_shapes);
-}
\ No newline at end of file
+    let spec_a = matches.value_of("solver").unwrap();
+    let solver_a = load_solver(spec_a, &config);
+    match matches.value_of("compare") {
+        Some(spec_b) => {
+            let solver_b = load_solver(spec_b, &config);
+            compare(&solver_a, spec_a, &solver_b, spec_b, &config);
+        }
+        None => evaluate(&solver_a, spec_a, &config),
+    }
+}
@@ -0,0 +1,101 @@
+// Generates a question, as a game message JSON like the server sends (on a single line).
+// Used by the local server ('server.sh'), and handy to make instances to give to 'solve'.
+// With '--planted', the question is a planted instance (see 'planted'), and its known perfect
+// packing is written to stderr as an answer JSON.
+
+extern crate application;
+
+use application::{
+    cli::{is_positive_integer, is_valid_bag, is_valid_level, is_valid_seed, is_valid_shape_weights,
+          parse_dims},
+    game_interface::GameMessage,
+    game_interface::Answer,
+    instances::{self, InstanceGenerator, ShapeDistribution},
+    planted,
+};
+use clap::{Arg, App};
+use rand::Rng;
+
+fn is_valid_planted_dims(dims: String) -> Result<(), String> {
+    match parse_dims(&dims) {
+        Some((w, h)) if (w * h) % 4 == 0 => Ok(()),
+        _ => Err(String::from("dims must be of the form WIDTHxHEIGHT, with an area multiple of 4, e.g. 16x16")),
+    }
+}
+
+fn is_valid_tick(tick: String) -> Result<(), String> {
+    tick.parse::<i32>().map(|_| ()).map_err(|_| String::from("tick must be an integer"))
+}
+
+fn main() {
+    let matches = App::new("Coveo 2022 Inscription Question Generator")
+                          .arg(Arg::with_name("totems")
+                               .value_name("TOTEMS")
+                               .long("totems")
+                               .help("Number of totems to generate")
+                               .validator(is_positive_integer)
+                               .required_unless_one(&["level", "bag", "planted"]))
+                          .arg(Arg::with_name("level")
+                               .value_name("LEVEL")
+                               .long("level")
+                               .help("Level to generate, with the challenge's rules (level 1 is always an 'I')")
+                               .validator(is_valid_level)
+                               .conflicts_with("totems"))
+                          .arg(Arg::with_name("bag")
+                               .value_name("BAG")
+                               .long("bag")
+                               .help("Explicit bag, given by the letters of its shapes (e.g. 'IJJLOSTZ') or counts \
+                                      (e.g. 'I=2,T=6')")
+                               .validator(is_valid_bag)
+                               .conflicts_with_all(&["totems", "level", "shape_weights", "only_even_t_shapes"]))
+                          .arg(Arg::with_name("planted")
+                               .value_name("WIDTHxHEIGHT")
+                               .long("planted")
+                               .help("Planted instance, a random tiling of these dimensions")
+                               .validator(is_valid_planted_dims)
+                               .conflicts_with_all(&["totems", "level", "bag", "shape_weights", "only_even_t_shapes"]))
+                          .arg(Arg::with_name("shape_weights")
+                               .value_name("WEIGHTS")
+                               .long("shape-weights")
+                               .help("Relative weights of the shapes, e.g. 'T=2,O=0.5' (shapes not listed have a \
+                                      weight of 1). Uniform by default, like the challenge.")
+                               .validator(is_valid_shape_weights))
+                          .arg(Arg::with_name("only_even_t_shapes")
+                               .long("only-even-t-shapes")
+                               .help("If questions with an odd number of 'T' shapes should not be generated")
+                               .takes_value(false))
+                          .arg(Arg::with_name("seed")
+                               .value_name("SEED")
+                               .long("seed")
+                               .help("Seed used to generate the question. Random if not set.")
+                               .validator(is_valid_seed))
+                          .arg(Arg::with_name("tick")
+                               .value_name("TICK")
+                               .long("tick")
+                               .help("Tick of the game message")
+                               .validator(is_valid_tick)
+                               .default_value("0"))
+                          .get_matches();
+    let generator = InstanceGenerator::with_options(
+        ShapeDistribution::from_spec(matches.value_of("shape_weights").unwrap_or("")).unwrap(),
+        /*allow_odd_t_shapes=*/!matches.is_present("only_even_t_shapes"));
+    let seed: u64 = matches.value_of("seed").map_or_else(|| rand::thread_rng().gen(), |seed| seed.parse().unwrap());
+    let mut rng = instances::seeded_rng(seed, 0);
+    let question = if let Some(dims) = matches.value_of("planted") {
+        let (width, height) = parse_dims(dims).unwrap();
+        let instance = planted::generate(width, height, &mut rng).unwrap_or_else(|| {
+            eprintln!("Could not tile {}x{}.", width, height);
+            std::process::exit(1);
+        });
+        eprintln!("{}", serde_json::to_string(&Answer::new(instance.solution)).unwrap());
+        instances::question_from_bag(&instance.bag)
+    } else if let Some(bag) = matches.value_of("bag") {
+        instances::question_from_bag(&instances::parse_bag(bag).unwrap())
+    } else if let Some(level) = matches.value_of("level") {
+        generator.level(level.parse::<usize>().unwrap() - 1, &mut rng)
+    } else {
+        generator.question(matches.value_of("totems").unwrap().parse().unwrap(), &mut rng)
+    };
+    let tick = matches.value_of("tick").unwrap().parse().unwrap();
+    println!("{}", serde_json::to_string(&GameMessage { tick, payload: question }).unwrap());
+}
@@ -3,95 +3,40 @@
 extern crate application;
 
 use application::{
-    game_interface::{Answer, Question, Totem, TotemBag, TotemQuestion, TOTEMS},
+    game_interface::{Answer, Question},
     hybrid_solver,
+    instances::InstanceGenerator,
+    logging,
     solver::Solver,
-    scoring::{score, OptimalDimensions},
-};
-use rand::seq::SliceRandom;
-use std::{
-    collections::HashSet,
-    time::Instant,
+    scoring::{answer_score, OptimalDimensions},
 };
+use std::time::Instant;
+use tracing::warn;
 
 type SelectedSolver = hybrid_solver::HybridSolver;
 
-fn level_optimal_score(level: usize, optimal_dims: &OptimalDimensions) -> f32 {
-    if level == 0 {
-        1.5  // Best that can be done for "I" (always) is 1x4.
-    } else {
-        let num_totems = 1 << level;
-        let (w, h) = optimal_dims.level_dims(level).first().unwrap();
-        score(num_totems, *w, *h)
-    }
-}
-
-fn is_valid_solution(question: &Question, answer: &Answer, start_time: &Instant) -> bool {
+fn solution_score(question: &Question, answer: &Answer, start_time: &Instant) -> Option<f32> {
     let seconds = start_time.elapsed().as_secs_f64();
     if seconds >= 1f64 {
-        println!("[!] {{Took more than 1s}}  Took {:?} to solve.", seconds);
-        return false;
+        warn!(seconds, "Took more than 1s to solve.");
+        return None;
     }
-    let mut totems = TotemBag::new();
-    let mut coords = HashSet::with_capacity(question.totems.len() * 4);
-    for totem in answer.totems.iter() {
-        for coord in totem.coordinates {
-            if !coords.insert(coord) {
-                println!("[!] {{Dupe coords}}  {:?} appears more than once.", coord);
-                return false;
-            }
+    match answer_score(question, answer) {
+        Ok(score) => Some(score),
+        Err(e) => {
+            warn!(error = %e, "Invalid answer.");
+            None
         }
-        totems[totem.shape] += 1;
-    }
-    if !coords.contains(&(0, 0)) {
-        println!("[!] {{Missing (0,0)}}  (0,0) missing from answer.");
-        return false;
-    }
-    if totems.0 != question.get_totem_bag().0 {
-        println!("[!] {{Incorrect shapes}}  Shapes mismatch. Got {:?}, expected {:?}.",
-                 totems, question.get_totem_bag());
-        return false;
-    }
-    true
-}
-
-fn generate_level(level: usize) -> Question {
-    if level == 0 {  // Level 1 is always "I".
-        return Question { totems: vec![TotemQuestion { shape: Totem::I }] };
-    }
-    let mut rng = rand::thread_rng();
-    let num_totems = 1 << level;
-    Question {
-        totems: (0..num_totems).map(
-            |_| TotemQuestion { shape: *TOTEMS.choose(&mut rng).unwrap() }).collect()
     }
 }
 
 // Generates and solves a given level (0-indexed).
-// Returns our score if our solution was valid _and_ optimal.
+// Returns our score if our solution was valid.
 fn run_level(solver: &SelectedSolver, level: usize) -> Option<f32> {
-    let question = generate_level(level);
+    let question = InstanceGenerator::new().level(level, &mut rand::thread_rng());
     let start_time = Instant::now();
     let answer = solver.solve(&question);
-    if !is_valid_solution(&question, &answer, &start_time) {
-        None
-    } else {
-        let max_x = answer
-            .totems
-            .iter()
-            .flat_map(|t| t.coordinates.iter().map(|p| p.0))
-            .max()
-            .unwrap();
-        let max_y = answer
-            .totems
-            .iter()
-            .flat_map(|t| t.coordinates.iter().map(|p| p.1))
-            .max()
-            .unwrap();
-        let w = max_x + 1;
-        let h = max_y + 1;
-        Some(score(question.totems.len(), w, h))
-    }
+    solution_score(&question, &answer, &start_time)
 }
 
 // Runs up to a full round of 10 levels (early exits if an invalid or suboptimal
@@ -101,7 +46,7 @@ fn run_round(solver: &SelectedSolver, optimal_dims: &OptimalDimensions) -> Optio
     let mut total_score = 0f32;
     for level in 0..10 {
         let score = run_level(solver, level)?;
-        let optimal_score = level_optimal_score(level, optimal_dims);
+        let optimal_score = optimal_dims.level_optimal_score(level);
         if (optimal_score - score).abs() > 1.0e-5 {
             println!("Suboptimal level {}  ({} < {}), abort round.", level+1, score, optimal_score);
             return None;
@@ -113,6 +58,7 @@ fn run_round(solver: &SelectedSolver, optimal_dims: &OptimalDimensions) -> Optio
 
 
 fn main() {
+    logging::init(tracing::Level::INFO);
     let optimal_dims = OptimalDimensions::new();
     let solver = SelectedSolver::new();
     let mut round = 0;
@@ -0,0 +1,64 @@
+// Precompute optimal answers for every possible bag of the small levels, then store to disk.
+// Used by the hybrid solver to answer these levels by lookup (see 'answer_table').
+// Answers that could not be proven optimal within the node budget are still stored (with the best
+// packing found), and reported.
+
+extern crate application;
+
+use application::{
+    answer_table::{all_bags, AnswerTable, MAX_TABLE_LEVEL},
+    block_solver::BlockSolver,
+    certificates::{optimal_answer, DEFAULT_MAX_NODES},
+    cli::{is_positive_integer, is_valid_level},
+    hybrid_solver::ANSWER_TABLE_PATH,
+    logging,
+    solver::Solver,
+};
+use clap::{Arg, App};
+use std::{path::Path, time::Instant};
+
+fn main() {
+    logging::init(tracing::Level::INFO);
+    let default_max_level = (MAX_TABLE_LEVEL + 1).to_string();
+    let default_max_nodes = DEFAULT_MAX_NODES.to_string();
+    let matches = App::new("Coveo 2022 Inscription Answer Precomputation")
+                          .arg(Arg::with_name("max_level")
+                               .value_name("LEVEL")
+                               .long("max-level")
+                               .help("Precompute answers for every bag of levels 1 up to this one")
+                               .validator(is_valid_level)
+                               .default_value(&default_max_level))
+                          .arg(Arg::with_name("max_nodes")
+                               .value_name("NODES")
+                               .long("max-nodes")
+                               .help("Search nodes spent on each dimensions before giving up on proving them")
+                               .validator(is_positive_integer)
+                               .default_value(&default_max_nodes))
+                          .arg(Arg::with_name("output")
+                               .value_name("FILE")
+                               .long("output")
+                               .help("Where to save the answers")
+                               .default_value(ANSWER_TABLE_PATH))
+                          .get_matches();
+    let max_level: usize = matches.value_of("max_level").unwrap().parse().unwrap();
+    let max_nodes: usize = matches.value_of("max_nodes").unwrap().parse().unwrap();
+    let output = matches.value_of("output").unwrap();
+
+    let blocks = BlockSolver::new();
+    let mut table = AnswerTable::new();
+    for level in 0..max_level {
+        let start_time = Instant::now();
+        let bags = all_bags(1 << level);
+        let mut unproven = 0;
+        for bag in &bags {
+            let (answer, proven) = optimal_answer(bag, max_nodes).unwrap_or_else(|| (blocks.pack(bag).0, false));
+            if !proven {
+                unproven += 1;
+            }
+            table.insert(bag, answer, proven);
+        }
+        println!("Level {}: {} bags, {} not proven optimal ({:.1}s).",
+                 level + 1, bags.len(), unproven, start_time.elapsed().as_secs_f64());
+    }
+    table.save(Path::new(output)).expect("Failed to save precomputed answers.");
+}
@@ -4,6 +4,7 @@
 extern crate application;
 
 use application::{
+    logging,
     rect_inventory::RectangleInventory,
 };
 use clap::{Arg, App};
@@ -19,8 +20,16 @@ fn is_valid_area(area: String) -> Result<(), String> {
     Ok(())
 }
 
+fn is_valid_tilings(tilings: String) -> Result<(), String> {
+    match tilings.parse::<usize>() {
+        Ok(tilings) if tilings >= 1 => Ok(()),
+        _ => Err(String::from("tilings must be a strictly positive integer")),
+    }
+}
+
 
 fn main() {
+    logging::init(tracing::Level::INFO);
     let matches = App::new("Coveo 2022 Inscription Rectangle Precomputation")
                           .arg(Arg::with_name("max_area")
                                .value_name("MAX_AREA")
@@ -28,11 +37,18 @@ fn main() {
                                .help("Precompute rectangles up to this area")
                                .required(true)
                                .validator(is_valid_area))
+                          .arg(Arg::with_name("max_tilings")
+                               .value_name("MAX_TILINGS")
+                               .long("tilings")
+                               .help("Maximum number of distinct tilings to keep per rectangle")
+                               .default_value("4")
+                               .validator(is_valid_tilings))
                           .get_matches();
     let area = matches.value_of("max_area").unwrap();
     let area: usize = area.parse().unwrap();
+    let max_tilings: usize = matches.value_of("max_tilings").unwrap().parse().unwrap();
 
-    let inventory = RectangleInventory::from_scratch(/*max_area=*/area);
+    let inventory = RectangleInventory::from_scratch(/*max_area=*/area, max_tilings);
     let filename = format!("src/precomputed_area_{}.rects", area);
     inventory.save(Path::new(&filename)).expect("Failed to save precomputed rectangles.");
 }
\ No newline at end of file
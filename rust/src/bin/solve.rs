@@ -0,0 +1,142 @@
+// Solves one given instance, e.g. to reproduce a failure from the server or a trace.
+// The question is read from a file (or stdin), either as a 'Question' or as a full 'GameMessage'
+// like the server sends. It can also be given with '--totems', from the letters of its shapes
+// (e.g. "IJJLOSTZ") or counts per shape (e.g. "I=2,T=6").
+//
+// Writes the answer JSON to stdout, along with its score and whether it's valid. The output is
+// still an 'Answer' (extra fields are ignored), so it can be given to the viewer as is.
+// Exits with an error code if no valid answer was found.
+// With '--certify', also writes whether the answer is proven optimal, with a proof of infeasibility
+// for each dimensions that would score higher (see 'certificates').
+
+extern crate application;
+
+use application::{
+    certificates::{self, Certificate, DEFAULT_MAX_NODES},
+    cli::{is_valid_bag, is_valid_dims, parse_dims},
+    game_interface::{Answer, GameMessage, Question},
+    instances,
+    logging,
+    scoring::answer_score,
+    solver::Solver,
+    solver_config::{ConfiguredSolver, SOLVER_NAMES},
+    transform::answer_dims,
+};
+use clap::{Arg, App};
+use serde::Serialize;
+use std::{
+    fs,
+    io::{self, Read},
+    time::Instant,
+};
+
+#[derive(Serialize)]
+struct Output {
+    #[serde(flatten)]
+    answer: Answer,
+    solver: String,
+    // Dimensions of the answer (its bounding box).
+    width: usize,
+    height: usize,
+    valid: bool,
+    score: Option<f32>,
+    // Why the answer is invalid, if so.
+    error: Option<String>,
+    seconds: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    certificate: Option<Certificate>,
+}
+
+// Reads a 'Question' or a 'GameMessage' from a file, or from stdin if 'path' is "-".
+fn read_question(path: &str) -> Result<Question, String> {
+    let text = if path == "-" {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text).map_err(|e| format!("Could not read stdin: {}", e))?;
+        text
+    } else {
+        fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?
+    };
+    match serde_json::from_str::<GameMessage>(&text) {
+        Ok(message) => Ok(message.payload),
+        Err(_) => serde_json::from_str::<Question>(&text)
+            .map_err(|e| format!("{} is neither a question nor a game message: {}", path, e)),
+    }
+}
+
+fn main() {
+    logging::init(tracing::Level::WARN);
+    let matches = App::new("Coveo 2022 Inscription Solver")
+                          .arg(Arg::with_name("input")
+                               .value_name("FILE")
+                               .help("Question or game message JSON file to solve. Reads stdin if '-' or not set.")
+                               .conflicts_with("totems"))
+                          .arg(Arg::with_name("totems")
+                               .value_name("SHAPES")
+                               .long("totems")
+                               .help("Question given by the letters of its shapes instead (e.g. 'IJJLOSTZ'), or by counts \
+                                      (e.g. 'I=2,T=6')")
+                               .validator(is_valid_bag))
+                          .arg(Arg::with_name("solver")
+                               .value_name("SOLVER")
+                               .long("solver")
+                               .help(&format!("Solver to use, optionally with options (e.g. 'rect:rolls=10'). \
+                                               One of {:?}.", SOLVER_NAMES))
+                               .default_value("hybrid"))
+                          .arg(Arg::with_name("dims")
+                               .value_name("WIDTHxHEIGHT")
+                               .long("dims")
+                               .help("Dimensions to pack in. By default, the solver picks them.")
+                               .validator(is_valid_dims))
+                          .arg(Arg::with_name("certify")
+                               .long("certify")
+                               .help("Checks whether the answer is proven optimal. Slow on big levels.")
+                               .takes_value(false))
+                          .get_matches();
+    let question = match matches.value_of("totems") {
+        Some(totems) => instances::parse_bag(totems).map(|bag| instances::question_from_bag(&bag)),
+        None => read_question(matches.value_of("input").unwrap_or("-")),
+    }.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let spec = matches.value_of("solver").unwrap();
+    let solver = ConfiguredSolver::from_spec(spec, /*multithreading=*/true).unwrap_or_else(|e| {
+        eprintln!("Invalid solver '{}': {}", spec, e);
+        std::process::exit(1);
+    });
+
+    let start_time = Instant::now();
+    let answer = match matches.value_of("dims") {
+        Some(dims) => {
+            let (width, height) = parse_dims(dims).unwrap();
+            let sln = solver.try_solve(width, height, &question.get_totem_bag());
+            Answer::new(sln.unwrap_or_default())
+        }
+        None => solver.solve(&question),
+    };
+    let seconds = start_time.elapsed().as_secs_f64();
+
+    let (width, height) = answer_dims(&answer.totems);
+    let (score, error) = match answer_score(&question, &answer) {
+        Ok(score) => (Some(score), None),
+        Err(_) if answer.totems.is_empty() => (None, Some(String::from("No fit found."))),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    let certificate = if matches.is_present("certify") {
+        Some(certificates::certify(&question.get_totem_bag(), &answer.totems, DEFAULT_MAX_NODES))
+    } else {
+        None
+    };
+    let output = Output {
+        answer, solver: spec.to_string(), width, height,
+        valid: score.is_some(), score, error, seconds,
+        status: certificate.as_ref().map(Certificate::status),
+        certificate,
+    };
+    println!("{}", serde_json::to_string_pretty(&output).unwrap());
+    if !output.valid {
+        std::process::exit(1);
+    }
+}
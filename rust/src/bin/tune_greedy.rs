@@ -0,0 +1,285 @@
+// Tool that tunes the weights of the greedy solver for a level, then stores them to disk (see
+// 'GreedyParams'). The fitness of some weights is how often the greedy solver packs the same
+// sampled bags in the level's dimensions, like 'evaluate' measures it.
+//
+// The search is a simplified CMA-ES, with a diagonal covariance: each generation samples weights
+// around a mean, then moves the mean and the step sizes towards the best half of the samples. The
+// best weights so far compete with the samples, so that a generation of unlucky samples doesn't
+// move away from them.
+// The touchpoints weight stays at 1 (scaling all weights doesn't change the placements picked) and
+// the (0, 0) weight isn't tuned, since the hybrid solver rotates fits that miss it anyway.
+// The weights found are only saved if they pack significantly more bags than the starting ones
+// (McNemar test, see 'stats') on held-out bags, drawn from another seed stream than the bags of the
+// search: the best rate of the search is an optimistic estimate, since it's the max of noisy
+// samples on the same bags.
+// Parameters tuned for other levels are kept in the output file.
+
+extern crate application;
+
+use application::{
+    cli::{is_positive_integer, is_valid_alpha, is_valid_dims, is_valid_level, is_valid_seed, parse_dims},
+    game_interface::{Totem, TotemBag},
+    greedy_solver::{self, GreedyParams, GreedySolver, TunedGreedyParams},
+    hybrid_solver::GREEDY_PARAMS_PATH,
+    instances::{self, InstanceGenerator, ShapeDistribution},
+    logging,
+    scoring::OptimalDimensions,
+    solver::Solver,
+    stats::mcnemar_p_value,
+};
+use clap::{Arg, App};
+use rand::{rngs::SmallRng, Rng};
+use std::{path::Path, thread, time::Instant};
+
+const NUM_WEIGHTS: usize = 5;
+const WEIGHT_NAMES: [&str; NUM_WEIGHTS] = ["border", "holes", "height_increase", "column_parity", "ties"];
+const INITIAL_STEP: f32 = 0.25;
+const MIN_STEP: f32 = 0.05;
+// The greedy solver relies on ties between placements to vary its attempts, which non-integer
+// weights break. Tuning starts with at least this 'ties' (equivalent to exact ties with the default
+// weights, since scores are then integers).
+const MIN_INITIAL_TIES: f32 = 0.5;
+// How much of the step sizes is updated from each generation.
+const STEP_LEARNING_RATE: f32 = 0.3;
+// Offset of the seed stream of the held-out bags, away from the streams of the search (one per level).
+const HELD_OUT_STREAM: u64 = 1 << 32;
+
+fn to_weights(params: &GreedyParams) -> [f32; NUM_WEIGHTS] {
+    [params.border, params.holes, params.height_increase, params.column_parity, params.ties]
+}
+
+fn from_weights(weights: &[f32; NUM_WEIGHTS]) -> GreedyParams {
+    GreedyParams {
+        touchpoints: 1.0,
+        border: weights[0],
+        holes: weights[1],
+        height_increase: weights[2],
+        column_parity: weights[3],
+        ties: weights[4].max(0.0),
+        ..GreedyParams::default()
+    }
+}
+
+fn describe(params: &GreedyParams) -> String {
+    let weights = to_weights(params);
+    WEIGHT_NAMES.iter().zip(weights.iter()).map(|(name, weight)| format!("{}={:.3}", name, weight))
+        .collect::<Vec<_>>().join(",")
+}
+
+// Whether the greedy solver packs each of 'bags' in width x height with 'params', within
+// 'max_attempts' attempts (the solver's default if not set).
+fn pack_results(params: &GreedyParams, bags: &[TotemBag], width: usize, height: usize, max_attempts: Option<usize>,
+                threads: usize) -> Vec<bool> {
+    if bags.is_empty() {
+        return Vec::new();
+    }
+    let mut solver = GreedySolver::with_params(*params);
+    if let Some(max_attempts) = max_attempts {
+        solver.set_max_attempts(max_attempts);
+    }
+    let chunk_size = bags.len().div_ceil(threads.max(1));
+    thread::scope(|scope| {
+        let handles: Vec<_> = bags.chunks(chunk_size).map(|chunk| {
+            let solver = solver.clone();
+            scope.spawn(move || {
+                chunk.iter().map(|bag| solver.try_solve(width, height, bag).is_some()).collect::<Vec<_>>()
+            })
+        }).collect();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+// Fraction of 'results' that are packed, 0 if there are none.
+fn rate(results: &[bool]) -> f64 {
+    if results.is_empty() {
+        return 0.0;
+    }
+    results.iter().filter(|&&packed| packed).count() as f64 / results.len() as f64
+}
+
+// Fraction of 'bags' that the greedy solver packs in width x height with 'params'.
+fn pack_rate(params: &GreedyParams, bags: &[TotemBag], width: usize, height: usize, max_attempts: Option<usize>,
+             threads: usize) -> f64 {
+    rate(&pack_results(params, bags, width, height, max_attempts, threads))
+}
+
+// Standard normal sample (Box-Muller).
+fn gaussian(rng: &mut SmallRng) -> f32 {
+    let (u, v): (f32, f32) = (rng.gen_range(f32::EPSILON..1.0), rng.gen());
+    (-2.0 * u.ln()).sqrt() * (2.0 * std::f32::consts::PI * v).cos()
+}
+
+fn main() {
+    logging::init(tracing::Level::WARN);
+    let matches = App::new("Coveo 2022 Inscription Greedy Tuning")
+                          .arg(Arg::with_name("level")
+                               .value_name("LEVEL")
+                               .long("level")
+                               .help("Level to tune the greedy solver for")
+                               .required(true)
+                               .validator(is_valid_level))
+                          .arg(Arg::with_name("dims")
+                               .value_name("WIDTHxHEIGHT")
+                               .long("dims")
+                               .help("Dimensions to pack in. Defaults to the best optimal dimensions of the level.")
+                               .validator(is_valid_dims))
+                          .arg(Arg::with_name("trials")
+                               .value_name("TRIALS")
+                               .long("trials")
+                               .help("Number of bags that each candidate weights are evaluated on")
+                               .validator(is_positive_integer)
+                               .default_value("50"))
+                          .arg(Arg::with_name("held_out_trials")
+                               .value_name("TRIALS")
+                               .long("held-out-trials")
+                               .help("Number of held-out bags to compare the best weights with the starting ones on")
+                               .validator(is_positive_integer)
+                               .default_value("200"))
+                          .arg(Arg::with_name("alpha")
+                               .value_name("ALPHA")
+                               .long("alpha")
+                               .help("Significance level of the improvement needed to save the best weights")
+                               .validator(is_valid_alpha)
+                               .default_value("0.05"))
+                          .arg(Arg::with_name("population")
+                               .value_name("SIZE")
+                               .long("population")
+                               .help("Number of candidate weights sampled per generation")
+                               .validator(is_positive_integer)
+                               .default_value("12"))
+                          .arg(Arg::with_name("generations")
+                               .value_name("GENERATIONS")
+                               .long("generations")
+                               .help("Number of generations")
+                               .validator(is_positive_integer)
+                               .default_value("15"))
+                          .arg(Arg::with_name("seed")
+                               .value_name("SEED")
+                               .long("seed")
+                               .help("Seed used to generate the bags and sample weights. Random if not set.")
+                               .validator(is_valid_seed))
+                          .arg(Arg::with_name("attempts")
+                               .value_name("ATTEMPTS")
+                               .long("attempts")
+                               .help("Number of packings attempted per bag. Defaults to the greedy solver's own, \
+                                      which depends on the number of totems.")
+                               .validator(is_positive_integer))
+                          .arg(Arg::with_name("threads")
+                               .value_name("THREADS")
+                               .long("threads")
+                               .help("Number of bags to evaluate in parallel")
+                               .validator(is_positive_integer)
+                               .default_value("4"))
+                          .arg(Arg::with_name("output")
+                               .value_name("FILE")
+                               .long("output")
+                               .help("Where to save the tuned parameters")
+                               .default_value(GREEDY_PARAMS_PATH))
+                          .get_matches();
+    let level: usize = matches.value_of("level").unwrap().parse().unwrap();
+    let level = level - 1;  // Logic assumes that levels are 0-indexed.
+    let (width, height) = match matches.value_of("dims") {
+        Some(dims) => parse_dims(dims).unwrap(),
+        None => *OptimalDimensions::new().level_dims(level).first().unwrap(),
+    };
+    let trials: usize = matches.value_of("trials").unwrap().parse().unwrap();
+    let held_out_trials: usize = matches.value_of("held_out_trials").unwrap().parse().unwrap();
+    let alpha: f64 = matches.value_of("alpha").unwrap().parse().unwrap();
+    let population: usize = matches.value_of("population").unwrap().parse().unwrap();
+    let generations: usize = matches.value_of("generations").unwrap().parse().unwrap();
+    let seed: u64 = matches.value_of("seed").map_or_else(|| rand::thread_rng().gen(), |seed| seed.parse().unwrap());
+    let max_attempts: Option<usize> = matches.value_of("attempts").map(|attempts| attempts.parse().unwrap());
+    let threads: usize = matches.value_of("threads").unwrap().parse().unwrap();
+    let output = Path::new(matches.value_of("output").unwrap());
+
+    let num_totems = 1 << level;
+    // Bags that can't be packed (odd 'T' shapes in a perfect packing) don't tell weights apart.
+    let perfect_pack = num_totems * 4 == width * height;
+    let generator = InstanceGenerator::with_options(ShapeDistribution::Uniform, /*allow_odd_t_shapes=*/!perfect_pack);
+    let sample_bags = |count: usize, rng: &mut SmallRng| -> Vec<TotemBag> {
+        (0..count).map(|_| generator.level(level, rng).get_totem_bag())
+            .filter(|bag| !perfect_pack || bag[Totem::T] % 2 == 0)
+            .collect()
+    };
+    let mut rng = instances::seeded_rng(seed, level as u64);
+    let bags = sample_bags(trials, &mut rng);
+    let held_out_bags = sample_bags(held_out_trials, &mut instances::seeded_rng(seed, HELD_OUT_STREAM + level as u64));
+
+    let mut tuned: TunedGreedyParams = if output.exists() {
+        greedy_solver::load_tuned_params(output).expect("Failed to load the existing greedy parameters.")
+    } else {
+        TunedGreedyParams::new()
+    };
+    let start = tuned.get(&num_totems).copied().unwrap_or_default();
+    let start_rate = pack_rate(&start, &bags, width, height, max_attempts, threads);
+    println!("Tuning level {} ({} totems) in {}x{}, on {} bags.", level + 1, num_totems, width, height, bags.len());
+    println!("Start: {:.1}%  ({})", start_rate * 100.0, describe(&start));
+
+    // Recombination weights of the best half, decreasing with the rank (like CMA-ES).
+    let num_parents = (population / 2).max(1);
+    let ranks: Vec<f32> = (0..num_parents).map(|i| ((num_parents as f32 + 0.5).ln() - (i as f32 + 1.0).ln()).max(0.0)).collect();
+    let rank_total: f32 = ranks.iter().sum();
+
+    let mut mean = to_weights(&start);
+    mean[4] = mean[4].max(MIN_INITIAL_TIES);
+    let mut steps = [INITIAL_STEP; NUM_WEIGHTS];
+    let (mut best, mut best_rate) = (start, start_rate);
+    for generation in 0..generations {
+        let start_time = Instant::now();
+        let mut candidates: Vec<([f32; NUM_WEIGHTS], f64)> = (0..population).map(|_| {
+            let mut weights = mean;
+            for (weight, step) in weights.iter_mut().zip(steps.iter()) {
+                *weight += step * gaussian(&mut rng);
+            }
+            let rate = pack_rate(&from_weights(&weights), &bags, width, height, max_attempts, threads);
+            (weights, rate)
+        }).collect();
+        candidates.push((to_weights(&best), best_rate));
+        candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        if candidates[0].1 > best_rate {
+            best = from_weights(&candidates[0].0);
+            best_rate = candidates[0].1;
+        }
+
+        let parents = &candidates[..num_parents];
+        let mut new_mean = [0f32; NUM_WEIGHTS];
+        let mut variances = [0f32; NUM_WEIGHTS];
+        for ((weights, _), rank) in parents.iter().zip(ranks.iter()) {
+            for i in 0..NUM_WEIGHTS {
+                new_mean[i] += rank / rank_total * weights[i];
+                variances[i] += rank / rank_total * (weights[i] - mean[i]).powi(2);
+            }
+        }
+        for i in 0..NUM_WEIGHTS {
+            let step = (1.0 - STEP_LEARNING_RATE) * steps[i] + STEP_LEARNING_RATE * variances[i].sqrt();
+            steps[i] = step.max(MIN_STEP);
+        }
+        mean = new_mean;
+        println!("Generation {}: best {:.1}%, median {:.1}%, overall best {:.1}%  ({:.1}s)", generation + 1,
+                 candidates[0].1 * 100.0, candidates[population / 2].1 * 100.0, best_rate * 100.0,
+                 start_time.elapsed().as_secs_f64());
+    }
+
+    println!("Best: {:.1}% (start: {:.1}%)  ({})", best_rate * 100.0, start_rate * 100.0, describe(&best));
+    if best_rate <= start_rate {
+        println!("Not better than the start, not saved.");
+        return;
+    }
+
+    // Paired comparison on the held-out bags.
+    let best_results = pack_results(&best, &held_out_bags, width, height, max_attempts, threads);
+    let start_results = pack_results(&start, &held_out_bags, width, height, max_attempts, threads);
+    let best_wins = best_results.iter().zip(start_results.iter()).filter(|&(&b, &s)| b && !s).count() as u64;
+    let start_wins = best_results.iter().zip(start_results.iter()).filter(|&(&b, &s)| !b && s).count() as u64;
+    let p_value = mcnemar_p_value(best_wins, start_wins);
+    println!("Held out: {:.1}% (start: {:.1}%) on {} bags, {} packed by the best only, {} by the start only (p={:.4}).",
+             rate(&best_results) * 100.0, rate(&start_results) * 100.0, held_out_bags.len(), best_wins, start_wins,
+             p_value);
+    if best_wins > start_wins && p_value < alpha {
+        tuned.insert(num_totems, best);
+        greedy_solver::save_tuned_params(output, &tuned).expect("Failed to save the greedy parameters.");
+        println!("Saved to {}.", output.display());
+    } else {
+        println!("Not significantly better than the start on held-out bags (alpha={}), not saved.", alpha);
+    }
+}
@@ -0,0 +1,518 @@
+// Interactive terminal viewer for solver traces (see 'trace', recorded with e.g. 'evaluate --trace-dir')
+// and answers (JSON, like sent to the server).
+// Steps forward and back through the events of a trace, showing the board with a colour per totem
+// and borders between pieces, what's left of the totem bag, and optionally a heatmap of the
+// touchpoints used by the greedy solver or the free rectangles (MaxRects) left on the board.
+// Needs the "viewer" feature:
+//   cargo run --release --features viewer --bin viewer -- traces/trial_0.json
+//
+// With '--print', prints a single frame and exits instead (e.g. to paste in an issue).
+
+extern crate application;
+
+use application::{
+    cli::is_positive_integer,
+    game_interface::{Answer, Totem, TotemBag, TOTEMS},
+    max_rects::MaxRects,
+    free_space::FreeSpace,
+    render::totem_color,
+    trace::{SolverEvent, TraceRecorder, TraceState},
+};
+use clap::{Arg, App};
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute, queue,
+    style::{Color, Print, Stylize},
+    terminal::{self, ClearType},
+};
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+// Events skipped with page up/down.
+const PAGE_EVENTS: usize = 100;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Overlay {
+    None,
+    // For each empty cell, how many of its sides touch a totem or a border.
+    Touchpoints,
+    // Maximal free rectangles left, as tracked by MaxRects.
+    FreeRects,
+}
+
+impl Overlay {
+    fn next(self) -> Self {
+        match self {
+            Overlay::None => Overlay::Touchpoints,
+            Overlay::Touchpoints => Overlay::FreeRects,
+            Overlay::FreeRects => Overlay::None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Overlay::None => "none",
+            Overlay::Touchpoints => "touchpoints",
+            Overlay::FreeRects => "free rectangles",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Owner {
+    Empty,
+    // Index of the totem in the state, and its shape.
+    Totem(usize, Totem),
+    // Index of the committed rectangle, for rectangles whose totems are not known yet.
+    Rect(usize),
+    Overlap,
+}
+
+// Board of the current state, indexed by [y][x].
+struct Board {
+    width: usize,
+    height: usize,
+    owners: Vec<Vec<Owner>>,
+}
+
+impl Board {
+    fn new(state: &TraceState) -> Self {
+        let coords = state.totems.iter().flat_map(|t| t.coordinates.iter().copied());
+        let (width, height) = coords.fold((state.width, state.height), |(w, h), (x, y)| (w.max(x + 1), h.max(y + 1)));
+        let mut owners = vec![vec![Owner::Empty; width]; height];
+        for (i, (rect_x, rect_y, rect_width, rect_height)) in state.rects.iter().enumerate() {
+            for row in owners.iter_mut().skip(*rect_y).take(*rect_height) {
+                for owner in row.iter_mut().skip(*rect_x).take(*rect_width) {
+                    *owner = if *owner == Owner::Empty { Owner::Rect(i) } else { Owner::Overlap };
+                }
+            }
+        }
+        for (i, totem) in state.totems.iter().enumerate() {
+            for (x, y) in &totem.coordinates {
+                let owner = &mut owners[*y][*x];
+                *owner = match *owner {
+                    // Totems are only known once their rectangle is placed, show the totems then.
+                    Owner::Empty | Owner::Rect(_) => Owner::Totem(i, totem.shape),
+                    _ => Owner::Overlap,
+                };
+            }
+        }
+        Board { width, height, owners }
+    }
+
+    fn get(&self, x: isize, y: isize) -> Option<Owner> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            None
+        } else {
+            Some(self.owners[y as usize][x as usize])
+        }
+    }
+
+    // Whether there is a border between two cells (None being outside of the board).
+    fn is_border(a: Option<Owner>, b: Option<Owner>) -> bool {
+        match (a, b) {
+            (None, None) => false,
+            (Some(Owner::Empty), Some(Owner::Empty)) => false,
+            (Some(Owner::Empty), None) | (None, Some(Owner::Empty)) => true,
+            (a, b) => a != b,
+        }
+    }
+
+    fn touchpoints(&self, x: usize, y: usize) -> usize {
+        let (x, y) = (x as isize, y as isize);
+        [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)].iter()
+            .filter(|(x, y)| self.get(*x, *y) != Some(Owner::Empty))
+            .count()
+    }
+
+    // Free rectangles as tracked by MaxRects if we had placed the rectangles and totems so far.
+    fn free_rects(&self, state: &TraceState) -> Vec<(usize, usize, usize, usize)> {
+        let mut max_rects = MaxRects::new(self.width, self.height);
+        for (x, y, width, height) in &state.rects {
+            max_rects.place_at(*x, *y, *width, *height);
+        }
+        for totem in &state.totems {
+            for (x, y) in &totem.coordinates {
+                max_rects.place_at(*x, *y, 1, 1);
+            }
+        }
+        let mut free = max_rects.free_rects();
+        free.sort_by_key(|(x, y, _, _)| (*y, *x));
+        free
+    }
+}
+
+fn rgb((r, g, b): (u8, u8, u8)) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+// Background colour of a piece.
+fn owner_color(owner: Owner) -> Option<Color> {
+    match owner {
+        Owner::Totem(_, shape) => Some(rgb(totem_color(shape))),
+        Owner::Rect(i) => Some(if i % 2 == 0 { Color::Grey } else { Color::DarkGrey }),
+        _ => None,
+    }
+}
+
+fn heat_color(value: usize) -> Color {
+    let value = value.min(4) as u8;
+    Color::Rgb { r: 60 + 45 * value, g: 40 + 20 * value, b: 40 }
+}
+
+// Box-drawing character joining the given borders around a grid corner.
+fn corner(up: bool, down: bool, left: bool, right: bool) -> char {
+    match (up, down, left, right) {
+        (false, false, false, false) => ' ',
+        (true, true, false, false) | (true, false, false, false) | (false, true, false, false) => '│',
+        (false, false, true, true) | (false, false, true, false) | (false, false, false, true) => '─',
+        (true, false, false, true) => '└',
+        (true, false, true, false) => '┘',
+        (false, true, false, true) => '┌',
+        (false, true, true, false) => '┐',
+        (true, true, false, true) => '├',
+        (true, true, true, false) => '┤',
+        (false, true, true, true) => '┬',
+        (true, false, true, true) => '┴',
+        (true, true, true, true) => '┼',
+    }
+}
+
+fn describe(event: &SolverEvent) -> String {
+    match event {
+        SolverEvent::TryDims { width, height, bag } => format!("trying {}x{} with {} totems", width, height, bag.total()),
+        SolverEvent::Place { totem } => format!("placed {:?} at {:?}", totem.shape, totem.coordinates),
+        SolverEvent::Backtrack => String::from("backtracked"),
+        SolverEvent::Restart => String::from("restarted"),
+        SolverEvent::PlaceRect { x, y, width, height } =>
+            format!("committed {}x{} rectangle at ({}, {})", width, height, x, y),
+        SolverEvent::Rollout { depth, total } => format!("rollout placed {}/{} rectangles", depth, total),
+        SolverEvent::Finish { solved } => String::from(if *solved { "solved" } else { "failed" }),
+    }
+}
+
+// Events of an answer, as if it was solved by placing its totems in order.
+fn answer_events(answer: &Answer) -> Vec<SolverEvent> {
+    let coords = answer.totems.iter().flat_map(|t| t.coordinates.iter().copied());
+    let (width, height) = coords.fold((0, 0), |(w, h), (x, y)| (w.max(x + 1), h.max(y + 1)));
+    let bag = TotemBag::from_iter(answer.totems.iter().map(|t| t.shape));
+    let mut events = vec![SolverEvent::TryDims { width, height, bag }];
+    events.extend(answer.totems.iter().map(|totem| SolverEvent::Place { totem: totem.clone() }));
+    events
+}
+
+fn load_events(path: &Path) -> Result<Vec<SolverEvent>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let events = match serde_json::from_str::<TraceRecorder>(&text) {
+        Ok(trace) => trace.events,
+        Err(_) => match serde_json::from_str::<Answer>(&text) {
+            Ok(answer) => answer_events(&answer),
+            Err(e) => return Err(format!("{} is neither a trace nor an answer: {}", path.display(), e)),
+        },
+    };
+    if events.is_empty() {
+        return Err(format!("{} has no events", path.display()));
+    }
+    Ok(events)
+}
+
+// Events after which the search got stuck: the last event before a restart or a failed finish.
+fn failure_points(events: &[SolverEvent]) -> Vec<usize> {
+    events.windows(2).enumerate()
+        .filter(|(_, pair)| matches!(pair[1], SolverEvent::Restart | SolverEvent::Finish { solved: false }))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+struct Viewer {
+    name: String,
+    events: Vec<SolverEvent>,
+    failures: Vec<usize>,
+    index: usize,
+    state: TraceState,
+    overlay: Overlay,
+    borders: bool,
+    selected_free_rect: usize,
+    // First line of the board shown, when it doesn't fit in the terminal.
+    scroll: usize,
+    message: Option<String>,
+}
+
+impl Viewer {
+    fn new(name: String, events: Vec<SolverEvent>, index: usize, overlay: Overlay) -> Self {
+        let failures = failure_points(&events);
+        let state = TraceState::at(&events, index);
+        Viewer {
+            name, events, failures, index, state, overlay,
+            borders: true,
+            selected_free_rect: 0,
+            scroll: 0,
+            message: None,
+        }
+    }
+
+    fn go_to(&mut self, index: usize) {
+        self.index = index.min(self.events.len() - 1);
+        self.state = TraceState::at(&self.events, self.index);
+    }
+
+    fn step(&mut self, delta: isize) {
+        self.go_to(self.index.saturating_add_signed(delta));
+    }
+
+    // Jumps to the next (or previous) event matching 'predicate'.
+    fn jump(&mut self, forward: bool, what: &str, predicate: impl Fn(usize, &SolverEvent) -> bool) {
+        let found = if forward {
+            (self.index + 1..self.events.len()).find(|i| predicate(*i, &self.events[*i]))
+        } else {
+            (0..self.index).rev().find(|i| predicate(*i, &self.events[*i]))
+        };
+        match found {
+            Some(index) => self.go_to(index),
+            None => self.message = Some(format!("No {} {}.", if forward { "next" } else { "previous" }, what)),
+        }
+    }
+
+    fn jump_to_failure(&mut self, forward: bool) {
+        let failures = std::mem::take(&mut self.failures);
+        self.jump(forward, "failure point", |i, _| failures.binary_search(&i).is_ok());
+        self.failures = failures;
+    }
+
+    fn jump_to_attempt(&mut self, forward: bool) {
+        self.jump(forward, "attempt", |_, e| matches!(e, SolverEvent::Restart | SolverEvent::TryDims { .. }));
+    }
+
+    fn header(&self) -> Vec<String> {
+        let state = &self.state;
+        let status = match state.solved {
+            Some(true) => "solved".green().to_string(),
+            Some(false) => "failed".red().to_string(),
+            None => String::from("searching"),
+        };
+        let mut lines = vec![
+            format!("{}  event {}/{}: {}", self.name, self.index + 1, self.events.len(), describe(&self.events[self.index])),
+            format!("{}x{}  restart #{}  placed {}/{} totems  {}",
+                    state.width, state.height, state.restarts, state.bag.total() - state.bag_left.total(),
+                    state.bag.total(), status),
+        ];
+        let bag_left: Vec<String> = TOTEMS.iter()
+            .map(|t| format!("{} {:<3}", format!(" {:?} ", t).black().on(rgb(totem_color(*t))), state.bag_left[t]))
+            .collect();
+        lines.push(format!("Left: {}", bag_left.join(" ")));
+        if !state.rects.is_empty() || state.last_rollout.is_some() {
+            let rollout = state.last_rollout.map_or(String::from("none"), |(depth, total)| format!("{}/{}", depth, total));
+            lines.push(format!("{} rectangles committed, last rollout: {}", state.rects.len(), rollout));
+        }
+        lines.push(format!("Overlay: {}  Failure points: {}", self.overlay.name(), self.failures.len()));
+        lines
+    }
+
+    fn board(&self) -> Vec<String> {
+        let board = Board::new(&self.state);
+        let free_rects = if self.overlay == Overlay::FreeRects { board.free_rects(&self.state) } else { Vec::new() };
+        let selected = free_rects.get(self.selected_free_rect % free_rects.len().max(1)).copied();
+        let mut lines = Vec::new();
+        if self.overlay == Overlay::FreeRects {
+            lines.push(match selected {
+                Some((x, y, w, h)) => format!("Free rectangle {}/{}: {}x{} at ({}, {})",
+                                              self.selected_free_rect % free_rects.len() + 1, free_rects.len(), w, h, x, y),
+                None => String::from("No free rectangles."),
+            });
+        }
+        let cell = |x: usize, y: usize| -> String {
+            match board.owners[y][x] {
+                owner @ Owner::Totem(..) => "  ".on(owner_color(owner).unwrap()).to_string(),
+                owner @ Owner::Rect(_) => "░░".with(Color::Black).on(owner_color(owner).unwrap()).to_string(),
+                Owner::Overlap => "XX".with(Color::White).on(Color::Red).to_string(),
+                Owner::Empty => match self.overlay {
+                    Overlay::None => " ·".dark_grey().to_string(),
+                    Overlay::Touchpoints => {
+                        let value = board.touchpoints(x, y);
+                        format!("{:>2}", value).with(Color::White).on(heat_color(value)).to_string()
+                    }
+                    Overlay::FreeRects => {
+                        let covering = free_rects.iter()
+                            .filter(|(rx, ry, rw, rh)| (*rx..rx + rw).contains(&x) && (*ry..ry + rh).contains(&y))
+                            .count();
+                        let is_selected = selected.is_some_and(|(rx, ry, rw, rh)| (rx..rx + rw).contains(&x) && (ry..ry + rh).contains(&y));
+                        let text = format!("{:>2}", covering).with(Color::White);
+                        if is_selected { text.on(Color::DarkBlue) } else { text.on(heat_color(covering)) }.to_string()
+                    }
+                },
+            }
+        };
+        // Gaps between cells of the same piece are filled with its colour.
+        let fill = |owners: &[Option<Owner>], text: &str| -> String {
+            match owners[0] {
+                Some(owner) if owners.iter().all(|o| *o == Some(owner)) => match owner_color(owner) {
+                    Some(color) => text.on(color).to_string(),
+                    None => text.to_string(),
+                },
+                _ => text.to_string(),
+            }
+        };
+        let (width, height) = (board.width as isize, board.height as isize);
+        let vertical_border = |x: isize, y: isize| Board::is_border(board.get(x - 1, y), board.get(x, y));
+        let horizontal_border = |x: isize, y: isize| Board::is_border(board.get(x, y - 1), board.get(x, y));
+        // Border line between rows 'y - 1' and 'y'.
+        let border_line = |y: isize| {
+            let mut line = String::new();
+            for x in 0..=width {
+                let (up, down) = (vertical_border(x, y), vertical_border(x, y - 1));
+                let (left, right) = (horizontal_border(x - 1, y), horizontal_border(x, y));
+                line.push_str(&match corner(up, down, left, right) {
+                    ' ' => fill(&[board.get(x - 1, y - 1), board.get(x, y - 1), board.get(x - 1, y), board.get(x, y)], " "),
+                    c => c.to_string(),
+                });
+                if x < width {
+                    line.push_str(&if right { String::from("──") } else { fill(&[board.get(x, y - 1), board.get(x, y)], "  ") });
+                }
+            }
+            line
+        };
+        // Draw from the top, since y points up.
+        for y in (0..height).rev() {
+            if self.borders {
+                lines.push(border_line(y + 1));
+            }
+            let mut line = String::new();
+            for x in 0..=width {
+                if self.borders {
+                    line.push_str(&if vertical_border(x, y) {
+                        String::from("│")
+                    } else {
+                        fill(&[board.get(x - 1, y), board.get(x, y)], " ")
+                    });
+                }
+                if x < width {
+                    line.push_str(&cell(x as usize, y as usize));
+                }
+            }
+            lines.push(line);
+        }
+        if self.borders {
+            lines.push(border_line(0));
+        }
+        lines
+    }
+
+    fn footer(&self) -> Vec<String> {
+        vec![
+            String::from("←/→ step  PgUp/PgDn ±100  Home/End  r/R next/prev attempt  f/F next/prev failure  \
+                          o overlay  Tab select free rect  b borders  ↑/↓ scroll  q quit"),
+            self.message.clone().unwrap_or_default(),
+        ]
+    }
+
+    // Handles a key press, returns false to quit.
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        self.message = None;
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Char(' ') => self.step(1),
+            KeyCode::Left | KeyCode::Char('h') => self.step(-1),
+            KeyCode::PageDown => self.step(PAGE_EVENTS as isize),
+            KeyCode::PageUp => self.step(-(PAGE_EVENTS as isize)),
+            KeyCode::Home => self.go_to(0),
+            KeyCode::End => self.go_to(self.events.len() - 1),
+            KeyCode::Char('f') => self.jump_to_failure(true),
+            KeyCode::Char('F') => self.jump_to_failure(false),
+            KeyCode::Char('r') => self.jump_to_attempt(true),
+            KeyCode::Char('R') => self.jump_to_attempt(false),
+            KeyCode::Char('o') => self.overlay = self.overlay.next(),
+            KeyCode::Tab => self.selected_free_rect = self.selected_free_rect.wrapping_add(1),
+            KeyCode::BackTab => self.selected_free_rect = self.selected_free_rect.wrapping_sub(1),
+            KeyCode::Char('b') => self.borders = !self.borders,
+            KeyCode::Down => self.scroll += 1,
+            KeyCode::Up => self.scroll = self.scroll.saturating_sub(1),
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let (_, rows) = terminal::size()?;
+        let (header, board, footer) = (self.header(), self.board(), self.footer());
+        let board_rows = (rows as usize).saturating_sub(header.len() + footer.len() + 1);
+        self.scroll = self.scroll.min(board.len().saturating_sub(board_rows));
+        let board = board.iter().skip(self.scroll).take(board_rows);
+        let blank = String::new();
+        let lines = header.iter().chain(std::iter::once(&blank)).chain(board).chain(&footer);
+        for (row, line) in lines.enumerate() {
+            queue!(out, cursor::MoveTo(0, row as u16), Print(line), terminal::Clear(ClearType::UntilNewLine))?;
+        }
+        queue!(out, terminal::Clear(ClearType::FromCursorDown))?;
+        out.flush()
+    }
+}
+
+fn run_interactive(viewer: &mut Viewer) -> io::Result<()> {
+    let mut out = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(out, terminal::EnterAlternateScreen, terminal::DisableLineWrap, cursor::Hide)?;
+    let result = (|| -> io::Result<()> {
+        loop {
+            viewer.draw(&mut out)?;
+            match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press && !viewer.handle_key(key.code) => return Ok(()),
+                _ => {}  // e.g. resize, redraw.
+            }
+        }
+    })();
+    // Always restore the terminal, even on errors.
+    execute!(out, cursor::Show, terminal::EnableLineWrap, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn main() {
+    let matches = App::new("Coveo 2022 Inscription Trace Viewer")
+                          .arg(Arg::with_name("file")
+                               .value_name("FILE")
+                               .help("Trace (e.g. from 'evaluate --trace-dir') or answer JSON file to view")
+                               .required(true))
+                          .arg(Arg::with_name("event")
+                               .value_name("N")
+                               .long("event")
+                               .help("Event to start at (1-indexed). Defaults to the last one.")
+                               .validator(is_positive_integer))
+                          .arg(Arg::with_name("overlay")
+                               .value_name("OVERLAY")
+                               .long("overlay")
+                               .help("Overlay on empty cells")
+                               .possible_values(&["none", "touchpoints", "free_rects"])
+                               .default_value("none"))
+                          .arg(Arg::with_name("print")
+                               .long("print")
+                               .help("Prints the frame at the given event and exits, instead of being interactive.")
+                               .takes_value(false))
+                          .get_matches();
+    let path = Path::new(matches.value_of("file").unwrap());
+    let events = load_events(path).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let index = matches.value_of("event").map_or(events.len() - 1, |n| n.parse::<usize>().unwrap() - 1);
+    let overlay = match matches.value_of("overlay").unwrap() {
+        "touchpoints" => Overlay::Touchpoints,
+        "free_rects" => Overlay::FreeRects,
+        _ => Overlay::None,
+    };
+    let name = path.file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned());
+    let index = index.min(events.len() - 1);
+    let mut viewer = Viewer::new(name, events, index, overlay);
+
+    if matches.is_present("print") {
+        for line in viewer.header().iter().chain(&viewer.board()) {
+            println!("{}", line);
+        }
+    } else if let Err(e) = run_interactive(&mut viewer) {
+        eprintln!("Terminal error: {}", e);
+        std::process::exit(1);
+    }
+}
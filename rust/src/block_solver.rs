@@ -0,0 +1,331 @@
+// Constructive solver: pairs totems into small blocks that are known to tile a rectangle (e.g. two
+// 'I's in a 4x2, two 'L's in a 4x2, four 'T's in a 4x4), then lays the blocks out in shelves.
+// No search is involved, so this always finds a valid (if not perfect) answer in microseconds,
+// whatever the bag. The hybrid solver uses it as a floor under its other strategies.
+//
+// 'S' and 'Z' can't tile a rectangle on their own, so they are paired first, with 'J', 'L' or 'T'
+// shapes. Totems that are left without a block are placed on their own, as their bounding box.
+// Shelves are filled left to right with blocks of decreasing heights, opening a new shelf on top
+// once a block doesn't fit in the width. To pick the dimensions, every shelf width (up to about
+// twice the side of a square) is tried, and the best scoring layout is kept.
+//
+// The recipes below were found with an exhaustive search of the rectangles that 2-4 totems tile.
+
+use crate::{
+    game_interface::{Answer, Point, Question, Totem, TotemAnswer, TotemBag},
+    scoring::{score, Dims},
+    shape_info::ShapeVariant,
+    solver::{macros::solver_boilerplate, Solver},
+    transform::Transform,
+};
+
+// Totems tiling a width x height rectangle (width >= height).
+struct Recipe {
+    width: usize,
+    height: usize,
+    totems: &'static [(Totem, [Point; 4])],
+}
+
+impl Recipe {
+    fn cost(&self) -> TotemBag {
+        TotemBag::from_iter(self.totems.iter().map(|(shape, _)| *shape))
+    }
+}
+
+const O: Recipe = Recipe { width: 2, height: 2, totems: &[
+    (Totem::O, [(0, 0), (1, 0), (0, 1), (1, 1)]),
+]};
+const I: Recipe = Recipe { width: 4, height: 1, totems: &[
+    (Totem::I, [(0, 0), (1, 0), (2, 0), (3, 0)]),
+]};
+const II: Recipe = Recipe { width: 4, height: 2, totems: &[
+    (Totem::I, [(0, 0), (1, 0), (2, 0), (3, 0)]),
+    (Totem::I, [(0, 1), (1, 1), (2, 1), (3, 1)]),
+]};
+const JJ: Recipe = Recipe { width: 4, height: 2, totems: &[
+    (Totem::J, [(0, 0), (1, 0), (2, 0), (0, 1)]),
+    (Totem::J, [(3, 0), (1, 1), (2, 1), (3, 1)]),
+]};
+const LL: Recipe = Recipe { width: 4, height: 2, totems: &[
+    (Totem::L, [(0, 0), (0, 1), (1, 1), (2, 1)]),
+    (Totem::L, [(1, 0), (2, 0), (3, 0), (3, 1)]),
+]};
+const IJL: Recipe = Recipe { width: 6, height: 2, totems: &[
+    (Totem::J, [(0, 0), (1, 0), (2, 0), (0, 1)]),
+    (Totem::L, [(3, 0), (4, 0), (5, 0), (5, 1)]),
+    (Totem::I, [(1, 1), (2, 1), (3, 1), (4, 1)]),
+]};
+const JLO: Recipe = Recipe { width: 4, height: 3, totems: &[
+    (Totem::J, [(0, 0), (0, 1), (0, 2), (1, 2)]),
+    (Totem::O, [(1, 0), (2, 0), (1, 1), (2, 1)]),
+    (Totem::L, [(3, 0), (3, 1), (2, 2), (3, 2)]),
+]};
+const TTTT: Recipe = Recipe { width: 4, height: 4, totems: &[
+    (Totem::T, [(0, 0), (0, 1), (1, 1), (0, 2)]),
+    (Totem::T, [(1, 0), (2, 0), (3, 0), (2, 1)]),
+    (Totem::T, [(3, 1), (2, 2), (3, 2), (3, 3)]),
+    (Totem::T, [(1, 2), (0, 3), (1, 3), (2, 3)]),
+]};
+const JTT: Recipe = Recipe { width: 4, height: 3, totems: &[
+    (Totem::J, [(0, 0), (1, 0), (2, 0), (0, 1)]),
+    (Totem::T, [(3, 0), (2, 1), (3, 1), (3, 2)]),
+    (Totem::T, [(1, 1), (0, 2), (1, 2), (2, 2)]),
+]};
+const LTT: Recipe = Recipe { width: 4, height: 3, totems: &[
+    (Totem::T, [(0, 0), (0, 1), (1, 1), (0, 2)]),
+    (Totem::L, [(1, 0), (2, 0), (3, 0), (3, 1)]),
+    (Totem::T, [(2, 1), (1, 2), (2, 2), (3, 2)]),
+]};
+const LLS: Recipe = Recipe { width: 4, height: 3, totems: &[
+    (Totem::L, [(0, 0), (1, 0), (0, 1), (0, 2)]),
+    (Totem::S, [(2, 0), (1, 1), (2, 1), (1, 2)]),
+    (Totem::L, [(3, 0), (3, 1), (2, 2), (3, 2)]),
+]};
+const JLS: Recipe = Recipe { width: 4, height: 3, totems: &[
+    (Totem::J, [(0, 0), (0, 1), (0, 2), (1, 2)]),
+    (Totem::L, [(1, 0), (2, 0), (3, 0), (3, 1)]),
+    (Totem::S, [(1, 1), (2, 1), (2, 2), (3, 2)]),
+]};
+const JJSS: Recipe = Recipe { width: 4, height: 4, totems: &[
+    (Totem::J, [(0, 0), (1, 0), (2, 0), (0, 1)]),
+    (Totem::S, [(3, 0), (2, 1), (3, 1), (2, 2)]),
+    (Totem::S, [(1, 1), (0, 2), (1, 2), (0, 3)]),
+    (Totem::J, [(3, 2), (1, 3), (2, 3), (3, 3)]),
+]};
+const JSTT: Recipe = Recipe { width: 4, height: 4, totems: &[
+    (Totem::J, [(0, 0), (1, 0), (2, 0), (0, 1)]),
+    (Totem::T, [(3, 0), (2, 1), (3, 1), (3, 2)]),
+    (Totem::S, [(1, 1), (0, 2), (1, 2), (0, 3)]),
+    (Totem::T, [(2, 2), (1, 3), (2, 3), (3, 3)]),
+]};
+const JJZ: Recipe = Recipe { width: 4, height: 3, totems: &[
+    (Totem::J, [(0, 0), (0, 1), (0, 2), (1, 2)]),
+    (Totem::Z, [(1, 0), (1, 1), (2, 1), (2, 2)]),
+    (Totem::J, [(2, 0), (3, 0), (3, 1), (3, 2)]),
+]};
+const JLZ: Recipe = Recipe { width: 4, height: 3, totems: &[
+    (Totem::J, [(0, 0), (1, 0), (2, 0), (0, 1)]),
+    (Totem::L, [(3, 0), (3, 1), (2, 2), (3, 2)]),
+    (Totem::Z, [(1, 1), (2, 1), (0, 2), (1, 2)]),
+]};
+const LLZZ: Recipe = Recipe { width: 4, height: 4, totems: &[
+    (Totem::L, [(0, 0), (1, 0), (0, 1), (0, 2)]),
+    (Totem::Z, [(2, 0), (3, 0), (1, 1), (2, 1)]),
+    (Totem::L, [(3, 1), (3, 2), (2, 3), (3, 3)]),
+    (Totem::Z, [(1, 2), (2, 2), (0, 3), (1, 3)]),
+]};
+const LTTZ: Recipe = Recipe { width: 4, height: 4, totems: &[
+    (Totem::L, [(0, 0), (1, 0), (0, 1), (0, 2)]),
+    (Totem::Z, [(2, 0), (3, 0), (1, 1), (2, 1)]),
+    (Totem::T, [(3, 1), (2, 2), (3, 2), (3, 3)]),
+    (Totem::T, [(1, 2), (0, 3), (1, 3), (2, 3)]),
+]};
+
+// In order of preference, for the 'S' and 'Z' shapes, then for the rest.
+const S_RECIPES: [&Recipe; 4] = [&LLS, &JLS, &JJSS, &JSTT];
+const Z_RECIPES: [&Recipe; 4] = [&JJZ, &JLZ, &LLZZ, &LTTZ];
+const RECIPES: [&Recipe; 9] = [&TTTT, &JTT, &LTT, &JJ, &LL, &IJL, &JLO, &II, &I];
+
+// Totems placed within a width x height rectangle, at (0, 0).
+struct Block {
+    width: usize,
+    height: usize,
+    totems: Vec<TotemAnswer>,
+}
+
+impl Block {
+    // If 'tall', the recipe is rotated to be higher than wide.
+    fn from_recipe(recipe: &Recipe, tall: bool) -> Self {
+        let totems = recipe.totems.iter().map(|(shape, coords)| TotemAnswer::new(*shape, *coords));
+        if tall {
+            let rotation = Transform::Rotate90;
+            Block {
+                width: recipe.height,
+                height: recipe.width,
+                totems: totems.map(|totem| rotation.apply_totem(&totem, recipe.width, recipe.height)).collect(),
+            }
+        } else {
+            Block { width: recipe.width, height: recipe.height, totems: totems.collect() }
+        }
+    }
+
+    // Totem on its own, in the widest (or highest, if 'tall') rotation that covers (0, 0), so that
+    // the layout always covers (0, 0).
+    fn single(shape: Totem, tall: bool) -> Self {
+        let variant = ShapeVariant::get_rotations(&shape).iter()
+            .filter(|variant| variant.coords.contains(&(0, 0)))
+            .max_by_key(|variant| if tall { variant.height } else { variant.width })
+            .unwrap();
+        Block { width: variant.width, height: variant.height, totems: vec![TotemAnswer::new(shape, variant.coords)] }
+    }
+}
+
+// Takes the first recipe that 'bag' can afford, if any.
+fn take_first(bag: &mut TotemBag, recipes: &[&'static Recipe]) -> Option<&'static Recipe> {
+    let recipe = *recipes.iter().find(|recipe| bag.can_afford(&recipe.cost()))?;
+    bag.subtract(&recipe.cost());
+    Some(recipe)
+}
+
+// Blocks for all the totems of 'bag', with the highest (or widest, if 'tall') first.
+fn pick_blocks(bag: &TotemBag, tall: bool) -> Vec<Block> {
+    let mut bag = bag.clone();
+    let mut recipes = Vec::new();
+    // Alternate between 'S' and 'Z', so that one doesn't take all the 'J's and 'L's.
+    loop {
+        let s = take_first(&mut bag, &S_RECIPES);
+        let z = take_first(&mut bag, &Z_RECIPES);
+        if s.is_none() && z.is_none() {
+            break;
+        }
+        recipes.extend(s.into_iter().chain(z));
+    }
+    for recipe in RECIPES.iter().chain(&[&O]) {
+        while bag.can_afford(&recipe.cost()) {
+            bag.subtract(&recipe.cost());
+            recipes.push(recipe);
+        }
+    }
+    let mut blocks: Vec<Block> = recipes.into_iter().map(|recipe| Block::from_recipe(recipe, tall)).collect();
+    blocks.extend(bag.expand().map(|shape| Block::single(shape, tall)));
+    // Stable, so that full rectangles come first (and cover (0, 0)) on ties.
+    blocks.sort_by_key(|block| std::cmp::Reverse(if tall { block.width } else { block.height }));
+    blocks
+}
+
+// Lays out 'blocks' in shelves of at most 'shelf_width' (columns of at most that height, if
+// 'tall'). Returns the totems and the dimensions they take, or None if a block doesn't fit.
+fn layout(blocks: &[Block], shelf_width: usize, tall: bool) -> Option<(Vec<TotemAnswer>, Dims)> {
+    let mut totems = Vec::with_capacity(blocks.iter().map(|block| block.totems.len()).sum());
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+    let mut dims = (0, 0);
+    for block in blocks {
+        let (width, height) = if tall { (block.height, block.width) } else { (block.width, block.height) };
+        if width > shelf_width {
+            return None;
+        }
+        if x + width > shelf_width {
+            x = 0;
+            y += shelf_height;
+            shelf_height = 0;
+        }
+        let (block_x, block_y) = if tall { (y, x) } else { (x, y) };
+        totems.extend(block.totems.iter().map(|totem| totem.offset_by(block_x, block_y)));
+        dims = (dims.0.max(block_x + block.width), dims.1.max(block_y + block.height));
+        shelf_height = shelf_height.max(height);
+        x += width;
+    }
+    Some((totems, dims))
+}
+
+pub struct BlockSolver {
+}
+
+impl BlockSolver {
+    // Best scoring layout of 'bag', along with its dimensions.
+    pub fn pack(&self, bag: &TotemBag) -> (Vec<TotemAnswer>, Dims) {
+        let num_totems = bag.total();
+        let blocks = pick_blocks(bag, /*tall=*/false);
+        let min_width = blocks.iter().map(|block| block.width).max().unwrap_or(0);
+        let square_side = ((num_totems * 4) as f64).sqrt().ceil() as usize;
+        let mut best: Option<(Vec<TotemAnswer>, Dims)> = None;
+        for shelf_width in min_width..=(2 * square_side).max(min_width) {
+            let (totems, dims) = layout(&blocks, shelf_width, /*tall=*/false).unwrap();
+            let is_better = best.as_ref().is_none_or(|(_, best_dims)| {
+                score(num_totems, dims.0, dims.1) > score(num_totems, best_dims.0, best_dims.1)
+            });
+            if is_better {
+                best = Some((totems, dims));
+            }
+        }
+        best.unwrap_or_default()
+    }
+}
+
+impl Solver for BlockSolver {
+    fn new() -> Self {
+        Self { }
+    }
+
+    fn solve(&self, question: &Question) -> Answer {
+        let num_totems = question.totems.len();
+        tracing::info!(totems = num_totems, "Received question.");
+        let bag = question.get_totem_bag();
+        solver_boilerplate! {
+            Answer::new(self.pack(&bag).0)
+        }
+    }
+
+    fn try_solve(&self, width: usize, height: usize, bag: &TotemBag) -> Option<Vec<TotemAnswer>> {
+        // Shelves along the width, or columns along the height for tall dimensions.
+        for tall in [false, true] {
+            let blocks = pick_blocks(bag, tall);
+            let shelf_width = if tall { height } else { width };
+            if let Some((totems, dims)) = layout(&blocks, shelf_width, tall) {
+                if dims.0 <= width && dims.1 <= height {
+                    return Some(totems);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game_interface::TOTEMS,
+        instances::{question_from_bag, seeded_rng, InstanceGenerator},
+        scoring::answer_score,
+    };
+    use std::collections::HashSet;
+
+    fn is_rotation_of(shape: Totem, coords: &[Point; 4]) -> bool {
+        let normalize = |coords: &[Point; 4]| {
+            let min_x = coords.iter().map(|p| p.0).min().unwrap();
+            let min_y = coords.iter().map(|p| p.1).min().unwrap();
+            coords.iter().map(|(x, y)| (x - min_x, y - min_y)).collect::<HashSet<_>>()
+        };
+        ShapeVariant::get_rotations(&shape).iter().any(|variant| normalize(&variant.coords) == normalize(coords))
+    }
+
+    #[test]
+    fn recipes_tile_their_rectangle() {
+        for recipe in S_RECIPES.iter().chain(&Z_RECIPES).chain(&RECIPES).chain(&[&O]) {
+            let block = Block::from_recipe(recipe, /*tall=*/true);
+            let cells: HashSet<Point> = block.totems.iter().flat_map(|totem| totem.coordinates).collect();
+            assert_eq!(cells.len(), recipe.width * recipe.height);
+            assert!(cells.iter().all(|&(x, y)| x < block.width && y < block.height));
+            for (shape, coords) in recipe.totems {
+                assert!(is_rotation_of(*shape, coords), "{:?} {:?}", shape, coords);
+            }
+        }
+    }
+
+    #[test]
+    fn packs_any_bag() {
+        let solver = BlockSolver::new();
+        let generator = InstanceGenerator::new();
+        let mut rng = seeded_rng(3, 0);
+        let mut bags: Vec<TotemBag> = TOTEMS.iter().map(|shape| TotemBag::from_iter([*shape])).collect();
+        bags.extend((0..10).map(|level| generator.level(level, &mut rng).get_totem_bag()));
+        for bag in bags {
+            let question = question_from_bag(&bag);
+            let (totems, (width, height)) = solver.pack(&bag);
+            assert_eq!(answer_score(&question, &Answer::new(totems)), Ok(score(bag.total(), width, height)));
+            assert!(solver.try_solve(width, height, &bag).is_some());
+            // Lone 'S' and 'Z' rotate differently in columns, so the rotated dimensions may not fit.
+            for (width, height) in [(width, height), (height, width)] {
+                if let Some(totems) = solver.try_solve(width, height, &bag) {
+                    assert!(totems.iter().all(|totem| is_rotation_of(totem.shape, &totem.coordinates)));
+                    assert!(totems.iter().flat_map(|totem| totem.coordinates).all(|(x, y)| x < width && y < height));
+                    assert!(answer_score(&question, &Answer::new(totems)).is_ok());
+                }
+            }
+        }
+        // Two 4x2 blocks, stacked in a column.
+        let bag = TotemBag::from_iter([Totem::I, Totem::I, Totem::J, Totem::J]);
+        assert!(solver.try_solve(2, 8, &bag).is_some());
+    }
+}
@@ -0,0 +1,327 @@
+// Optimality certificates: proofs that an answer has the best achievable score for its bag.
+// An answer is optimal if every dimensions that would score higher can't fit the bag. For each of
+// them, we look for a proof of infeasibility:
+//   - a feasibility rule: not enough cells, a totem that can't fit, or a perfect packing with an
+//     odd number of 'T' shapes (see the README),
+//   - otherwise, a refutation by exhaustive search, for small problems.
+// Dimensions without a proof leave the answer unproven: either the solver missed a packing, or
+// the search would take too long to tell.
+//
+// Note that all dimensions with a higher score are checked, not just the ones the hybrid solver
+// tries. A w x h board can be rotated into a h x w one without changing the shapes, so only one
+// orientation is checked.
+//
+// The same search also finds optimal answers for small bags (see 'optimal_answer'), used to
+// precompute the answers of the small levels.
+
+use crate::{
+    game_interface::{Totem, TotemAnswer, TotemBag, TOTEMS},
+    scoring::score,
+    shape_info::ShapeVariant,
+    transform::answer_dims,
+};
+use serde::Serialize;
+use std::time::Instant;
+
+// Search nodes after which a refutation is given up.
+pub const DEFAULT_MAX_NODES: usize = 1_000_000;
+// How often a search with a deadline checks the time.
+const NODES_PER_TIME_CHECK: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Proof {
+    // Fewer cells than needed for the totems.
+    TooSmall,
+    // A totem doesn't fit in any rotation.
+    TotemDoesNotFit { shape: Totem },
+    // Perfect packing with an odd number of 'T' shapes.
+    OddTShapes,
+    // The exhaustive search found no packing.
+    Refuted { nodes: usize },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DimsCertificate {
+    pub width: usize,
+    pub height: usize,
+    // None if infeasibility could not be proven.
+    pub proof: Option<Proof>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Certificate {
+    // Dimensions with a positive score higher than the answer's, best first.
+    pub better_dims: Vec<DimsCertificate>,
+    // Whether 'better_dims' has every dimensions that score higher. Not the case for answers
+    // without a positive score: there are infinitely many dimensions scoring between them and 0.
+    pub complete: bool,
+}
+
+impl Certificate {
+    pub fn is_proven_optimal(&self) -> bool {
+        self.complete && self.better_dims.iter().all(|dims| dims.proof.is_some())
+    }
+
+    pub fn status(&self) -> &'static str {
+        if self.is_proven_optimal() { "proven optimal" } else { "unproven" }
+    }
+}
+
+enum SearchResult {
+    Found,
+    Exhausted,
+    OutOfNodes,
+}
+
+// Whether a bag can be packed within given dimensions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Feasibility {
+    Feasible,
+    Infeasible(Proof),
+    // Ran out of search nodes (or time).
+    Unknown,
+}
+
+// Result of a search for a valid answer of given dimensions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Packing {
+    Found(Vec<TotemAnswer>),
+    Infeasible,
+    // Ran out of search nodes.
+    Unknown,
+}
+
+// Complete search for a packing: the first undecided cell (lowest, then leftmost) is either left
+// empty, if there are holes left, or covered by the anchor of some totem left in the bag.
+// Unless 'exact' is set, this doesn't require (0, 0) to be covered, so that refutations also hold
+// for rotated boards. When set, only valid answers of exactly these dimensions are accepted: they
+// cover (0, 0), and reach the last column and row.
+struct Search {
+    width: usize,
+    height: usize,
+    decided: Vec<bool>,  // Indexed by y * width + x, filled or left empty.
+    bag: TotemBag,
+    holes_left: usize,
+    exact: bool,
+    placed: Vec<TotemAnswer>,
+    solution: Option<Vec<TotemAnswer>>,
+    nodes: usize,
+    max_nodes: usize,
+    // Also gives up past this time, if set.
+    deadline: Option<Instant>,
+}
+
+impl Search {
+    fn new(width: usize, height: usize, bag: &TotemBag, exact: bool, max_nodes: usize) -> Self {
+        Search {
+            width, height,
+            decided: vec![false; width * height],
+            bag: bag.clone(),
+            holes_left: width * height - bag.total() * 4,
+            exact,
+            placed: Vec::with_capacity(bag.total()),
+            solution: None,
+            nodes: 0,
+            max_nodes,
+            deadline: None,
+        }
+    }
+
+    fn fits(&self, variant: &ShapeVariant, x: usize, y: usize) -> Option<[usize; 4]> {
+        let (anchor_x, anchor_y) = variant.anchor();
+        if x < anchor_x || y < anchor_y || x - anchor_x + variant.width > self.width || y - anchor_y + variant.height > self.height {
+            return None;
+        }
+        let mut cells = [0; 4];
+        for (cell, (dx, dy)) in cells.iter_mut().zip(&variant.coords) {
+            *cell = (y + dy - anchor_y) * self.width + x + dx - anchor_x;
+            if self.decided[*cell] {
+                return None;
+            }
+        }
+        Some(cells)
+    }
+
+    fn run(&mut self, cell: usize) -> SearchResult {
+        let cell = match (cell..self.decided.len()).find(|&i| !self.decided[i]) {
+            Some(cell) => cell,
+            None if self.exact && !self.spans_board() => return SearchResult::Exhausted,
+            None => {
+                self.solution = Some(self.placed.clone());
+                return SearchResult::Found;
+            }
+        };
+        self.nodes += 1;
+        if self.nodes > self.max_nodes {
+            return SearchResult::OutOfNodes;
+        }
+        let check_time = self.nodes % NODES_PER_TIME_CHECK == 0;
+        if check_time && self.deadline.is_some_and(|deadline| Instant::now() > deadline) {
+            return SearchResult::OutOfNodes;
+        }
+        let (x, y) = (cell % self.width, cell / self.width);
+        for totem in TOTEMS.iter() {
+            if self.bag[totem] == 0 {
+                continue;
+            }
+            for variant in ShapeVariant::get_rotations(totem) {
+                if let Some(cells) = self.fits(variant, x, y) {
+                    let (anchor_x, anchor_y) = variant.anchor();
+                    self.placed.push(TotemAnswer::new(*totem, variant.coords).offset_by(x - anchor_x, y - anchor_y));
+                    self.set(&cells, true);
+                    self.bag[totem] -= 1;
+                    let result = self.run(cell + 1);
+                    self.bag[totem] += 1;
+                    self.set(&cells, false);
+                    self.placed.pop();
+                    if !matches!(result, SearchResult::Exhausted) {
+                        return result;
+                    }
+                }
+            }
+        }
+        if self.holes_left > 0 && !(self.exact && cell == 0) {
+            self.holes_left -= 1;
+            self.decided[cell] = true;
+            let result = self.run(cell + 1);
+            self.decided[cell] = false;
+            self.holes_left += 1;
+            return result;
+        }
+        SearchResult::Exhausted
+    }
+
+    fn spans_board(&self) -> bool {
+        answer_dims(&self.placed) == (self.width, self.height)
+    }
+
+    fn set(&mut self, cells: &[usize], decided: bool) {
+        for cell in cells {
+            self.decided[*cell] = decided;
+        }
+    }
+}
+
+// Proof that 'bag' can't be packed in width x height, if one is found within 'max_nodes' search
+// nodes.
+pub fn prove_infeasible(width: usize, height: usize, bag: &TotemBag, max_nodes: usize) -> Option<Proof> {
+    match feasibility(width, height, bag, max_nodes, None) {
+        Feasibility::Infeasible(proof) => Some(proof),
+        Feasibility::Feasible | Feasibility::Unknown => None,
+    }
+}
+
+// Whether 'bag' can be packed within width x height (not necessarily covering (0, 0)), as far as
+// 'max_nodes' search nodes (and the time until 'deadline', if set) can tell.
+pub fn feasibility(width: usize, height: usize, bag: &TotemBag, max_nodes: usize,
+                   deadline: Option<Instant>) -> Feasibility {
+    let num_cells = bag.total() * 4;
+    if width * height < num_cells {
+        return Feasibility::Infeasible(Proof::TooSmall);
+    }
+    for totem in TOTEMS.iter().filter(|totem| bag.contains(totem)) {
+        let fits = ShapeVariant::get_rotations(totem).iter().any(|v| v.width <= width && v.height <= height);
+        if !fits {
+            return Feasibility::Infeasible(Proof::TotemDoesNotFit { shape: *totem });
+        }
+    }
+    if width * height == num_cells && bag[Totem::T] % 2 == 1 {
+        return Feasibility::Infeasible(Proof::OddTShapes);
+    }
+    let mut search = Search::new(width, height, bag, /*exact=*/false, max_nodes);
+    search.deadline = deadline;
+    match search.run(0) {
+        SearchResult::Found => Feasibility::Feasible,
+        SearchResult::Exhausted => Feasibility::Infeasible(Proof::Refuted { nodes: search.nodes }),
+        SearchResult::OutOfNodes => Feasibility::Unknown,
+    }
+}
+
+// Searches for a valid answer for 'bag' of exactly width x height.
+pub fn find_packing(width: usize, height: usize, bag: &TotemBag, max_nodes: usize) -> Packing {
+    if width * height < bag.total() * 4 {
+        return Packing::Infeasible;
+    }
+    let mut search = Search::new(width, height, bag, /*exact=*/true, max_nodes);
+    match search.run(0) {
+        SearchResult::Found => Packing::Found(search.solution.unwrap()),
+        SearchResult::Exhausted => Packing::Infeasible,
+        SearchResult::OutOfNodes => Packing::Unknown,
+    }
+}
+
+// Best scoring valid answer for 'bag', and whether it is proven optimal: dimensions are searched
+// best first, in both orientations, so the answer is optimal if all better dimensions were
+// searched exhaustively. None if no dimensions with a positive score could be packed.
+pub fn optimal_answer(bag: &TotemBag, max_nodes: usize) -> Option<(Vec<TotemAnswer>, bool)> {
+    let mut proven = true;
+    for (width, height) in better_dims(bag.total(), f32::MIN) {
+        let orientations = if width == height { vec![(width, height)] } else { vec![(width, height), (height, width)] };
+        for (width, height) in orientations {
+            match find_packing(width, height, bag, max_nodes) {
+                Packing::Found(answer) => return Some((answer, proven)),
+                Packing::Infeasible => {}
+                Packing::Unknown => proven = false,
+            }
+        }
+    }
+    None
+}
+
+// Dimensions (with width <= height) with a positive score strictly more than 'min_score' for
+// 'num_totems'.
+fn better_dims(num_totems: usize, min_score: f32) -> Vec<(usize, usize)> {
+    let mut dims = Vec::new();
+    // Positive scores need fewer than 10 cells per totem.
+    let max_cells = 10 * num_totems;
+    for width in (1..).take_while(|w| w * w < max_cells) {
+        for height in (width..).take_while(|h| width * h < max_cells) {
+            let dims_score = score(num_totems, width, height);
+            if width * height >= num_totems * 4 && dims_score > 0f32 && dims_score > min_score {
+                dims.push((width, height));
+            }
+        }
+    }
+    dims.sort_by(|a, b| score(num_totems, b.0, b.1).partial_cmp(&score(num_totems, a.0, a.1)).unwrap());
+    dims
+}
+
+// Certificate for an answer of 'bag'.
+pub fn certify(bag: &TotemBag, answer: &[TotemAnswer], max_nodes: usize) -> Certificate {
+    let (width, height) = answer_dims(answer);
+    let num_totems = bag.total();
+    let min_score = if answer.is_empty() { f32::MIN } else { score(num_totems, width, height) };
+    let better_dims = better_dims(num_totems, min_score).into_iter()
+        .map(|(width, height)| DimsCertificate { width, height, proof: prove_infeasible(width, height, bag, max_nodes) })
+        .collect();
+    Certificate { better_dims, complete: min_score > 0f32 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{exhaustive_solver::ExhaustiveSolver, solver::Solver};
+
+    #[test]
+    fn proves_small_answers_optimal() {
+        // Single 'I': 1x4 is the best, since it doesn't fit in anything that scores higher.
+        let bag = TotemBag::from_iter([Totem::I]);
+        let answer = ExhaustiveSolver::new().try_solve(1, 4, &bag).unwrap();
+        let certificate = certify(&bag, &answer, DEFAULT_MAX_NODES);
+        assert!(certificate.is_proven_optimal());
+        assert!(certificate.better_dims.iter().any(|d| d.proof == Some(Proof::TotemDoesNotFit { shape: Totem::I })));
+
+        // Four 'T's tile a 4x4 square, so there is no proof. Two of them can't tile 4x2, though.
+        assert_eq!(prove_infeasible(4, 4, &TotemBag::from_iter([Totem::T; 4]), DEFAULT_MAX_NODES), None);
+        assert!(matches!(prove_infeasible(4, 2, &TotemBag::from_iter([Totem::T; 2]), DEFAULT_MAX_NODES),
+                         Some(Proof::Refuted { .. })));
+        assert_eq!(prove_infeasible(4, 4, &TotemBag::from_iter([Totem::T; 5]), DEFAULT_MAX_NODES),
+                   Some(Proof::TooSmall));
+        assert_eq!(prove_infeasible(4, 4, &TotemBag([1, 0, 0, 0, 0, 3, 0]), DEFAULT_MAX_NODES),
+                   Some(Proof::OddTShapes));
+        // Four 'S's can't tile a 4x4 square (the bottom-left corner can't be covered).
+        assert!(matches!(prove_infeasible(4, 4, &TotemBag::from_iter([Totem::S; 4]), DEFAULT_MAX_NODES),
+                         Some(Proof::Refuted { .. })));
+    }
+}
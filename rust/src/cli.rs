@@ -0,0 +1,53 @@
+// Argument parsing and validation shared by the command line tools (see 'bin/'). Validators have
+// the signature clap expects from 'Arg::validator': they take the raw value and return why it's
+// invalid, if so.
+
+use crate::instances::{self, ShapeDistribution};
+
+// Parses dimensions of the form WIDTHxHEIGHT, e.g. "16x16". Both must be positive.
+pub fn parse_dims(dims: &str) -> Option<(usize, usize)> {
+    let (w, h) = dims.split_once('x')?;
+    let (w, h) = (w.parse().ok()?, h.parse().ok()?);
+    if w > 0 && h > 0 { Some((w, h)) } else { None }
+}
+
+pub fn is_valid_dims(dims: String) -> Result<(), String> {
+    parse_dims(&dims).map(|_| ()).ok_or_else(|| String::from("dims must be of the form WIDTHxHEIGHT, e.g. 16x16"))
+}
+
+// Levels are 1-indexed, like in the challenge.
+pub fn is_valid_level(level: String) -> Result<(), String> {
+    match level.parse::<usize>() {
+        Ok(level) if (1..=10).contains(&level) => Ok(()),
+        Ok(_) => Err(String::from("level must be between 1 and 10, inclusively")),
+        Err(_) => Err(String::from("level must be a positive integer")),
+    }
+}
+
+pub fn is_positive_integer(value: String) -> Result<(), String> {
+    match value.parse::<u64>() {
+        Ok(value) if value > 0 => Ok(()),
+        _ => Err(String::from("must be a positive integer")),
+    }
+}
+
+// Significance level of a statistical test.
+pub fn is_valid_alpha(alpha: String) -> Result<(), String> {
+    match alpha.parse::<f64>() {
+        Ok(alpha) if alpha > 0f64 && alpha < 1f64 => Ok(()),
+        _ => Err(String::from("alpha must be between 0 and 1, exclusively")),
+    }
+}
+
+pub fn is_valid_seed(seed: String) -> Result<(), String> {
+    seed.parse::<u64>().map(|_| ()).map_err(|_| String::from("seed must be a non-negative integer"))
+}
+
+// A bag given by the letters of its shapes or by counts per shape (see 'instances::parse_bag').
+pub fn is_valid_bag(bag: String) -> Result<(), String> {
+    instances::parse_bag(&bag).map(|_| ())
+}
+
+pub fn is_valid_shape_weights(weights: String) -> Result<(), String> {
+    ShapeDistribution::from_spec(&weights).map(|_| ())
+}
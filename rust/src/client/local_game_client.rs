@@ -1,12 +1,11 @@
 use application::{
-    game_interface::{GameMessage, Question, Totem, TotemQuestion},
+    game_interface::GameMessage,
+    instances::{self, InstanceGenerator},
+    render,
     solver::Solver,
 };
-use rand::{
-    self,
-    distributions::{Distribution, Uniform},
-};
 use std::{env, marker::PhantomData};
+use tracing::info;
 
 pub struct LocalGameClient<S>
 where
@@ -26,27 +25,28 @@ where
     }
 
     pub async fn run(&self) {
-        println!("[Running in local mode]");
+        info!("Running in local mode.");
 
-        let mut rng = rand::thread_rng();
-        let n_totems = match env::var("TOTEMS") {
-            Ok(val) => val.parse().unwrap(),
-            Err(_) => 8,
+        // BAG gives an explicit bag to solve (e.g. "IJJLOSTZ" or "I=2,T=6"), otherwise TOTEMS random
+        // totems are generated.
+        let question = match env::var("BAG") {
+            Ok(bag) => instances::question_from_bag(&instances::parse_bag(&bag).unwrap()),
+            Err(_) => {
+                let n_totems = match env::var("TOTEMS") {
+                    Ok(val) => val.parse().unwrap(),
+                    Err(_) => 8,
+                };
+                InstanceGenerator::new().question(n_totems, &mut rand::thread_rng())
+            }
         };
-        let mut questions: Vec<TotemQuestion> = Vec::new();
-        let die = Uniform::from(0..7);
-        for _ in 0..n_totems {
-            let idx: usize = die.sample(&mut rng);
-            let shape: Totem = unsafe { std::mem::transmute(idx) };
-            questions.push(TotemQuestion { shape });
-        }
-        let question = Question { totems: questions };
+        let n_totems = question.totems.len();
         let game_message = GameMessage {
             tick: 1,
             payload: question,
         };
 
         let solver = S::new();
-        solver.solve(&game_message.payload);
+        let answer = solver.solve(&game_message.payload);
+        render::save_if_requested(&answer, &format!("local_{}_totems", n_totems));
     }
 }
@@ -4,9 +4,11 @@ use futures_util::{SinkExt, StreamExt};
 use serde_json::{json, Value};
 use tokio_tungstenite::connect_async;
 use tokio_tungstenite::tungstenite::Message;
+use tracing::error;
 
 use application::{
     game_interface::GameMessage,
+    render,
     solver::Solver,
 };
 
@@ -50,7 +52,7 @@ where
                     .expect("The server sent a message that was not valid UTF-8");
 
                 if message_text.is_empty() {
-                    eprintln!("The server did not respond to our registration request");
+                    error!("The server did not respond to our registration request");
                     break;
                 }
 
@@ -58,7 +60,7 @@ where
                     .expect("The server sent an invalid JSON payload");
 
                 if parsed["type"] == "ERROR" {
-                    eprintln!("{}", parsed);
+                    error!(message = %parsed, "The server returned an error.");
                     break;
                 }
 
@@ -74,6 +76,8 @@ where
                     .send(Message::Text(response.to_string()))
                     .await
                     .expect("Could not send to the server");
+                // Only once the answer is sent, to keep the file I/O out of the time limit.
+                render::save_if_requested(&answer, &format!("tick_{}", game_message.tick));
             }
         }
     }
@@ -0,0 +1,142 @@
+// Detects placements that doom a board: the empty cells around the totem just placed are split in
+// connected regions, and each region wastes some cells that no totem can cover:
+//   - all of them if the region is too small or too narrow for every shape left in the bag (e.g. a
+//     1-wide pocket with no 'I' left),
+//   - otherwise at least its area modulo 4.
+// If these add up to more than the holes the board can afford, not every totem can be placed.
+//
+// Only regions touching the last placement are checked (the others didn't change), so this is
+// incremental. The search of a region stops past 'max_region_area' cells: big open regions are
+// assumed to waste nothing, which keeps the check cheap and the pruning sound.
+
+use crate::{
+    game_interface::{Point, TotemBag, TOTEMS},
+    shape_info::ShapeVariant,
+};
+
+pub struct RegionChecker {
+    width: usize,
+    height: usize,
+    max_region_area: usize,
+    // Generation of the last search that reached each cell, to avoid clearing. Each region search
+    // has its own generation, so that cells of a region given up on (too big) are told apart.
+    seen: Vec<u32>,
+    generation: u32,
+    stack: Vec<Point>,
+}
+
+impl RegionChecker {
+    pub fn new(width: usize, height: usize, max_region_area: usize) -> Self {
+        RegionChecker { width, height, max_region_area, seen: vec![0; width * height], generation: 0, stack: Vec::new() }
+    }
+
+    // Whether the totem just placed at 'placed' leaves regions wasting more than 'holes' cells,
+    // given the totems left in 'bag'. 'is_set' tells if a cell is covered.
+    pub fn is_doomed(&mut self, placed: &[Point; 4], bag: &TotemBag, holes: usize,
+                     is_set: impl Fn(usize, usize) -> bool) -> bool {
+        let first_generation = self.generation + 1;
+        let mut wasted = 0;
+        for &(x, y) in placed {
+            for (nx, ny) in self.neighbors(x, y) {
+                if is_set(nx, ny) || self.seen[ny * self.width + nx] >= first_generation {
+                    continue;
+                }
+                wasted += self.region_waste((nx, ny), bag, first_generation, &is_set);
+                if wasted > holes {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    // Lower bound on the cells of the region of 'start' that can't be covered. Regions searched
+    // since 'first_generation' are already accounted for.
+    fn region_waste(&mut self, start: Point, bag: &TotemBag, first_generation: u32,
+                    is_set: &impl Fn(usize, usize) -> bool) -> usize {
+        self.generation += 1;
+        let (mut area, mut min, mut max) = (0, start, start);
+        self.stack.clear();
+        self.stack.push(start);
+        self.seen[start.1 * self.width + start.0] = self.generation;
+        while let Some((x, y)) = self.stack.pop() {
+            area += 1;
+            if area > self.max_region_area {
+                return 0;
+            }
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+            for (nx, ny) in self.neighbors(x, y) {
+                let index = ny * self.width + nx;
+                if is_set(nx, ny) || self.seen[index] == self.generation {
+                    continue;
+                }
+                if self.seen[index] >= first_generation {
+                    // Part of a region given up on earlier in this check, too big.
+                    return 0;
+                }
+                self.seen[index] = self.generation;
+                self.stack.push((nx, ny));
+            }
+        }
+        let (width, height) = (max.0 + 1 - min.0, max.1 + 1 - min.1);
+        let any_fits = TOTEMS.iter().filter(|totem| bag.contains(totem)).any(|totem| {
+            ShapeVariant::get_rotations(totem).iter().any(|variant| variant.width <= width && variant.height <= height)
+        });
+        if area < 4 || !any_fits { area } else { area % 4 }
+    }
+
+    fn neighbors(&self, x: usize, y: usize) -> impl Iterator<Item = Point> {
+        let (width, height) = (self.width, self.height);
+        let left = (x > 0).then(|| (x - 1, y));
+        let right = (x + 1 < width).then(|| (x + 1, y));
+        let down = (y > 0).then(|| (x, y - 1));
+        let up = (y + 1 < height).then(|| (x, y + 1));
+        IntoIterator::into_iter([left, right, down, up]).flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_interface::Totem;
+
+    #[test]
+    fn detects_dead_pockets() {
+        // .OO.
+        // .OO.
+        let (width, height) = (4, 2);
+        let placed = [(1, 0), (2, 0), (1, 1), (2, 1)];
+        let is_set = |x: usize, _y: usize| x == 1 || x == 2;
+        let mut checker = RegionChecker::new(width, height, width * height);
+        let bag = TotemBag::from_iter([Totem::I]);
+        assert!(checker.is_doomed(&placed, &bag, 0, is_set));
+        assert!(checker.is_doomed(&placed, &bag, 3, is_set));
+        assert!(!checker.is_doomed(&placed, &bag, 4, is_set));
+
+        // .....
+        // .....
+        // .....
+        // .IIII  <- just placed.
+        let (width, height) = (5, 4);
+        let placed = [(1, 0), (2, 0), (3, 0), (4, 0)];
+        let is_set = |x: usize, y: usize| y == 0 && x > 0;
+        let mut checker = RegionChecker::new(width, height, width * height);
+        let no_i = TotemBag::from_iter([Totem::O, Totem::O, Totem::T, Totem::T]);
+        let i = TotemBag::from_iter([Totem::I, Totem::O, Totem::O, Totem::T]);
+        // The column on the left joins the open region above, so nothing is wasted.
+        assert!(!checker.is_doomed(&placed, &no_i, 0, is_set));
+        // Even when giving up on big regions, reached from many cells of the totem.
+        let mut small_checker = RegionChecker::new(width, height, 4);
+        assert!(!small_checker.is_doomed(&placed, &no_i, 0, is_set));
+        // .X...
+        // .X...
+        // .X...
+        // .IIII  <- seals a 1-wide column on the left (4 cells), and a 3x3 region (1 cell wasted).
+        let is_set = |x: usize, y: usize| (y == 0 && x > 0) || (x == 1 && y > 0);
+        assert!(checker.is_doomed(&placed, &no_i, 4, is_set));
+        assert!(!checker.is_doomed(&placed, &no_i, 5, is_set));
+        assert!(checker.is_doomed(&placed, &i, 0, is_set));
+        assert!(!checker.is_doomed(&placed, &i, 1, is_set));
+    }
+}
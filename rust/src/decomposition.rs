@@ -0,0 +1,394 @@
+// Decomposes a totem bag into a multiset of inventory rectangle costs, as an alternative to the
+// depth-first search of 'subset_sum', which can give up on unlucky rectangle orderings.
+//
+// Three tools are combined:
+//   - a lattice check: every decomposition is an integer combination of the rectangle costs, so a
+//     bag outside the lattice they generate (e.g. one with an odd number of 'T's) is immediately
+//     rejected;
+//   - an exact dynamic programming table of which small bags (up to 'max_small_total' totems) can
+//     be decomposed, which fully guides the end of a decomposition without any backtracking;
+//   - a randomized descent for the rest of the bag, that remembers the remainders that led to
+//     deadends. The randomness is biased to get decompositions close to a target number of
+//     rectangles, or that use preferred rectangle sizes.
+
+use crate::{
+    game_interface::{TotemBag, TOTEMS, TOTEM_COUNT},
+    rect_inventory::{RectangleInventory, RectangleMetadata},
+    subset_sum::{hash_bag, TotemBagHash},
+};
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+
+// How many times in a row 'Decompositions' can sample an already returned decomposition before
+// assuming that there are no others.
+const MAX_REPEATS: usize = 10;
+
+// Integer lattice spanned by a set of totem bags, kept in (upper triangular) Hermite normal form.
+#[derive(Clone)]
+struct CostLattice {
+    // Basis row whose first non-zero entry (positive) is at the given column, if any.
+    rows: [Option<[i64; TOTEM_COUNT]>; TOTEM_COUNT],
+}
+
+// Returns (g, s, t) such that g = gcd(a, b) = s*a + t*b.
+fn extended_gcd(a: i64, b: i64) -> (i64, i64, i64) {
+    if b == 0 {
+        if a < 0 { (-a, -1, 0) } else { (a, 1, 0) }
+    } else {
+        let (g, s, t) = extended_gcd(b, a % b);
+        (g, t, s - (a / b) * t)
+    }
+}
+
+fn combine(a: &[i64; TOTEM_COUNT], ka: i64, b: &[i64; TOTEM_COUNT], kb: i64) -> [i64; TOTEM_COUNT] {
+    let mut out = [0; TOTEM_COUNT];
+    for i in 0..TOTEM_COUNT {
+        out[i] = ka * a[i] + kb * b[i];
+    }
+    out
+}
+
+impl CostLattice {
+    fn new() -> Self {
+        CostLattice { rows: [None; TOTEM_COUNT] }
+    }
+
+    fn insert(&mut self, bag: &TotemBag) {
+        let mut v = [0i64; TOTEM_COUNT];
+        for (i, totem) in TOTEMS.iter().enumerate() {
+            v[i] = bag[totem] as i64;
+        }
+        for col in 0..TOTEM_COUNT {
+            if v[col] == 0 {
+                continue;
+            }
+            match self.rows[col] {
+                None => {
+                    if v[col] < 0 {
+                        v = combine(&v, -1, &v, 0);
+                    }
+                    self.rows[col] = Some(v);
+                    break;
+                }
+                Some(row) => {
+                    // Replace (row, v) by a unimodular combination where v has a 0 at 'col'.
+                    let (g, s, t) = extended_gcd(row[col], v[col]);
+                    let new_row = combine(&row, s, &v, t);
+                    v = combine(&row, v[col] / g, &v, -row[col] / g);
+                    self.rows[col] = Some(new_row);
+                }
+            }
+        }
+        self.reduce();
+    }
+
+    // Keep entries small by reducing each row by the rows of the following columns.
+    fn reduce(&mut self) {
+        for i in 0..TOTEM_COUNT {
+            for j in (i + 1)..TOTEM_COUNT {
+                if let (Some(row), Some(pivot_row)) = (self.rows[i], self.rows[j]) {
+                    let k = row[j].div_euclid(pivot_row[j]);
+                    if k != 0 {
+                        self.rows[i] = Some(combine(&row, 1, &pivot_row, -k));
+                    }
+                }
+            }
+        }
+    }
+
+    fn contains(&self, bag: &TotemBag) -> bool {
+        let mut v = [0i64; TOTEM_COUNT];
+        for (i, totem) in TOTEMS.iter().enumerate() {
+            v[i] = bag[totem] as i64;
+        }
+        for col in 0..TOTEM_COUNT {
+            if v[col] == 0 {
+                continue;
+            }
+            match self.rows[col] {
+                None => return false,
+                Some(row) => {
+                    if v[col] % row[col] != 0 {
+                        return false;
+                    }
+                    v = combine(&v, 1, &row, -(v[col] / row[col]));
+                }
+            }
+        }
+        true
+    }
+}
+
+// Preferences over the decompositions we want to sample.
+#[derive(Clone, Default)]
+pub struct DecompositionBias {
+    // Favor rectangles whose totem count gets us close to this many rectangles in total.
+    pub target_rectangles: Option<usize>,
+    // Favor rectangles with these dimensions (in any orientation).
+    pub preferred_dims: Vec<(usize, usize)>,
+}
+
+impl DecompositionBias {
+    fn weight(&self, rect: &RectangleMetadata, cost_total: usize, bag_total: usize) -> f64 {
+        let mut weight = 1f64;
+        if let Some(target) = self.target_rectangles {
+            let target_size = bag_total as f64 / target.max(1) as f64;
+            weight /= 1f64 + (cost_total as f64 - target_size).abs();
+        }
+        let (w, h) = (rect.dims.width, rect.dims.height);
+        if self.preferred_dims.iter().any(|&d| d == (w, h) || d == (h, w)) {
+            weight *= 4f64;
+        }
+        weight
+    }
+}
+
+#[derive(Clone)]
+pub struct BagDecomposer {
+    rectangles: Vec<RectangleMetadata>,
+    // Distinct rectangle costs, with the rectangles (indices in 'rectangles') that have this cost.
+    costs: Vec<(TotemBag, Vec<usize>)>,
+    lattice: CostLattice,
+    // Every bag with at most 'max_small_total' totems that can be decomposed.
+    small_decomposable: HashSet<TotemBagHash>,
+    max_small_total: usize,
+}
+
+impl BagDecomposer {
+    pub fn new(inventory: &RectangleInventory, max_small_total: usize) -> Self {
+        let rectangles = inventory.metadata.clone();
+        let mut cost_indices: HashMap<TotemBagHash, usize> = HashMap::new();
+        let mut costs: Vec<(TotemBag, Vec<usize>)> = Vec::new();
+        let mut lattice = CostLattice::new();
+        for (i, rect) in rectangles.iter().enumerate() {
+            let cost = inventory.get_cost(rect);
+            let index = *cost_indices.entry(hash_bag(cost)).or_insert_with(|| {
+                lattice.insert(cost);
+                costs.push((cost.clone(), Vec::new()));
+                costs.len() - 1
+            });
+            costs[index].1.push(i);
+        }
+        costs.sort_by_key(|(cost, _)| cost.total());
+        let small_decomposable = small_decomposable_bags(&costs, max_small_total);
+        BagDecomposer { rectangles, costs, lattice, small_decomposable, max_small_total }
+    }
+
+    // Whether a remainder could still lead to a decomposition. Exact for small bags, necessary
+    // (but not sufficient) condition for bigger ones.
+    fn is_viable(&self, bag: &TotemBag) -> bool {
+        if bag.total() <= self.max_small_total {
+            self.small_decomposable.contains(&hash_bag(bag))
+        } else {
+            self.lattice.contains(bag)
+        }
+    }
+
+    // Fast filter for bags that can't be decomposed. Not a decision procedure: exact for small bags,
+    // but bigger ones are only checked against the lattice, so a bag that isn't ruled out may still
+    // have no decomposition (and sampling then fails).
+    pub fn is_ruled_out(&self, bag: &TotemBag) -> bool {
+        !self.is_viable(bag)
+    }
+
+    // Sample a random decomposition of 'bag', if we can find one within 'max_restarts' deadends.
+    // 'deadends' remembers remainders that can't be decomposed, and can be shared across calls on
+    // the same bag.
+    fn sample<R: Rng>(&self, bag: &TotemBag, bias: &DecompositionBias, rng: &mut R, max_restarts: usize,
+                      deadends: &mut HashSet<TotemBagHash>) -> Option<Vec<RectangleMetadata>> {
+        if !self.is_viable(bag) {
+            return None;
+        }
+        let bag_total = bag.total();
+        let mut candidates = Vec::new();
+        let mut weights = Vec::new();
+        for _ in 0..max_restarts {
+            let mut remaining = bag.clone();
+            let mut chosen = Vec::new();
+            loop {
+                if remaining.is_empty() {
+                    return Some(chosen);
+                }
+                candidates.clear();
+                weights.clear();
+                let remaining_total = remaining.total();
+                for (cost_index, (cost, indices)) in self.costs.iter().enumerate() {
+                    let cost_total = cost.total();
+                    if cost_total > remaining_total {
+                        break;  // sorted by total
+                    }
+                    if !remaining.can_afford(cost) {
+                        continue;
+                    }
+                    for &i in indices {
+                        candidates.push((cost_index, i));
+                        weights.push(bias.weight(&self.rectangles[i], cost_total, bag_total));
+                    }
+                }
+                // Checking if the rest is viable is much more expensive than affordability, so only
+                // do it for the picked candidate, and pick again if it leads to a deadend.
+                let mut picked = None;
+                while let Some(candidate) = pick_weighted(&weights, rng) {
+                    let (cost_index, _) = candidates[candidate];
+                    let mut rest = remaining.clone();
+                    rest.subtract(&self.costs[cost_index].0);
+                    if self.is_viable(&rest) && !deadends.contains(&hash_bag(&rest)) {
+                        picked = Some(candidates[candidate]);
+                        break;
+                    }
+                    // All rectangles with that cost lead to the same rest.
+                    for (weight, (other_cost_index, _)) in weights.iter_mut().zip(candidates.iter()) {
+                        if *other_cost_index == cost_index {
+                            *weight = 0f64;
+                        }
+                    }
+                }
+                let (cost_index, i) = match picked {
+                    Some(candidate) => candidate,
+                    None => {
+                        deadends.insert(hash_bag(&remaining));
+                        break;  // restart, knowing about this deadend
+                    }
+                };
+                remaining.subtract(&self.costs[cost_index].0);
+                chosen.push(self.rectangles[i]);
+            }
+        }
+        None
+    }
+
+    // Find one decomposition of 'bag', if any, giving up after 'max_restarts' deadends.
+    pub fn decompose<R: Rng>(&self, bag: &TotemBag, bias: &DecompositionBias, rng: &mut R,
+                             max_restarts: usize) -> Option<Vec<RectangleMetadata>> {
+        self.sample(bag, bias, rng, max_restarts, &mut HashSet::new())
+    }
+
+    // Iterator over distinct random decompositions of 'bag'.
+    pub fn decompositions<'a, R: Rng>(&'a self, bag: &TotemBag, bias: &'a DecompositionBias, rng: &'a mut R,
+                                      max_restarts: usize) -> Decompositions<'a, R> {
+        Decompositions {
+            decomposer: self,
+            bag: bag.clone(),
+            bias,
+            rng,
+            max_restarts,
+            deadends: HashSet::new(),
+            seen: HashSet::new(),
+        }
+    }
+}
+
+// Picks an index at random, proportionally to the weights. None if all weights are 0.
+fn pick_weighted<R: Rng>(weights: &[f64], rng: &mut R) -> Option<usize> {
+    let total: f64 = weights.iter().sum();
+    if total <= 0f64 {
+        return None;
+    }
+    let mut target = rng.gen::<f64>() * total;
+    for (i, weight) in weights.iter().enumerate() {
+        if *weight > 0f64 && target < *weight {
+            return Some(i);
+        }
+        target -= weight;
+    }
+    weights.iter().rposition(|w| *w > 0f64)  // rounding errors
+}
+
+// Exact table of all bags with at most 'max_total' totems that can be decomposed into 'costs'.
+fn small_decomposable_bags(costs: &[(TotemBag, Vec<usize>)], max_total: usize) -> HashSet<TotemBagHash> {
+    // Bags grouped by total, built up by adding one totem at a time.
+    let mut decomposable = HashSet::new();
+    decomposable.insert(hash_bag(&TotemBag::new()));
+    let mut layer = vec![TotemBag::new()];
+    for _ in 0..max_total {
+        let mut next_layer: Vec<TotemBag> = Vec::new();
+        for bag in &layer {
+            // Only add totems in non-decreasing order to generate each bag once.
+            let last = TOTEMS.iter().rposition(|t| bag[t] > 0).unwrap_or(0);
+            for totem in &TOTEMS[last..] {
+                let mut next = bag.clone();
+                next[totem] += 1;
+                next_layer.push(next);
+            }
+        }
+        for bag in &next_layer {
+            let is_decomposable = costs.iter().any(|(cost, _)| {
+                if !bag.can_afford(cost) {
+                    return false;
+                }
+                let mut rest = bag.clone();
+                rest.subtract(cost);
+                decomposable.contains(&hash_bag(&rest))
+            });
+            if is_decomposable {
+                decomposable.insert(hash_bag(bag));
+            }
+        }
+        layer = next_layer;
+    }
+    decomposable
+}
+
+pub struct Decompositions<'a, R: Rng> {
+    decomposer: &'a BagDecomposer,
+    bag: TotemBag,
+    bias: &'a DecompositionBias,
+    rng: &'a mut R,
+    max_restarts: usize,
+    deadends: HashSet<TotemBagHash>,
+    // Decompositions already returned, as sorted rectangle indices.
+    seen: HashSet<Vec<usize>>,
+}
+
+impl<R: Rng> Iterator for Decompositions<'_, R> {
+    type Item = Vec<RectangleMetadata>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Repeats are possible, allow a few before giving up.
+        for _ in 0..MAX_REPEATS {
+            let rectangles = self.decomposer.sample(&self.bag, self.bias, self.rng, self.max_restarts,
+                                                    &mut self.deadends)?;
+            let mut key: Vec<usize> = rectangles.iter().map(|r| r.index).collect();
+            key.sort_unstable();
+            if self.seen.insert(key) {
+                return Some(rectangles);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn odd_t_is_not_decomposable() {
+        let inventory = RectangleInventory::from_scratch(/*max_area=*/20, /*max_tilings=*/1);
+        let decomposer = BagDecomposer::new(&inventory, /*max_small_total=*/5);
+        //                  I, J, L, O, S, T, Z
+        assert!(decomposer.is_ruled_out(&TotemBag([4, 6, 0, 2, 0, 1, 0])));
+        assert!(decomposer.is_ruled_out(&TotemBag([0, 0, 0, 0, 0, 3, 0])));
+        assert!(!decomposer.is_ruled_out(&TotemBag([4, 6, 0, 2, 0, 0, 0])));
+    }
+
+    #[test]
+    fn decompositions_sum_to_bag() {
+        let inventory = RectangleInventory::from_scratch(/*max_area=*/20, /*max_tilings=*/1);
+        let decomposer = BagDecomposer::new(&inventory, /*max_small_total=*/5);
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(1);
+        //                  I, J, L, O, S, T, Z
+        let bag = TotemBag([9, 6, 5, 4, 2, 4, 2]);
+        let bias = DecompositionBias { target_rectangles: Some(8), ..Default::default() };
+        let decompositions: Vec<_> = decomposer.decompositions(&bag, &bias, &mut rng, 100).take(10).collect();
+        assert_eq!(decompositions.len(), 10);
+        for rectangles in decompositions {
+            let mut total = TotemBag::new();
+            for rect in &rectangles {
+                total.add(inventory.get_cost(rect));
+            }
+            assert_eq!(total.0, bag.0);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+// Predicts how hard a bag is to pack in given dimensions: the probability that the solver packs it,
+// and how long an attempt takes. Some bags (many 'I's, lots of 'S'/'Z') are far harder to pack than
+// others of the same level, so the hybrid solver uses this to skip dimensions that are unlikely to
+// work, and go straight to the next best ones.
+//
+// There is one model per dimensions, calibrated offline from 'evaluate --output json' reports (see
+// 'bin/calibrate_difficulty.rs'). Dimensions without a model have no prediction.
+// Features are, for each shape, how far its count is from what a uniform draw would give, in
+// standard deviations. The success probability is a logistic regression on them, and the attempt
+// time a linear regression of the log of the seconds (so its prediction is a geometric mean).
+// Perfect packings with an odd number of 'T' shapes are known to be impossible (see the README), so
+// they are left out of the calibration, and always predicted to fail.
+
+use crate::game_interface::{Totem, TotemBag, TOTEM_COUNT};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+};
+
+// Bias, then one feature per shape.
+const NUM_FEATURES: usize = 1 + TOTEM_COUNT;
+// L2 regularization of the weights (not the bias), so that rare shapes don't get extreme weights.
+const L2_PENALTY: f64 = 0.01;
+const LEARNING_RATE: f64 = 0.5;
+const ITERATIONS: usize = 2000;
+// Floor on attempt times, to avoid taking the log of 0.
+const MIN_SECONDS: f64 = 1e-6;
+
+fn features(bag: &TotemBag) -> [f64; NUM_FEATURES] {
+    let num_totems = bag.total() as f64;
+    let p = 1f64 / TOTEM_COUNT as f64;
+    let std_dev = (num_totems * p * (1f64 - p)).sqrt().max(1e-9);
+    let mut features = [1f64; NUM_FEATURES];
+    for (feature, count) in features[1..].iter_mut().zip(bag.0.iter()) {
+        *feature = (*count as f64 - num_totems * p) / std_dev;
+    }
+    features
+}
+
+fn dot(weights: &[f64; NUM_FEATURES], features: &[f64; NUM_FEATURES]) -> f64 {
+    weights.iter().zip(features).map(|(w, f)| w * f).sum()
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1f64 / (1f64 + (-x).exp())
+}
+
+// Minimizes the mean loss of 'predict' against 'targets' by gradient descent. Both the logistic
+// and the squared losses have a gradient of (prediction - target) * features.
+fn fit(samples: &[([f64; NUM_FEATURES], f64)], initial_bias: f64,
+       predict: impl Fn(f64) -> f64) -> [f64; NUM_FEATURES] {
+    let mut weights = [0f64; NUM_FEATURES];
+    weights[0] = initial_bias;
+    let n = samples.len() as f64;
+    for _ in 0..ITERATIONS {
+        let mut gradient = [0f64; NUM_FEATURES];
+        for (features, target) in samples {
+            let error = predict(dot(&weights, features)) - target;
+            for (g, f) in gradient.iter_mut().zip(features) {
+                *g += error * f / n;
+            }
+        }
+        for i in 0..NUM_FEATURES {
+            let penalty = if i == 0 { 0f64 } else { L2_PENALTY * weights[i] };
+            weights[i] -= LEARNING_RATE * (gradient[i] + penalty);
+        }
+    }
+    weights
+}
+
+// Result of an attempt at packing a bag, to calibrate on.
+pub struct Observation {
+    pub bag: TotemBag,
+    pub success: bool,
+    pub seconds: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Prediction {
+    pub success_probability: f64,
+    pub expected_seconds: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct DimsModel {
+    success: [f64; NUM_FEATURES],
+    log_seconds: [f64; NUM_FEATURES],
+    // Number of observations it was calibrated on.
+    samples: usize,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DifficultyModel {
+    // Keyed by "WIDTHxHEIGHT".
+    dims: BTreeMap<String, DimsModel>,
+}
+
+fn is_impossible(width: usize, height: usize, bag: &TotemBag) -> bool {
+    bag.total() * 4 == width * height && bag[Totem::T] % 2 == 1
+}
+
+fn dims_key(width: usize, height: usize) -> String {
+    format!("{}x{}", width, height)
+}
+
+impl DifficultyModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        serde_json::from_reader(reader).map_err(io::Error::from)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self).map_err(io::Error::from)
+    }
+
+    // (Re)calibrates the model of the given dimensions. Does nothing without observations.
+    pub fn calibrate(&mut self, width: usize, height: usize, observations: &[Observation]) {
+        let observations: Vec<&Observation> = observations.iter()
+            .filter(|o| !is_impossible(width, height, &o.bag))
+            .collect();
+        if observations.is_empty() {
+            return;
+        }
+        let n = observations.len() as f64;
+        // Start from the base rates, to converge faster. Smoothed to stay finite.
+        let successes = observations.iter().filter(|o| o.success).count() as f64;
+        let base_rate = (successes + 0.5) / (n + 1f64);
+        let successes: Vec<_> = observations.iter()
+            .map(|o| (features(&o.bag), if o.success { 1f64 } else { 0f64 }))
+            .collect();
+        let log_seconds: Vec<_> = observations.iter()
+            .map(|o| (features(&o.bag), o.seconds.max(MIN_SECONDS).ln()))
+            .collect();
+        let mean_log_seconds = log_seconds.iter().map(|(_, t)| t).sum::<f64>() / n;
+        let model = DimsModel {
+            success: fit(&successes, (base_rate / (1f64 - base_rate)).ln(), sigmoid),
+            log_seconds: fit(&log_seconds, mean_log_seconds, |x| x),
+            samples: observations.len(),
+        };
+        self.dims.insert(dims_key(width, height), model);
+    }
+
+    // None if there is no model for these dimensions.
+    pub fn predict(&self, width: usize, height: usize, bag: &TotemBag) -> Option<Prediction> {
+        let model = self.dims.get(&dims_key(width, height))?;
+        if is_impossible(width, height, bag) {
+            return Some(Prediction { success_probability: 0f64, expected_seconds: 0f64 });
+        }
+        let features = features(bag);
+        Some(Prediction {
+            success_probability: sigmoid(dot(&model.success, &features)),
+            expected_seconds: dot(&model.log_seconds, &features).exp(),
+        })
+    }
+
+    // Dimensions that have a model, as (width, height, number of observations).
+    pub fn calibrated_dims(&self) -> Vec<(usize, usize, usize)> {
+        self.dims.iter().filter_map(|(key, model)| {
+            let (w, h) = key.split_once('x')?;
+            Some((w.parse().ok()?, h.parse().ok()?, model.samples))
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instances::{seeded_rng, InstanceGenerator};
+
+    #[test]
+    fn learns_that_many_i_is_hard() {
+        // Synthetic solver that fails when there are more 'I's than average, and is slower then.
+        let generator = InstanceGenerator::new();
+        let mut rng = seeded_rng(1, 0);
+        let observations: Vec<Observation> = (0..300).map(|_| {
+            let bag = generator.bag(64, &mut rng);
+            let hard = bag[Totem::I] > 64 / 7;
+            Observation { bag, success: !hard, seconds: if hard { 0.5 } else { 0.05 } }
+        }).collect();
+        let mut model = DifficultyModel::new();
+        model.calibrate(17, 16, &observations);
+
+        let easy = TotemBag([6, 10, 10, 10, 10, 8, 10]);
+        let hard = TotemBag([16, 8, 8, 8, 8, 8, 8]);
+        let (easy, hard) = (model.predict(17, 16, &easy).unwrap(), model.predict(17, 16, &hard).unwrap());
+        assert!(easy.success_probability > 0.8, "{:?}", easy);
+        assert!(hard.success_probability < 0.2, "{:?}", hard);
+        assert!(hard.expected_seconds > easy.expected_seconds);
+        assert_eq!(model.predict(8, 8, &TotemBag([16, 0, 0, 0, 0, 0, 0])), None);
+        assert_eq!(model.calibrated_dims(), vec![(17, 16, 300)]);
+    }
+}
@@ -1,12 +1,22 @@
 // Solver that tries to exhaustively search for a fit.
 // The running time grows exponentially with the number of totems,
 // so this should only be used for <= 8 totems.
+// Placements that seal off regions that can't be filled are backtracked right away (see
+// 'dead_regions').
+// A search can be given a deadline ('try_solve_until', used by the hybrid solver), past which it
+// gives up.
 use crate::{
+    dead_regions::RegionChecker,
     game_interface::{Point, Totem, TotemAnswer, TotemBag, TOTEMS},
     shape_info::ShapeVariant,
     solver::Solver,
+    trace::{NoopObserver, SolverEvent, SolverObserver},
 };
-use std::cmp;
+use std::{cmp, time::Instant};
+use tracing::debug;
+
+// How often a search with a deadline checks the time.
+const NODES_PER_TIME_CHECK: usize = 1024;
 
 struct ShapeAssigment {
     shape: Totem,
@@ -25,16 +35,37 @@ struct Board {
     max_y: usize,
     grid: Vec<Vec<bool>>,
     assigments: Vec<ShapeAssigment>,
+    // Cells that can be left empty.
+    holes: usize,
+    regions: RegionChecker,
+    deadline: Option<Instant>,
+    nodes: usize,
+    // Set once past the deadline, to unwind the search.
+    out_of_time: bool,
 }
 
 impl Board {
-    fn new(width: usize, height: usize, num_totems: usize) -> Self {
+    fn new(width: usize, height: usize, num_totems: usize, deadline: Option<Instant>) -> Self {
         let grid = vec![vec![false; width]; height];
         Board {
-            width: width, height: height, grid: grid,
+            width, height, grid,
             max_x: 0, max_y: 0,
             assigments: Vec::with_capacity(num_totems),
+            holes: (width * height).saturating_sub(num_totems * 4),
+            regions: RegionChecker::new(width, height, /*max_region_area=*/width * height),
+            deadline,
+            nodes: 0,
+            out_of_time: false,
+        }
+    }
+
+    // Counts a search node, and whether the search is past its deadline.
+    fn is_out_of_time(&mut self) -> bool {
+        self.nodes += 1;
+        if !self.out_of_time && self.nodes % NODES_PER_TIME_CHECK == 0 {
+            self.out_of_time = self.deadline.is_some_and(|deadline| Instant::now() > deadline);
         }
+        self.out_of_time
     }
 
     // Note: assumes that the shape fits on the board. Does not check.
@@ -59,7 +90,7 @@ impl Board {
                 return true;
             }
         }
-        return false
+        false
     }
 
     fn mark(&mut self, shape: &ShapeVariant, left_x: usize, bottom_y: usize) {
@@ -74,7 +105,7 @@ impl Board {
         }
         let shape = shape.offset_by(left_x, bottom_y);
         self.assigments.push(ShapeAssigment { shape: shape.shape, coords: shape.coords,
-                                              prev_max_x: prev_max_x, prev_max_y: prev_max_y });
+                                              prev_max_x, prev_max_y });
     }
 
     fn unmark(&mut self, shape: &ShapeVariant, left_x: usize, bottom_y: usize) {
@@ -88,6 +119,13 @@ impl Board {
         }
     }
 
+    // Whether the last totem placed sealed off regions that can't be filled by 'bag'.
+    fn is_doomed(&mut self, bag: &TotemBag) -> bool {
+        let grid = &self.grid;
+        let placed = &self.assigments.last().unwrap().coords;
+        self.regions.is_doomed(placed, bag, self.holes, |x, y| grid[y][x])
+    }
+
     fn assigments_to_answer(&self) -> Vec<TotemAnswer> {
         self.assigments.iter().map(|a| { TotemAnswer::new(a.shape, a.coords) }).collect()
     }
@@ -96,15 +134,19 @@ impl Board {
 
 // Tries to place each shape rotation in each 'x' position, recursively.
 // If a given placement failed to solve, 'board' and 'bag' will go back to their input values.
-fn recursive_solve(board: &mut Board, bag: &mut TotemBag) -> Option<Vec<TotemAnswer>> {
+fn recursive_solve<O: SolverObserver>(board: &mut Board, bag: &mut TotemBag,
+                                      observer: &mut O) -> Option<Vec<TotemAnswer>> {
+    if board.is_out_of_time() {
+        return None;
+    }
     let mut shapes_left = 0;
     for totem in TOTEMS.iter() {
         let n_totems = bag[totem];
         shapes_left += n_totems;
         if n_totems == 0 { continue }
-        for variant in ShapeVariant::get_rotations(&totem) {
+        for variant in ShapeVariant::get_rotations(totem) {
             let mut upper_dx = board.width as i32 + 1 - variant.width as i32;
-            if board.assigments.len() == 0 {
+            if board.assigments.is_empty() {
                 // For first shape, force (0, 0) to be set.
                 upper_dx = cmp::min(upper_dx, 1);
                 if !variant.coords.iter().any(|(x, y)| *x == 0 && *y == 0) {
@@ -112,14 +154,24 @@ fn recursive_solve(board: &mut Board, bag: &mut TotemBag) -> Option<Vec<TotemAns
                 }
             }
             for dx in 0..upper_dx {
+                if board.out_of_time {
+                    return None;
+                }
                 let mut dy = 0;
-                if board.move_first_fit_above(dx as usize, &variant, &mut dy) {
-                    board.mark(&variant, dx as usize, dy);
+                if board.move_first_fit_above(dx as usize, variant, &mut dy) {
+                    board.mark(variant, dx as usize, dy);
+                    let placed = board.assigments.last().unwrap();
+                    observer.on_event(SolverEvent::Place { totem: TotemAnswer::new(placed.shape, placed.coords) });
                     bag[totem] -= 1;
-                    if let Some(sln) = recursive_solve(board, bag) {
+                    if board.is_doomed(bag) {
+                        board.unmark(variant, dx as usize, dy);
+                        observer.on_event(SolverEvent::Backtrack);
+                        bag[totem] += 1;
+                    } else if let Some(sln) = recursive_solve(board, bag, observer) {
                         return Some(sln);
                     } else {
-                        board.unmark(&variant, dx as usize, dy);
+                        board.unmark(variant, dx as usize, dy);
+                        observer.on_event(SolverEvent::Backtrack);
                         bag[totem] += 1;
                     }
                 }
@@ -136,15 +188,37 @@ fn recursive_solve(board: &mut Board, bag: &mut TotemBag) -> Option<Vec<TotemAns
 pub struct ExhaustiveSolver {
 }
 
+impl ExhaustiveSolver {
+    // Like 'try_solve', but gives up past 'deadline'.
+    pub fn try_solve_until(&self, width: usize, height: usize, bag: &TotemBag,
+                           deadline: Instant) -> Option<Vec<TotemAnswer>> {
+        self.search(width, height, bag, Some(deadline), &mut NoopObserver)
+    }
+
+    fn search<O: SolverObserver>(&self, width: usize, height: usize, bag: &TotemBag, deadline: Option<Instant>,
+                                 observer: &mut O) -> Option<Vec<TotemAnswer>> {
+        let num_totems = bag.total();
+        let mut board = Board::new(width, height, num_totems, deadline);
+        let mut bag = bag.clone();
+        let sln = recursive_solve(&mut board, &mut bag, observer);
+        if board.out_of_time {
+            debug!(nodes = board.nodes, "Out of time for the exhaustive search.");
+        }
+        sln
+    }
+}
+
 impl Solver for ExhaustiveSolver {
     fn new() -> Self {
         Self { }
     }
 
     fn try_solve(&self, width: usize, height: usize, bag: &TotemBag) -> Option<Vec<TotemAnswer>> {
-        let num_totems = bag.total();
-        let mut board = Board::new(width, height, num_totems);
-        let mut bag = bag.clone();
-        recursive_solve(&mut board, &mut bag)
+        self.try_solve_observed(width, height, bag, &mut NoopObserver)
+    }
+
+    fn try_solve_observed<O: SolverObserver>(&self, width: usize, height: usize, bag: &TotemBag,
+                                             observer: &mut O) -> Option<Vec<TotemAnswer>> {
+        self.search(width, height, bag, None, observer)
     }
 }
\ No newline at end of file
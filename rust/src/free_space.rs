@@ -0,0 +1,456 @@
+// Common interface for data structures that keep track of the free area of a bin while we pack
+// rectangles in it, so that packers can swap between them:
+//   - MaxRects ('max_rects.rs'): maximal, possibly overlapping, free rectangles. Most precise, but
+//     the most expensive to update;
+//   - Skyline: only the top edge ("skyline") of the placed rectangles, so nothing can be placed
+//     under an overhang. Very cheap, and a natural fit for bottom-up perfect packing where no
+//     overhang should ever be created;
+//   - Guillotine: disjoint free rectangles, split in two after each placement.
+//
+// All of them are described in:
+// http://pds25.egloos.com/pds/201504/21/98/RectangleBinPack.pdf
+// which also describes the placement heuristics below.
+
+use crate::game_interface::Point;
+use std::cmp;
+
+// Rule used to pick where to place a new rectangle.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlacementHeuristic {
+    // Lowest position, then leftmost.
+    BottomLeft,
+    // Free rectangle with the smallest leftover on its shortest side.
+    BestShortSideFit,
+    // Free rectangle with the smallest leftover on its longest side.
+    BestLongSideFit,
+    // Free rectangle with the smallest leftover area.
+    BestAreaFit,
+    // Position that touches the most border and placed rectangle edges.
+    ContactPoint,
+}
+
+pub const PLACEMENT_HEURISTICS: [PlacementHeuristic; 5] = [
+    PlacementHeuristic::BottomLeft,
+    PlacementHeuristic::BestShortSideFit,
+    PlacementHeuristic::BestLongSideFit,
+    PlacementHeuristic::BestAreaFit,
+    PlacementHeuristic::ContactPoint,
+];
+
+pub trait FreeSpace {
+    fn new(width: usize, height: usize) -> Self where Self: Sized;
+
+    // All (x, y) bottom-left positions where a 'width' x 'height' rectangle could be placed.
+    fn candidates(&self, width: usize, height: usize) -> Vec<Point>;
+
+    // If target fits, places it and returns the (x, y) position of its bottom-left corner, picking
+    // the position that is best according to 'heuristic'.
+    fn insert(&mut self, width: usize, height: usize, heuristic: PlacementHeuristic) -> Option<Point>;
+
+    // If target fits, returns (x, y) position of the bottom-left corner where it would fit.
+    // Will pick the lowest left-aligned fit.
+    fn bottom_left_insert(&mut self, width: usize, height: usize) -> Option<Point> {
+        self.insert(width, height, PlacementHeuristic::BottomLeft)
+    }
+
+    // Undo the last placement, restoring the exact state from before it. This is much cheaper
+    // than cloning the whole structure before each placement when searching.
+    // Returns false if there was nothing to undo.
+    fn undo(&mut self) -> bool;
+
+    // Number of rectangles placed so far.
+    fn num_placed(&self) -> usize;
+}
+
+// Which 'FreeSpace' implementation to use, for packers that can be configured.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FreeSpaceKind {
+    MaxRects,
+    Skyline,
+    Guillotine,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Rect {
+    pub(crate) x: usize,
+    pub(crate) y: usize,  // NOTE: bottom
+    pub(crate) w: usize,
+    pub(crate) h: usize,
+}
+
+impl Rect {
+    pub(crate) fn new(x: usize, y: usize, w: usize, h: usize) -> Self {
+        Rect {
+            x, y, w, h
+        }
+    }
+
+    pub(crate) fn top(&self) -> usize {
+        self.y + self.h
+    }
+
+    pub(crate) fn bottom(&self) -> usize {
+        self.y
+    }
+
+    pub(crate) fn left(&self) -> usize {
+        self.x
+    }
+
+    pub(crate) fn right(&self) -> usize {
+        self.x + self.w
+    }
+
+    pub(crate) fn fits(&self, width: usize, height: usize) -> bool {
+        width <= self.w && height <= self.h
+    }
+
+    pub(crate) fn fully_contains(&self, other: &Rect) -> bool {
+        other.left() >= self.left() &&
+        other.right() <= self.right() &&
+        other.bottom() >= self.bottom() &&
+        other.top() <= self.top()
+    }
+
+    pub(crate) fn intersects(&self, other: &Rect) -> bool {
+        let no_intersect =
+            self.right() <= other.left() || self.left() >= other.right() ||
+            self.bottom() >= other.top() || self.top() <= other.bottom();
+        !no_intersect
+    }
+}
+
+// Length of the overlap between intervals [start1, end1) and [start2, end2).
+pub(crate) fn common_interval_length(start1: usize, end1: usize, start2: usize, end2: usize) -> usize {
+    let start = cmp::max(start1, start2);
+    let end = cmp::min(end1, end2);
+    end.saturating_sub(start)
+}
+
+// Total length of the edges of 'rect' that touch the borders of a 'width' x 'height' bin or
+// 'used' rectangles.
+pub(crate) fn contact_score(rect: &Rect, used: &[Rect], width: usize, height: usize) -> usize {
+    let mut score = 0;
+    if rect.left() == 0 || rect.right() == width {
+        score += rect.h;
+    }
+    if rect.bottom() == 0 || rect.top() == height {
+        score += rect.w;
+    }
+    for other in used {
+        if other.left() == rect.right() || other.right() == rect.left() {
+            score += common_interval_length(other.bottom(), other.top(), rect.bottom(), rect.top());
+        }
+        if other.bottom() == rect.top() || other.top() == rect.bottom() {
+            score += common_interval_length(other.left(), other.right(), rect.left(), rect.right());
+        }
+    }
+    score
+}
+
+// Score of placing a 'width' x 'height' rectangle in the bottom-left corner of 'free_rect',
+// assuming it fits, for structures that track free rectangles. Lower is better.
+pub(crate) fn free_rect_score(free_rect: &Rect, width: usize, height: usize, heuristic: PlacementHeuristic,
+                              used: &[Rect], bin_width: usize, bin_height: usize) -> (isize, isize) {
+    let leftover_x = (free_rect.w - width) as isize;
+    let leftover_y = (free_rect.h - height) as isize;
+    let short_side = cmp::min(leftover_x, leftover_y);
+    let long_side = cmp::max(leftover_x, leftover_y);
+    match heuristic {
+        PlacementHeuristic::BottomLeft => (free_rect.y as isize, free_rect.x as isize),
+        PlacementHeuristic::BestShortSideFit => (short_side, long_side),
+        PlacementHeuristic::BestLongSideFit => (long_side, short_side),
+        PlacementHeuristic::BestAreaFit => ((free_rect.w * free_rect.h - width * height) as isize, short_side),
+        PlacementHeuristic::ContactPoint => {
+            let placed = Rect::new(free_rect.x, free_rect.y, width, height);
+            (-(contact_score(&placed, used, bin_width, bin_height) as isize), free_rect.y as isize)
+        }
+    }
+}
+
+// Horizontal segment of the skyline.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Segment {
+    x: usize,
+    y: usize,
+    w: usize,
+}
+
+// Change made to the skyline by a placement: 'removed' segments at 'start' were replaced by
+// 'inserted' new ones.
+#[derive(Clone, Debug)]
+struct SkylineChange {
+    start: usize,
+    removed: Vec<Segment>,
+    inserted: usize,
+}
+
+// Skyline data structure: the bin is filled bottom-up, and we only keep track of the height of
+// each column range (sorted segments covering the whole width).
+#[derive(Clone)]
+pub struct Skyline {
+    width: usize,
+    height: usize,
+    segments: Vec<Segment>,
+    used: Vec<Rect>,
+    journal: Vec<SkylineChange>,
+}
+
+impl Skyline {
+    // If a 'width' x 'height' rectangle fits with its left edge at the start of the segment at
+    // 'index', returns the y where it would rest and the area that would be wasted under it.
+    fn fit_at(&self, index: usize, width: usize, height: usize) -> Option<(usize, usize)> {
+        let x = self.segments[index].x;
+        if x + width > self.width {
+            return None;
+        }
+        let mut y = 0;
+        let mut covered = 0;
+        let mut i = index;
+        while covered < width {
+            y = cmp::max(y, self.segments[i].y);
+            covered += self.segments[i].w;
+            i += 1;
+        }
+        if y + height > self.height {
+            return None;
+        }
+        let mut wasted = 0;
+        let mut covered = 0;
+        let mut i = index;
+        while covered < width {
+            let segment = &self.segments[i];
+            let w = cmp::min(segment.w, width - covered);
+            wasted += w * (y - segment.y);
+            covered += w;
+            i += 1;
+        }
+        Some((y, wasted))
+    }
+
+    fn place_at(&mut self, index: usize, y: usize, width: usize, height: usize) {
+        let x = self.segments[index].x;
+        let mut end = index;
+        let mut covered = 0;
+        while covered < width {
+            covered += self.segments[end].w;
+            end += 1;
+        }
+        let mut inserted = vec![Segment { x, y: y + height, w: width }];
+        if covered > width {  // The last covered segment sticks out on the right.
+            let last = self.segments[end - 1];
+            inserted.push(Segment { x: x + width, y: last.y, w: covered - width });
+        }
+        // Merge with the segment on the left if it ends up at the same height.
+        let mut start = index;
+        if start > 0 && self.segments[start - 1].y == y + height {
+            start -= 1;
+            inserted[0] = Segment { x: self.segments[start].x, y: y + height, w: self.segments[start].w + width };
+        }
+        // Same on the right, if we did not split a segment.
+        if inserted.len() == 1 && end < self.segments.len() && self.segments[end].y == y + height {
+            inserted[0].w += self.segments[end].w;
+            end += 1;
+        }
+        let num_inserted = inserted.len();
+        let removed = self.segments.splice(start..end, inserted).collect();
+        self.journal.push(SkylineChange { start, removed, inserted: num_inserted });
+        self.used.push(Rect::new(x, y, width, height));
+    }
+}
+
+impl FreeSpace for Skyline {
+    fn new(width: usize, height: usize) -> Self {
+        Skyline {
+            width, height,
+            segments: vec![Segment { x: 0, y: 0, w: width }],
+            used: Vec::new(),
+            journal: Vec::new(),
+        }
+    }
+
+    fn candidates(&self, width: usize, height: usize) -> Vec<Point> {
+        (0..self.segments.len())
+            .filter_map(|i| self.fit_at(i, width, height).map(|(y, _)| (self.segments[i].x, y)))
+            .collect()
+    }
+
+    // Supports BottomLeft, BestAreaFit (least wasted area under the rectangle) and ContactPoint.
+    // The other heuristics are based on free rectangles and fall back to BottomLeft.
+    fn insert(&mut self, width: usize, height: usize, heuristic: PlacementHeuristic) -> Option<Point> {
+        let mut best: Option<(usize, usize, (isize, isize))> = None;
+        for i in 0..self.segments.len() {
+            if let Some((y, wasted)) = self.fit_at(i, width, height) {
+                let x = self.segments[i].x;
+                let score = match heuristic {
+                    PlacementHeuristic::BestAreaFit => (wasted as isize, y as isize),
+                    PlacementHeuristic::ContactPoint => {
+                        let placed = Rect::new(x, y, width, height);
+                        (-(contact_score(&placed, &self.used, self.width, self.height) as isize), y as isize)
+                    }
+                    _ => (y as isize, x as isize),
+                };
+                let better_candidate = match best {
+                    None => true,
+                    Some((_, _, best_score)) => score < best_score,
+                };
+                if better_candidate {
+                    best = Some((i, y, score));
+                }
+            }
+        }
+        let (index, y, _) = best?;
+        let x = self.segments[index].x;
+        self.place_at(index, y, width, height);
+        Some((x, y))
+    }
+
+    fn undo(&mut self) -> bool {
+        let change = match self.journal.pop() {
+            Some(change) => change,
+            None => return false,
+        };
+        self.used.pop();
+        self.segments.splice(change.start..change.start + change.inserted, change.removed);
+        true
+    }
+
+    fn num_placed(&self) -> usize {
+        self.used.len()
+    }
+}
+
+// Change made to the free rectangles by a placement: the free rectangle at 'index' was
+// swap_removed, and 'pushed' rectangles were added at the end.
+#[derive(Clone, Debug)]
+struct GuillotineChange {
+    index: usize,
+    removed: Rect,
+    pushed: usize,
+}
+
+// Guillotine data structure: disjoint free rectangles. Placing a rectangle in the bottom-left of a
+// free rectangle splits the rest of it in two, along the axis with the shortest leftover.
+// Note that free rectangles are never merged back, so some placements that would fit across two
+// free rectangles can be missed.
+#[derive(Clone)]
+pub struct Guillotine {
+    width: usize,
+    height: usize,
+    free: Vec<Rect>,
+    used: Vec<Rect>,
+    journal: Vec<GuillotineChange>,
+}
+
+impl FreeSpace for Guillotine {
+    fn new(width: usize, height: usize) -> Self {
+        Guillotine {
+            width, height,
+            free: vec![Rect::new(0, 0, width, height)],
+            used: Vec::new(),
+            journal: Vec::new(),
+        }
+    }
+
+    fn candidates(&self, width: usize, height: usize) -> Vec<Point> {
+        self.free.iter().filter(|r| r.fits(width, height)).map(|r| (r.x, r.y)).collect()
+    }
+
+    fn insert(&mut self, width: usize, height: usize, heuristic: PlacementHeuristic) -> Option<Point> {
+        let mut best: Option<(usize, (isize, isize))> = None;
+        for (i, rect) in self.free.iter().enumerate() {
+            if rect.fits(width, height) {
+                let score = free_rect_score(rect, width, height, heuristic, &self.used, self.width, self.height);
+                let better_candidate = match best {
+                    None => true,
+                    Some((_, best_score)) => score < best_score,
+                };
+                if better_candidate {
+                    best = Some((i, score));
+                }
+            }
+        }
+        let (index, _) = best?;
+        let free_rect = self.free.swap_remove(index);
+        let (x, y) = (free_rect.x, free_rect.y);
+        let leftover_x = free_rect.w - width;
+        let leftover_y = free_rect.h - height;
+        // Split along the shorter leftover axis, to keep the bigger free rectangle as big as possible.
+        let (right, top) = if leftover_x < leftover_y {
+            (Rect::new(x + width, y, leftover_x, height), Rect::new(x, y + height, free_rect.w, leftover_y))
+        } else {
+            (Rect::new(x + width, y, leftover_x, free_rect.h), Rect::new(x, y + height, width, leftover_y))
+        };
+        let mut pushed = 0;
+        for split in [right, top].iter() {
+            if split.w > 0 && split.h > 0 {
+                self.free.push(split.clone());
+                pushed += 1;
+            }
+        }
+        self.journal.push(GuillotineChange { index, removed: free_rect, pushed });
+        self.used.push(Rect::new(x, y, width, height));
+        Some((x, y))
+    }
+
+    fn undo(&mut self) -> bool {
+        let change = match self.journal.pop() {
+            Some(change) => change,
+            None => return false,
+        };
+        self.used.pop();
+        for _ in 0..change.pushed {
+            self.free.pop();
+        }
+        if change.index == self.free.len() {
+            self.free.push(change.removed);
+        } else {
+            let moved = std::mem::replace(&mut self.free[change.index], change.removed);
+            self.free.push(moved);
+        }
+        true
+    }
+
+    fn num_placed(&self) -> usize {
+        self.used.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::max_rects::MaxRects;
+
+    // Packs 2x2 squares in a 4x4 bin, which all structures should do perfectly.
+    fn packs_squares<F: FreeSpace>() {
+        let mut space = F::new(4, 4);
+        let mut positions: Vec<Point> = (0..4).map(|_| space.bottom_left_insert(2, 2).unwrap()).collect();
+        positions.sort_unstable();
+        assert_eq!(positions, vec![(0, 0), (0, 2), (2, 0), (2, 2)]);
+        assert!(space.bottom_left_insert(1, 1).is_none());
+        for _ in 0..4 {
+            assert!(space.undo());
+        }
+        assert!(!space.undo());
+        assert_eq!(space.candidates(4, 4), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn all_structures_pack_squares() {
+        packs_squares::<MaxRects>();
+        packs_squares::<Skyline>();
+        packs_squares::<Guillotine>();
+    }
+
+    #[test]
+    fn skyline_rests_on_highest_segment() {
+        let mut skyline = Skyline::new(6, 6);
+        assert_eq!(skyline.bottom_left_insert(2, 3), Some((0, 0)));
+        assert_eq!(skyline.bottom_left_insert(2, 1), Some((2, 0)));
+        // Spans both previous rectangles, rests on the higher one.
+        assert_eq!(skyline.insert(3, 1, PlacementHeuristic::BottomLeft), Some((2, 1)));
+        assert_eq!(skyline.candidates(6, 1), vec![(0, 3)]);
+        assert!(skyline.undo());
+        assert_eq!(skyline.segments.len(), 3);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::shape_info::ShapeVariant;
 use serde::{Deserialize, Serialize};
-use std::{iter, ops};
+use std::{convert::TryFrom, iter, ops};
 
 pub const TOTEM_COUNT: usize = 7;
 
@@ -30,6 +30,35 @@ impl Totem {
     pub fn get_rotations(&self) -> &'static [ShapeVariant] {
         ShapeVariant::get_rotations(self)
     }
+
+    // Shape obtained when mirroring this totem (J and L swap, as do S and Z).
+    pub fn mirrored(&self) -> Totem {
+        match self {
+            Totem::J => Totem::L,
+            Totem::L => Totem::J,
+            Totem::S => Totem::Z,
+            Totem::Z => Totem::S,
+            other => *other,
+        }
+    }
+}
+
+impl TryFrom<char> for Totem {
+    type Error = String;
+
+    // Parses a shape from its letter, e.g. 'T' (case insensitive).
+    fn try_from(src: char) -> Result<Self, Self::Error> {
+        match src.to_ascii_uppercase() {
+            'I' => Ok(Totem::I),
+            'J' => Ok(Totem::J),
+            'L' => Ok(Totem::L),
+            'O' => Ok(Totem::O),
+            'S' => Ok(Totem::S),
+            'T' => Ok(Totem::T),
+            'Z' => Ok(Totem::Z),
+            _ => Err(format!("'{}' is not a totem, expected one of IJLOSTZ", src)),
+        }
+    }
 }
 
 impl From<Totem> for usize {
@@ -40,8 +69,7 @@ impl From<Totem> for usize {
 
 impl From<usize> for Totem {
     fn from(src: usize) -> Self {
-        assert!(src < TOTEM_COUNT);
-        unsafe { std::mem::transmute(src) }
+        TOTEMS[src]
     }
 }
 
@@ -107,7 +135,7 @@ pub struct TotemQuestion {
 }
 
 #[repr(transparent)]
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct TotemBag(pub [usize; TOTEM_COUNT]);
 
 impl TotemBag {
@@ -128,7 +156,7 @@ impl TotemBag {
     }
 
     pub fn total(&self) -> usize {
-        return self.0.iter().sum();
+        self.0.iter().sum()
     }
 
     pub fn is_empty(&self) -> bool {
@@ -142,7 +170,7 @@ impl TotemBag {
     pub fn expand(&self) -> impl iter::Iterator<Item = Totem> + '_ {
         TOTEMS
             .iter()
-            .flat_map(move |&t| iter::repeat(t).take(self.0[t]))
+            .flat_map(move |&t| iter::repeat_n(t, self.0[t]))
     }
 
     pub fn can_afford(&self, cost: &TotemBag) -> bool {
@@ -172,6 +200,11 @@ impl TotemBag {
     pub fn max(&self) -> Totem {
         *TOTEMS.iter().max_by_key(|&t| self.0[t]).unwrap()
     }
+
+    // Bag we get when mirroring all of its totems.
+    pub fn mirrored(&self) -> TotemBag {
+        TotemBag::from_iter(self.expand().map(|t| t.mirrored()))
+    }
 }
 
 impl ops::Index<usize> for TotemBag {
@@ -225,11 +258,23 @@ impl Question {
     pub fn get_totem_bag(&self) -> TotemBag {
         TotemBag::from_iter(self.totems.iter().map(|t| t.shape))
     }
+
+    // Parses a question from the letters of its shapes, e.g. "IJJLOSTZ". Whitespace is ignored.
+    pub fn from_shapes(shapes: &str) -> Result<Self, String> {
+        let totems = shapes.chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| Totem::try_from(c).map(|shape| TotemQuestion { shape }))
+            .collect::<Result<Vec<_>, _>>()?;
+        if totems.is_empty() {
+            return Err(String::from("no totems given"));
+        }
+        Ok(Question { totems })
+    }
 }
 
 pub type Point = (usize, usize);
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct TotemAnswer {
     pub shape: Totem,
     pub coordinates: [Point; 4],
@@ -241,7 +286,7 @@ impl TotemAnswer {
     }
 
     pub fn offset_by(&self, x: usize, y: usize) -> TotemAnswer {
-        let mut coords = self.coordinates.clone();
+        let mut coords = self.coordinates;
         for (dx, dy) in &mut coords {
             *dx += x;
             *dy += y;
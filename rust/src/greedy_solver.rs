@@ -5,18 +5,93 @@
 // Running multiple times can increase the chances of packing, so running each simulation fast is
 // important.
 
+// The score of a placement is a weighted sum of features (see 'GreedyParams'). The default weights
+// are the original heuristic: touchpoints, where borders count as touchpoints, and covering (0, 0)
+// is worth a lot. Weights can be tuned per number of totems with 'bin/tune_greedy.rs'.
+
+// An attempt is abandoned as soon as a placement seals off regions that can't be filled (see
+// 'dead_regions'), instead of placing totems until none fits.
+
+// Attempts are repeated until one packs the bag, up to a deadline ('try_solve_until', used by the
+// hybrid solver with its share of the time budget): the cost of an attempt is measured as they
+// run, and no attempt is started if it would likely end past the deadline. Without a deadline,
+// a fixed number of attempts is made (see 'set_max_attempts'), so that results don't depend on the
+// speed of the machine. Either way, attempts stop once another solver racing on the same problem
+// found a solution (see 'set_stop_flag').
+
 // In practice, this greedy algorithm seems sufficient to solve optimally most levels that don't
 // require an exact pack (although we did get a lucky 256 totems pack on the server, once!)
 
 use crate::{
+    dead_regions::RegionChecker,
     game_interface::{Totem, TotemAnswer, TotemBag, TOTEMS},
     shape_info::ShapeVariant,
-    solver::Solver,
+    solver::{Solver, StopFlag},
+    trace::{NoopObserver, SolverEvent, SolverObserver},
 };
 use rand::{
     self,
     seq::SliceRandom,
 };
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+    sync::Arc,
+    time::Instant,
+};
+use tracing::debug;
+
+// Weights of the features of a placement. The placement with the highest score is picked.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct GreedyParams {
+    // Sides of the totem touching other totems.
+    pub touchpoints: f32,
+    // Sides of the totem touching the border of the board.
+    pub border: f32,
+    // Covering (0, 0), which answers must do.
+    pub origin: f32,
+    // Empty cells right below the totem, that it covers over.
+    pub holes: f32,
+    // Rows added to the height of the packing.
+    pub height_increase: f32,
+    // Change in the number of neighboring columns whose heights differ by an odd number of rows.
+    pub column_parity: f32,
+    // Placements scoring within this of the best one are picked from at random.
+    pub ties: f32,
+}
+
+impl Default for GreedyParams {
+    fn default() -> Self {
+        GreedyParams {
+            touchpoints: 1.0,
+            border: 1.0,
+            origin: 100.0,
+            holes: 0.0,
+            height_increase: 0.0,
+            column_parity: 0.0,
+            ties: 0.0,
+        }
+    }
+}
+
+// Parameters tuned per number of totems.
+pub type TunedGreedyParams = BTreeMap<usize, GreedyParams>;
+
+pub fn load_tuned_params(path: &Path) -> io::Result<TunedGreedyParams> {
+    serde_json::from_reader(BufReader::new(File::open(path)?)).map_err(io::Error::from)
+}
+
+pub fn save_tuned_params(path: &Path, tuned: &TunedGreedyParams) -> io::Result<()> {
+    serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), tuned).map_err(io::Error::from)
+}
+
+// Regions bigger than this are not checked for dead cells. Regions the greedy seals off are usually
+// small, and checking bigger ones costs more than the attempts it cuts short.
+const MAX_DEAD_REGION_AREA: usize = 8;
 
 struct Board {
     width: usize,
@@ -24,38 +99,49 @@ struct Board {
     // For each row, a bitmask of each square that is in use.
     // (note: 0b1 would be to the very right, or x=63).
     masked_grid: Vec<u64>,
-    // For each square, the amount of squares with totems that it has as neighbors.
-    touchpoints: Vec<Vec<u32>>,
+    // For each square, the score of covering it: the amount of squares with totems that it has as
+    // neighbors and its borders (weighted), and the (0, 0) bonus.
+    cell_scores: Vec<Vec<f32>>,
+    touchpoint_weight: f32,
+    // For each x, one more than the highest y with a totem on it (0 if none).
+    column_tops: Vec<usize>,
+    // Highest of 'column_tops'.
+    top: usize,
     // Totems placed so far.
     totems: Vec<TotemAnswer>,
     // For each x, the first y that has no totem on it yet.
     // Used to speed up finding a spot where a shape could fit.
     first_unset_y_at_x: Vec<usize>,
+    // Cells that can be left empty.
+    holes: usize,
+    regions: RegionChecker,
 }
 
 impl Board {
-    fn new(width: usize, height: usize, answer_size: usize) -> Board {
+    fn new(width: usize, height: usize, answer_size: usize, params: &GreedyParams) -> Board {
         assert!(width <= 64);
-        let mut touchpoints = vec![vec![0; width]; height];
-        // Treat borders as touchpoints
-        for x in 0..width {
-            touchpoints[0][x] += 1;
-            touchpoints[height - 1][x] += 1;
-        }
-        for y in 0..height {
-            touchpoints[y][0] += 1;
-            touchpoints[y][width - 1] += 1;
+        let mut cell_scores = vec![vec![0f32; width]; height];
+        for (y, row) in cell_scores.iter_mut().enumerate() {
+            for (x, cell_score) in row.iter_mut().enumerate() {
+                let borders = (x == 0) as u32 + (x + 1 == width) as u32 + (y == 0) as u32 + (y + 1 == height) as u32;
+                *cell_score = params.border * borders as f32;
+            }
         }
-        touchpoints[0][0] += 100; // Give (0,0) a big boost to ensure we set it.
+        cell_scores[0][0] += params.origin;
         Board {
             width,
             height,
             // Because we always check 4 pre-made rows of masks for totems when checking for a fit (for speed),
             // need some padding.
             masked_grid: vec![0; height + 3],
-            touchpoints,
+            cell_scores,
+            touchpoint_weight: params.touchpoints,
+            column_tops: vec![0; width],
+            top: 0,
             totems: Vec::with_capacity(answer_size),
             first_unset_y_at_x: vec![0; width],
+            holes: (width * height).saturating_sub(answer_size * 4),
+            regions: RegionChecker::new(width, height, MAX_DEAD_REGION_AREA),
         }
     }
 
@@ -69,17 +155,19 @@ impl Board {
             let x = *x + left_x;
             let y = *y + bottom_y;
             if y > 0 {
-                self.touchpoints[y - 1][x] += 1;
+                self.cell_scores[y - 1][x] += self.touchpoint_weight;
             }
             if y + 1 < self.height {
-                self.touchpoints[y + 1][x] += 1;
+                self.cell_scores[y + 1][x] += self.touchpoint_weight;
             }
             if x > 0 {
-                self.touchpoints[y][x - 1] += 1;
+                self.cell_scores[y][x - 1] += self.touchpoint_weight;
             }
             if x + 1 < self.width {
-                self.touchpoints[y][x + 1] += 1;
+                self.cell_scores[y][x + 1] += self.touchpoint_weight;
             }
+            self.column_tops[x] = self.column_tops[x].max(y + 1);
+            self.top = self.top.max(y + 1);
             let mut unset_y = self.first_unset_y_at_x[x];
             if unset_y == y {
                 while unset_y < self.height && self.is_set(x, unset_y) {
@@ -89,14 +177,21 @@ impl Board {
             }
         }
         for dy in 0..shape.height {
-            let y = bottom_y + dy as usize;
-            let mask = shape.mask_at(left_x, dy as usize);
+            let y = bottom_y + dy;
+            let mask = shape.mask_at(left_x, dy);
             self.masked_grid[y] |= mask;
         }
         let shape = shape.offset_by(left_x, bottom_y);
         self.totems.push(TotemAnswer::new(shape.shape, shape.coords));
     }
 
+    // Whether the last totem placed sealed off regions that can't be filled by 'bag'.
+    fn is_doomed(&mut self, bag: &TotemBag) -> bool {
+        let masked_grid = &self.masked_grid;
+        let placed = &self.totems.last().unwrap().coordinates;
+        self.regions.is_doomed(placed, bag, self.holes, |x, y| masked_grid[y] & (1u64 << (63 - x)) != 0)
+    }
+
     fn fits(&self, shape: &ShapeVariant, left_x: usize, bottom_y: usize) -> bool {
         let mut fit = 0;
         for dy in 0..4 {  // constant loop size for speed, shapes are padded if needed.
@@ -105,17 +200,38 @@ impl Board {
             let board_mask = unsafe { self.masked_grid.get_unchecked(y) };  // Not great, but faster.
             fit |= shape_mask & board_mask;
         }
-        return fit == 0;
+        fit == 0
     }
 
-    fn num_touchpoints(&self, shape: &ShapeVariant, left_x: usize, bottom_y: usize) -> u32 {
-        let mut total = 0;
+    fn score(&self, shape: &ShapeVariant, left_x: usize, bottom_y: usize, params: &GreedyParams) -> f32 {
+        let mut score = 0f32;
         for (x, y) in &shape.coords {
-            let x = *x + left_x;
-            let y = *y + bottom_y;
-            total += self.touchpoints[y][x];
+            score += self.cell_scores[bottom_y + *y][left_x + *x];
+        }
+        // Features below are slower to compute, and unused by default.
+        if params.holes != 0.0 {
+            let holes = shape.coords.iter().filter(|(dx, dy)| {
+                let (x, y) = (left_x + *dx, bottom_y + *dy);
+                y > 0 && !self.is_set(x, y - 1) && !shape.coords.contains(&(*dx, dy.wrapping_sub(1)))
+            }).count();
+            score += params.holes * holes as f32;
         }
-        total
+        if params.height_increase != 0.0 || params.column_parity != 0.0 {
+            // Column tops after the placement, for the columns of the shape.
+            let mut tops = [0; 4];
+            tops[..shape.width].copy_from_slice(&self.column_tops[left_x..left_x + shape.width]);
+            for (dx, dy) in &shape.coords {
+                tops[*dx] = tops[*dx].max(bottom_y + *dy + 1);
+            }
+            let shape_top = *tops[..shape.width].iter().max().unwrap();
+            score += params.height_increase * shape_top.saturating_sub(self.top) as f32;
+            let top_after = |x: usize| if x >= left_x && x < left_x + shape.width { tops[x - left_x] } else { self.column_tops[x] };
+            let (first, last) = (left_x.saturating_sub(1), (left_x + shape.width).min(self.width - 1));
+            let odd_before = (first..last).filter(|&x| (self.column_tops[x] + self.column_tops[x + 1]) % 2 == 1).count();
+            let odd_after = (first..last).filter(|&x| (top_after(x) + top_after(x + 1)) % 2 == 1).count();
+            score += params.column_parity * (odd_after as f32 - odd_before as f32);
+        }
+        score
     }
 
     // Minimum 'y' position where a shape could fit at a given 'x' position.
@@ -135,7 +251,7 @@ impl Board {
     // Returns whether we could fit the shape.
     fn move_first_fit_above(&self, shape: &ShapeVariant, left_x: usize, out_y: &mut usize) -> bool {
         let min_y = self.min_y_for_shape(shape, left_x);
-        for y in min_y..(self.height + 1 - shape.height as usize) {
+        for y in min_y..(self.height + 1 - shape.height) {
             if self.fits(shape, left_x, y) {
                 *out_y = y;
                 return true;
@@ -150,34 +266,37 @@ struct Placement {
     rotation_index: usize,
     x: usize,
     y: usize,
+    score: f32,
 }
 
-fn try_gravity_greedy_fit(board: &mut Board, mut bag: TotemBag) -> Option<Vec<TotemAnswer>> {
+fn try_gravity_greedy_fit<O: SolverObserver>(board: &mut Board, mut bag: TotemBag, params: &GreedyParams,
+                                             observer: &mut O) -> Option<Vec<TotemAnswer>> {
     let mut rng = rand::thread_rng();
     let mut options = Vec::with_capacity(7 * 4 * board.width);  // 7 shapes, 4 rotations max, 'width' x positions.
     loop {
         options.clear();
         let mut shapes_left = 0;
-        let mut max_touchpoints = 0;
+        let mut max_score = f32::MIN;
 
         for totem in TOTEMS.iter() {
             let n_totem = bag[totem];
             shapes_left += n_totem;
             if n_totem > 0 {
-                for (rot_idx, variant) in ShapeVariant::get_rotations(&totem).iter().enumerate() {
+                for (rot_idx, variant) in ShapeVariant::get_rotations(totem).iter().enumerate() {
                     for dx in 0..(board.width as i32 + 1 - variant.width as i32) {
                         let x = dx as usize;
                         let mut y = 0;
-                        if board.move_first_fit_above(&variant, x, &mut y) {
-                            let touchpoints = board.num_touchpoints(&variant, x, y);
-                            if touchpoints > max_touchpoints {
-                                max_touchpoints = touchpoints;
-                                options.clear();  // new better options found, restart.
+                        if board.move_first_fit_above(variant, x, &mut y) {
+                            let score = board.score(variant, x, y, params);
+                            if score > max_score {
+                                max_score = score;
+                                // new better options found, drop the ones no longer close enough.
+                                options.retain(|option: &Placement| option.score >= max_score - params.ties);
                             }
-                            if touchpoints == max_touchpoints {
+                            if score >= max_score - params.ties {
                                 options.push(Placement {
                                     totem: variant.shape, rotation_index: rot_idx,
-                                    x: x, y: y
+                                    x, y, score
                                     });
                             }
                         }
@@ -193,33 +312,122 @@ fn try_gravity_greedy_fit(board: &mut Board, mut bag: TotemBag) -> Option<Vec<To
         }
         let placement = options.choose(&mut rng).unwrap();
         let rotations = ShapeVariant::get_rotations(&placement.totem);
-        let shape = rotations.iter().nth(placement.rotation_index).unwrap();
-        board.mark(&shape, placement.x, placement.y);
+        let shape = &rotations[placement.rotation_index];
+        board.mark(shape, placement.x, placement.y);
+        observer.on_event(SolverEvent::Place { totem: board.totems.last().unwrap().clone() });
         bag[shape.shape] -= 1;
+        if board.is_doomed(&bag) {
+            return None;
+        }
     }
 }
 
+struct ParamsTable {
+    default: GreedyParams,
+    // Used instead of 'default' for bags of these numbers of totems.
+    tuned: TunedGreedyParams,
+}
+
+// Attempts made without a deadline, by default. Can only afford so many at higher levels.
+fn default_max_attempts(num_totems: usize) -> usize {
+    if num_totems < 256 { 1000 } else { 100 }
+}
+
+// Parameters are shared between the clones that run in parallel.
 #[derive(Clone)]
 pub struct GreedySolver {
+    params: Arc<ParamsTable>,
+    // Attempts made without a deadline. Defaults to 'default_max_attempts'.
+    max_attempts: Option<usize>,
+    stop: StopFlag,
 }
 
-impl Solver for GreedySolver {
-    fn new() -> Self {
-        Self { }
+impl GreedySolver {
+    pub fn with_params(params: GreedyParams) -> Self {
+        Self {
+            params: Arc::new(ParamsTable { default: params, tuned: TunedGreedyParams::new() }),
+            max_attempts: None,
+            stop: StopFlag::default(),
+        }
     }
 
-    fn try_solve(&self, width: usize, height: usize, bag: &TotemBag) -> Option<Vec<TotemAnswer>> {
-        let num_totems = bag.total();
+    pub fn with_tuned_params(tuned: TunedGreedyParams) -> Self {
+        Self {
+            params: Arc::new(ParamsTable { default: GreedyParams::default(), tuned }),
+            max_attempts: None,
+            stop: StopFlag::default(),
+        }
+    }
+
+    // Replaces 'default_max_attempts' for solves without a deadline.
+    pub fn set_max_attempts(&mut self, max_attempts: usize) {
+        assert!(max_attempts > 0, "Need at least one attempt.");
+        self.max_attempts = Some(max_attempts);
+    }
+
+    // Stops the attempts in progress once 'stop' is.
+    pub fn set_stop_flag(&mut self, stop: StopFlag) {
+        self.stop = stop;
+    }
+
+    // Parameters used for bags of 'num_totems' totems.
+    pub fn params(&self, num_totems: usize) -> &GreedyParams {
+        self.params.tuned.get(&num_totems).unwrap_or(&self.params.default)
+    }
+
+    // Like 'try_solve', but makes as many attempts as fit before 'deadline' (at least one).
+    pub fn try_solve_until(&self, width: usize, height: usize, bag: &TotemBag,
+                           deadline: Instant) -> Option<Vec<TotemAnswer>> {
+        self.attempt(width, height, bag, &mut NoopObserver, Some(deadline))
+    }
 
-        // Try multiple times due to the stochastic nature when multiple totems have the same number of
-        // touchpoints. Doing so improves the packing %. Can only afford so many attempts at higher levels,
-        // however.
-        let attempts = if num_totems < 256 { 1000 } else { 100 };
-        for _ in 0..attempts {
-            if let Some(sln) = try_gravity_greedy_fit(&mut Board::new(width, height, num_totems), bag.clone()) {
+    // Try multiple times due to the stochastic nature when multiple totems have the same number of
+    // touchpoints. Doing so improves the packing %.
+    fn attempt<O: SolverObserver>(&self, width: usize, height: usize, bag: &TotemBag, observer: &mut O,
+                                  deadline: Option<Instant>) -> Option<Vec<TotemAnswer>> {
+        let start = Instant::now();
+        let num_totems = bag.total();
+        let params = self.params(num_totems);
+        // With a deadline, attempts only stop with time.
+        let max_attempts = match deadline {
+            Some(_) => u32::MAX,
+            None => self.max_attempts.unwrap_or_else(|| default_max_attempts(num_totems)) as u32,
+        };
+        for done in 0..max_attempts {
+            if self.stop.is_stopped() {
+                debug!(attempts = done, "Stopped, another solver found a solution.");
+                return None;
+            }
+            if done > 0 {
+                // Assume the next attempt costs as much as the average one so far.
+                let now = Instant::now();
+                if deadline.is_some_and(|deadline| now + (now - start) / done > deadline) {
+                    debug!(attempts = done, elapsed_ms = start.elapsed().as_millis() as u64,
+                           "Out of time for greedy attempts.");
+                    return None;
+                }
+                observer.on_event(SolverEvent::Restart);
+            }
+            let mut board = Board::new(width, height, num_totems, params);
+            if let Some(sln) = try_gravity_greedy_fit(&mut board, bag.clone(), params, observer) {
                 return Some(sln);
             }
         }
         None
     }
 }
+
+impl Solver for GreedySolver {
+    fn new() -> Self {
+        Self::with_params(GreedyParams::default())
+    }
+
+    fn try_solve(&self, width: usize, height: usize, bag: &TotemBag) -> Option<Vec<TotemAnswer>> {
+        self.try_solve_observed(width, height, bag, &mut NoopObserver)
+    }
+
+    fn try_solve_observed<O: SolverObserver>(&self, width: usize, height: usize, bag: &TotemBag,
+                                             observer: &mut O) -> Option<Vec<TotemAnswer>> {
+        self.attempt(width, height, bag, observer, None)
+    }
+}
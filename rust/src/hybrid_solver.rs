@@ -1,17 +1,58 @@
 // Solver that picks the right solving strategy based on the level.
 // It will also try multiple solution dimensions, in the order that
 // would maximize the score.
+// If a calibrated difficulty model is available (see 'difficulty'), dimensions where the bag is
+// unlikely to be packed, or not within their share of the time budget, are skipped.
+// The constructive packing of 'BlockSolver' is used as a floor: dimensions that don't score better
+// than it are not tried, and it is the answer if nothing better is found.
+// If the SOLUTION_CACHE environment variable gives a file, attempts at small bags go through a
+// solution cache persisted there across games (see 'solution_cache'). A cached h x w result also
+// serves w x h, rotated.
+// The greedy solver uses the parameters tuned per level, if any (see 'bin/tune_greedy.rs').
+// Each attempt gets a share of the time budget, as a deadline that every strategy stops at: the
+// time left is split evenly across the dimensions still to try. Once the budget is spent, the
+// dimensions left are not tried.
+// Bags of the small levels are answered by lookup if a precomputed answer table is available (see
+// 'answer_table').
 
 use crate::{
+    answer_table::AnswerTable,
+    block_solver::BlockSolver,
+    certificates::{self, Certificate, Feasibility},
+    difficulty::DifficultyModel,
     exhaustive_solver::ExhaustiveSolver,
-    game_interface::{Answer, Question, Totem, TotemAnswer, TotemBag, TotemQuestion, TOTEMS},
-    greedy_solver::GreedySolver,
+    game_interface::{Answer, Question, Totem, TotemAnswer, TotemBag, TOTEMS},
+    greedy_solver::{self, GreedySolver},
     scoring::{score, Dims, OptimalDimensions},
     shape_info::ShapeVariant,
-    solver::{macros::solver_boilerplate, Solver},
+    solution_cache::{CachedResult, SolutionCache},
+    solver::{macros::solver_boilerplate, Solver, StopFlag, DEFAULT_TIME_BUDGET},
     rect_packing_solver::RectPackingSolver,
+    trace::{observed_attempt, SolverObserver},
+    transform::{cover_origin, Transform, ROTATIONS},
 };
-use std::{cmp, thread};
+use std::{
+    cmp,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{debug, debug_span, info, info_span, warn, Span};
+
+pub const DIFFICULTY_MODEL_PATH: &str = "src/difficulty_model.json";
+// Dimensions are skipped if the difficulty model predicts that packing them is less likely than
+// this, or that an attempt takes longer than their share of the time budget.
+// The last dimensions of a level are always tried.
+const MIN_SUCCESS_PROBABILITY: f64 = 0.05;
+
+pub const ANSWER_TABLE_PATH: &str = "src/answer_table.json";
+
+pub const GREEDY_PARAMS_PATH: &str = "src/greedy_params.json";
+
+// Search nodes spent proving that a failed attempt is infeasible, before caching it. This is a
+// budget of its own, on top of the attempt's: cached bags are small (see 'SolutionCache'), so a
+// proof takes milliseconds at most.
+const CACHE_PROOF_MAX_NODES: usize = 100_000;
 
 // Minimum dimensions needed to fit the individual totems in the bag.
 // This is used to avoid trying e.g. a 2x2 board when we have an "L" piece, for instance.
@@ -19,8 +60,8 @@ use std::{cmp, thread};
 fn min_dimensions_needed(bag: &TotemBag) -> Dims {
     let mut dims = (0, 0);
     for totem in TOTEMS.iter() {
-        if bag.contains(&totem) {
-            let dim = ShapeVariant::minimum_dims(&totem);
+        if bag.contains(totem) {
+            let dim = ShapeVariant::minimum_dims(totem);
             dims.0 = cmp::max(dims.0, dim.0);
             dims.1 = cmp::max(dims.1, dim.1);
         }
@@ -28,44 +69,176 @@ fn min_dimensions_needed(bag: &TotemBag) -> Dims {
     dims
 }
 
+// Packing strategy picked for a given problem.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Strategy {
+    // Perfect packing that can't be done (odd number of 'T's), nothing is tried.
+    Impossible,
+    Exhaustive,
+    RectPacking,
+    Greedy,
+}
+
+impl Strategy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::Impossible => "impossible",
+            Strategy::Exhaustive => "exhaustive",
+            Strategy::RectPacking => "rect_packing",
+            Strategy::Greedy => "greedy",
+        }
+    }
+}
+
 pub struct HybridSolver {
     // Usually want this on, but can be useful to turn off when profiling.
     use_multithreading: bool,
-    // Probably want this on on the server, but not when evaluating offline in a loop.
-    verbose: bool,
     optimal_dims: OptimalDimensions,
+    difficulty: Option<DifficultyModel>,
+    cache: Option<SolutionCache>,
+    answers: Option<AnswerTable>,
+    time_budget: Duration,
 
+    blocks: BlockSolver,
     greedy: GreedySolver,
     exhaustive: ExhaustiveSolver,
     rect_packing: RectPackingSolver,
 }
 
 impl HybridSolver {
-    pub fn with_options(multithreading: bool, verbose: bool) -> Self {
+    pub fn with_options(multithreading: bool) -> Self {
         Self {
             optimal_dims: OptimalDimensions::new(),
             use_multithreading: multithreading,
-            verbose: verbose,
-            greedy: GreedySolver::new(),
+            difficulty: load_difficulty_model(),
+            cache: open_solution_cache(),
+            answers: load_answer_table(),
+            time_budget: DEFAULT_TIME_BUDGET,
+            blocks: BlockSolver::new(),
+            greedy: load_greedy_solver(),
             exhaustive: ExhaustiveSolver::new(),
             rect_packing: RectPackingSolver::new(),
         }
     }
 
+    // Replaces the difficulty model loaded from 'DIFFICULTY_MODEL_PATH', e.g. to compare with and
+    // without one.
+    pub fn set_difficulty_model(&mut self, model: Option<DifficultyModel>) {
+        self.difficulty = model;
+    }
+
+    // Replaces the solution cache given by SOLUTION_CACHE, e.g. to measure the solver itself.
+    pub fn set_solution_cache(&mut self, cache: Option<SolutionCache>) {
+        self.cache = cache;
+    }
+
+    // Replaces the answer table loaded from 'ANSWER_TABLE_PATH', e.g. to measure the solver itself.
+    pub fn set_answer_table(&mut self, answers: Option<AnswerTable>) {
+        self.answers = answers;
+    }
+
+    // Replaces 'DEFAULT_TIME_BUDGET', e.g. for a slower or faster machine.
+    pub fn set_time_budget(&mut self, budget: Duration) {
+        self.time_budget = budget;
+    }
+
+    // Attempt at packing in width x height with 'try_solve', through the solution cache.
+    fn cached_try_solve(&self, width: usize, height: usize, bag: &TotemBag,
+                        try_solve: &mut impl FnMut(usize, usize) -> Option<Vec<TotemAnswer>>) -> Option<Vec<TotemAnswer>> {
+        let cache = match &self.cache {
+            Some(cache) if SolutionCache::is_cacheable(bag) => cache,
+            _ => return try_solve(width, height),
+        };
+        match cache.get(bag, width, height) {
+            Some(CachedResult::Solved(sln)) => {
+                debug!(width, height, "Solution cached.");
+                return Some(sln);
+            }
+            Some(CachedResult::Infeasible) => {
+                debug!(width, height, "Infeasibility cached.");
+                return None;
+            }
+            None => {}
+        }
+        match cache.get(bag, height, width) {
+            Some(CachedResult::Solved(sln)) => {
+                let quarter_turns = [Transform::Rotate90, Transform::Rotate270];
+                if let Some((sln, _)) = cover_origin(&sln, height, width, &quarter_turns) {
+                    debug!(width, height, "Rotated solution cached.");
+                    return Some(sln);
+                }
+            }
+            Some(CachedResult::Infeasible) => {
+                // Refutations don't depend on (0, 0), so they hold for the rotated board too.
+                debug!(width, height, "Rotated infeasibility cached.");
+                return None;
+            }
+            None => {}
+        }
+        let sln = try_solve(width, height);
+        match &sln {
+            Some(sln) => cache.insert(bag, width, height, CachedResult::Solved(sln.clone())),
+            None => {
+                let feasibility = certificates::feasibility(width, height, bag, CACHE_PROOF_MAX_NODES, None);
+                if matches!(feasibility, Feasibility::Infeasible(_)) {
+                    cache.insert(bag, width, height, CachedResult::Infeasible);
+                }
+            }
+        }
+        sln
+    }
+
+    // If the difficulty model predicts that the bag is not worth trying in these dimensions, given
+    // the time an attempt would get.
+    fn should_skip(&self, width: usize, height: usize, bag: &TotemBag, share: Duration) -> bool {
+        let prediction = match self.difficulty.as_ref().and_then(|model| model.predict(width, height, bag)) {
+            Some(prediction) => prediction,
+            None => return false,
+        };
+        let skip = prediction.success_probability < MIN_SUCCESS_PROBABILITY
+            || prediction.expected_seconds > share.as_secs_f64();
+        if skip {
+            info!(width, height, success_probability = prediction.success_probability,
+                  expected_seconds = prediction.expected_seconds, share_ms = share.as_millis() as u64,
+                  "Skipping dims, unlikely to pack in time.");
+        }
+        skip
+    }
+
+    // Strategy that 'try_solve' uses for the given problem.
+    pub fn strategy(&self, width: usize, height: usize, bag: &TotemBag) -> Strategy {
+        let num_totems = bag.total();
+        let perfect_pack = num_totems * 4 == width * height;
+        if perfect_pack && bag[Totem::T] % 2 == 1 {
+            // We can't fully pack a rectangle if we are given an odd number of 'T' shapes (see README),
+            // so there's no point in trying.
+            return Strategy::Impossible;
+        }
+        // For <= 8, we can do an exhaustive search.
+        if num_totems <= 8 {
+            return Strategy::Exhaustive;
+        }
+        // The hard levels where we must perfectly fit the pieces. Use precomputed rectangles and
+        // treat this as a "rectangle packing" problem, then.
+        // Note: 16 seems to be better with the greedy approach.
+        let hard_level = num_totems == 64 || num_totems == 256;
+        if hard_level && perfect_pack {
+            Strategy::RectPacking
+        } else {
+            Strategy::Greedy
+        }
+    }
+
     /// Answer the question
     pub fn get_answer(&self, question: &Question) -> Answer {
         let num_totems = question.totems.len();
-        println!("Received question with {} totems.", num_totems);
+        let _span = info_span!("solve", totems = num_totems).entered();
+        info!(totems = num_totems, "Received question.");
 
         let inferred_level = (num_totems as f64).log2().ceil() as usize;
         let (optimal_w, optimal_h) = self.optimal_dims.level_dims(inferred_level)[0];
-        println!(
-            "Optimal dims for level {} would be {}x{}, which would give score {}",
-            inferred_level + 1,
-            optimal_w,
-            optimal_h,
-            score(num_totems, optimal_w, optimal_h)
-        );
+        info!(level = inferred_level + 1, width = optimal_w, height = optimal_h,
+              score = score(num_totems, optimal_w, optimal_h), "Optimal dims for the level.");
 
         let bag = question.get_totem_bag();
         solver_boilerplate! {
@@ -75,122 +248,280 @@ impl HybridSolver {
 
     // Try each optimal dimensions in order, trying to fit each one using 'try_solve'
     // to pick the right strategy.
-    fn full_solve(&self, bag: &TotemBag, level: usize) -> Vec<TotemAnswer> {
+    // Unlike 'get_answer', this does not log the question.
+    pub fn full_solve(&self, bag: &TotemBag, level: usize) -> Vec<TotemAnswer> {
+        self.full_solve_with(bag, level, |w, h, deadline| self.try_solve_until(w, h, bag, deadline))
+    }
+
+    // Like 'full_solve', reporting each dimensions tried and their search to 'observer'.
+    // Runs single threaded, to get a sequential trace.
+    pub fn full_solve_observed<O: SolverObserver>(&self, bag: &TotemBag, level: usize,
+                                                  observer: &mut O) -> Vec<TotemAnswer> {
+        self.full_solve_with(bag, level, |w, h, _| observed_attempt(self, w, h, bag, observer))
+    }
+
+    // Like 'full_solve', along with a certificate of whether the answer is optimal (see
+    // 'certificates.rs'). Each better dimensions is searched up to 'max_nodes', so this is only
+    // cheap for small levels.
+    pub fn full_solve_certified(&self, bag: &TotemBag, level: usize,
+                                max_nodes: usize) -> (Vec<TotemAnswer>, Certificate) {
+        let answer = self.full_solve(bag, level);
+        let _span = debug_span!("certify", level = level + 1).entered();
+        let certificate = certificates::certify(bag, &answer, max_nodes);
+        for dims in certificate.better_dims.iter().filter(|dims| dims.proof.is_none()) {
+            debug!(width = dims.width, height = dims.height, "Better dims not proven infeasible.");
+        }
+        info!(status = certificate.status(), "Certified answer.");
+        (answer, certificate)
+    }
+
+    // 'try_solve' attempts a packing in w x h, by the given deadline.
+    fn full_solve_with(&self, bag: &TotemBag, level: usize,
+                       mut try_solve: impl FnMut(usize, usize, Instant) -> Option<Vec<TotemAnswer>>) -> Vec<TotemAnswer> {
+        let start = Instant::now();
+        // Solvers fit the totems within w x h, but don't all guarantee that (0, 0) is covered: rotate
+        // the fit if needed, to get a valid answer.
+        let mut try_solve = |w, h, deadline| {
+            let fit = self.cached_try_solve(w, h, bag, &mut |w, h| try_solve(w, h, deadline))?;
+            let fixed = cover_origin(&fit, w, h, &ROTATIONS);
+            if fixed.is_none() {
+                debug!(width = w, height = h, "Fit found without any corner covered.");
+            }
+            fixed.map(|(fit, _)| fit)
+        };
+        let _span = debug_span!("dims_selection", level = level + 1).entered();
+        // Answers not proven optimal may be beaten by a live solve.
+        match self.answers.as_ref().and_then(|answers| answers.get(bag)) {
+            Some(answer) if answer.proven => {
+                info!("Answer precomputed.");
+                return answer.totems.clone();
+            }
+            Some(_) => debug!("Precomputed answer not proven optimal, solving."),
+            None => {}
+        }
         let min_dims = min_dimensions_needed(bag);
         let num_totems = bag.total();
-        for (w, h) in self.optimal_dims.level_dims(level) {
+        let level_dims = self.optimal_dims.level_dims(level);
+        let (floor, floor_dims) = self.blocks.pack(bag);
+        let floor_score = score(num_totems, floor_dims.0, floor_dims.1);
+        // Dimensions to try (and whether they are the last of the level), known upfront to split the
+        // time budget across them.
+        let mut candidates = Vec::new();
+        let mut floor_reached = false;
+        for (i, (w, h)) in level_dims.iter().enumerate() {
+            if score(num_totems, *w, *h) <= floor_score {
+                floor_reached = true;
+                break;
+            }
             // Note: implicit assumption here that optimal_dims have the shortest dim first,
             // and that min_dimensions_needed also does so based on minimal width
             if min_dims.0 > *w || min_dims.1 > *h {
-                println!("Skipping {}x{} (would have given {}), could not fit {}x{} totem",
-                         *w, *h, score(num_totems, *w, *h), min_dims.0, min_dims.1);
+                debug!(width = *w, height = *h, score = score(num_totems, *w, *h), min_width = min_dims.0,
+                       min_height = min_dims.1, "Skipping dims, a totem could not fit.");
+                continue;
+            }
+            candidates.push((*w, *h, i + 1 == level_dims.len()));
+        }
+        // Non-square dims are tried in both orientations, each getting a share of the time left.
+        let mut attempts_left: u32 = candidates.iter().map(|(w, h, _)| if w == h { 1 } else { 2 }).sum();
+        let share = |attempts_left: u32| self.time_budget.saturating_sub(start.elapsed()) / attempts_left.max(1);
+        for &(w, h, is_last) in &candidates {
+            if start.elapsed() >= self.time_budget {
+                info!(width = w, height = h, score = score(num_totems, w, h), "Out of time, not trying dims.");
+                return floor;
+            }
+            let orientations = if w == h { 1 } else { 2 };
+            if !is_last && self.should_skip(w, h, bag, share(attempts_left)) {
+                attempts_left -= orientations;
                 continue;
             }
-            print!("Trying {}x{}... would give {}... ", *w, *h, score(num_totems, *w, *h));
-            if let Some(fit) = self.try_solve(*w, *h, bag) {
-                println!("OK!");
+            info!(width = w, height = h, score = score(num_totems, w, h), "Trying dims.");
+            let deadline = Instant::now() + share(attempts_left);
+            attempts_left -= 1;
+            if let Some(fit) = try_solve(w, h, deadline) {
+                info!("Fit found.");
                 return fit;
-            } else if *w != *h {
-                if let Some(fit) = self.try_solve(*h, *w, bag) {
+            } else if w != h {
+                let deadline = Instant::now() + share(attempts_left);
+                attempts_left -= 1;
+                if let Some(fit) = try_solve(h, w, deadline) {
                     // Because of our (0, 0) constraint, sometimes the rotation works.
                     // We typically run fast enough to just try both (non-squares optimal dims
                     // are mostly lower levels).
-                    println!("OK!  (with rotation {}x{})", *h, *w);
+                    info!(width = h, height = w, "Fit found with rotation.");
                     return fit;
                 }
             }
-            println!("No fit found.");
+            info!("No fit found.");
+        }
+        if floor_reached {
+            info!(width = floor_dims.0, height = floor_dims.1, score = floor_score,
+                  "Constructive packing scores as well as the remaining dims.");
+            return floor;
+        }
+        warn!("Failed to find a solution. Should increase ranges in 'optimal dims'.");
+        // Fallback to the constructive packing instead of returning nothing.
+        floor
+    }
+}
+
+fn load_difficulty_model() -> Option<DifficultyModel> {
+    let path = Path::new(DIFFICULTY_MODEL_PATH);
+    if !path.exists() {
+        return None;
+    }
+    match DifficultyModel::load(path) {
+        Ok(model) => {
+            info!(path = DIFFICULTY_MODEL_PATH, dims = model.calibrated_dims().len(), "Loaded difficulty model.");
+            Some(model)
+        }
+        Err(e) => {
+            warn!(path = DIFFICULTY_MODEL_PATH, error = %e, "Could not load difficulty model, not using one.");
+            None
         }
-        println!("!!! FAILED TO FIND SOLUTION. Should increase ranges in 'optimal dims'.");
-        // Fallback to greedy instead of returning nothing.
-        let question = Question { totems: bag.expand().map(|t| TotemQuestion { shape: t }).collect() };
-        self.greedy.solve(&question).totems
     }
 }
 
+fn load_answer_table() -> Option<AnswerTable> {
+    let path = Path::new(ANSWER_TABLE_PATH);
+    if !path.exists() {
+        return None;
+    }
+    match AnswerTable::load(path) {
+        Ok(answers) => {
+            info!(path = ANSWER_TABLE_PATH, bags = answers.len(), "Loaded answer table.");
+            Some(answers)
+        }
+        Err(e) => {
+            warn!(path = ANSWER_TABLE_PATH, error = %e, "Could not load answer table, not using one.");
+            None
+        }
+    }
+}
+
+fn load_greedy_solver() -> GreedySolver {
+    let path = Path::new(GREEDY_PARAMS_PATH);
+    if !path.exists() {
+        return GreedySolver::new();
+    }
+    match greedy_solver::load_tuned_params(path) {
+        Ok(tuned) => {
+            info!(path = GREEDY_PARAMS_PATH, levels = tuned.len(), "Loaded tuned greedy parameters.");
+            GreedySolver::with_tuned_params(tuned)
+        }
+        Err(e) => {
+            warn!(path = GREEDY_PARAMS_PATH, error = %e, "Could not load greedy parameters, using the defaults.");
+            GreedySolver::new()
+        }
+    }
+}
+
+// Opens the solution cache in the file given by the SOLUTION_CACHE environment variable, if set.
+fn open_solution_cache() -> Option<SolutionCache> {
+    let path = std::env::var("SOLUTION_CACHE").ok()?;
+    match SolutionCache::open(Path::new(&path)) {
+        Ok(cache) => {
+            info!(path = %path, entries = cache.len(), "Opened solution cache.");
+            Some(cache)
+        }
+        Err(e) => {
+            warn!(path = %path, error = %e, "Could not load solution cache, not using one.");
+            None
+        }
+    }
+}
+
+// Runs '$solve' with clones of the solver '$x' in parallel, as '$solver'. The first clone to find
+// a solution stops the others.
 macro_rules! multithread_solver {
-    ( $x: expr, $w: ident, $h: ident, $bag: ident ) => {
+    ( $x: expr, $bag: ident, |$solver: ident| $solve: expr ) => {
         {
             // From tests, we think we're on a c5a.2xlarge, so 4 cores, 8 hyperthreaded.
             // As IIUC going up to 8 would hurt, since we're doing purely CPU processing
             // and not much IO:
             // https://www.credera.com/insights/whats-in-a-vcpu-state-of-amazon-ec2-in-2018
             let cores = 4-1;  // leave some breathing room with -1
+            let stop = StopFlag::new();
             let mut handles = vec![];
             for _ in 0..cores {
                 let bag = $bag.clone();
-                let solver = $x.clone();
+                let mut $solver = $x.clone();
+                $solver.set_stop_flag(stop.clone());
+                let stop = stop.clone();
+                let span = Span::current();  // So that logs of each thread are within our span.
                 handles.push(thread::spawn(move || {
-                    solver.try_solve($w, $h, &bag)
+                    let _span = span.entered();
+                    let $bag = &bag;
+                    let sln = $solve;
+                    if sln.is_some() {
+                        stop.stop();
+                    }
+                    sln
                 }));
             }
-            for handle in handles {
-                if let Some(sln) = handle.join().unwrap() {
-                    return Some(sln);
+            // All threads are joined, so that none still runs during the next attempt.
+            let slns: Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+            slns.into_iter().flatten().next()
+        }
+    };
+}
+
+impl HybridSolver {
+    // Like 'try_solve', but with the strategy stopping at 'deadline'.
+    fn try_solve_until(&self, width: usize, height: usize, bag: &TotemBag,
+                       deadline: Instant) -> Option<Vec<TotemAnswer>> {
+        let strategy = self.strategy(width, height, bag);
+        let _span = debug_span!("try_dims", width, height, strategy = strategy.name()).entered();
+        if strategy != Strategy::Impossible {
+            // Multithreading only relevant for greedy solvers.
+            let multithreaded = self.use_multithreading && strategy != Strategy::Exhaustive;
+            info!(strategy = strategy.name(), multithreaded, width, height, totems = bag.total(), "Using packer.");
+        }
+        match strategy {
+            Strategy::Impossible => None,
+            Strategy::Exhaustive => self.exhaustive.try_solve_until(width, height, bag, deadline),
+            Strategy::RectPacking => {
+                if self.use_multithreading {
+                    multithread_solver!(self.rect_packing, bag,
+                                        |solver| solver.try_solve_until(width, height, bag, deadline))
+                } else {
+                    self.rect_packing.try_solve_until(width, height, bag, deadline)
+                }
+            }
+            Strategy::Greedy => {
+                if self.use_multithreading {
+                    multithread_solver!(self.greedy, bag, |solver| solver.try_solve_until(width, height, bag, deadline))
+                } else {
+                    self.greedy.try_solve_until(width, height, bag, deadline)
                 }
             }
-            None
         }
-    };
+    }
 }
 
 impl Solver for HybridSolver {
     fn new() -> Self {
-        Self::with_options(/*multithreading=*/true, /*verbose=*/true)
+        Self::with_options(/*multithreading=*/true)
     }
 
     fn solve(&self, question: &Question) -> Answer {
         self.get_answer(question)
     }
 
+    // Like the attempts of 'full_solve' when these dims are the only ones tried: with the whole
+    // time budget.
     fn try_solve(&self, width: usize, height: usize, bag: &TotemBag) -> Option<Vec<TotemAnswer>> {
-        let num_totems = bag.total();
-
-        // For <= 8, we can do an exhaustive search.
-        let greedy = num_totems > 8;
-        // The hard levels where we must perfectly fit the pieces. Use precomputed rectangles and
-        // treat this as a "rectangle packing" problem, then.
-        // Note: 16 seems to be better with the greedy approach.
-        let hard_level = num_totems == 64 || num_totems == 256;
-        let perfect_pack = num_totems * 4 == width * height;
-
-        if perfect_pack && bag[Totem::T] % 2 == 1 {
-            // We can't fully pack a rectangle if we are given an odd number of 'T' shapes (see README),
-            // so there's no point in trying.
-            return None;
-        }
-
-        let exhaustive_packer = !greedy;
-        let rect_packer = greedy && hard_level && perfect_pack;
-        let greedy_packer = greedy && !rect_packer;
+        self.try_solve_until(width, height, bag, Instant::now() + self.time_budget)
+    }
 
-        if self.verbose {
-            print!("Using ");
-            // Multithreading only relevant for greedy solvers.
-            if greedy {
-                if self.use_multithreading { print!("multithreaded"); }
-                else { print!("single threaded"); }    
-                print!(" ");
-            }
-            if rect_packer { print!("MCTS rectangle packer"); }
-            else if greedy_packer { print!("greedy packer"); }
-            else { print!("exhaustive packer (slow)"); }
-            println!(" for {}x{} on {} totems.", width, height, num_totems);
-        }
-        if exhaustive_packer {
-            self.exhaustive.try_solve(width, height, bag)
-        } else if rect_packer {
-            if self.use_multithreading {
-                multithread_solver!(self.rect_packing, width, height, bag)
-            } else {
-                self.rect_packing.try_solve(width, height, bag)
-            }
-        } else {  // greedy packer
-            if self.use_multithreading {
-                multithread_solver!(self.greedy, width, height, bag)
-            } else {
-                self.greedy.try_solve(width, height, bag)
-            }
+    // Always single threaded, to get a sequential trace.
+    fn try_solve_observed<O: SolverObserver>(&self, width: usize, height: usize, bag: &TotemBag,
+                                             observer: &mut O) -> Option<Vec<TotemAnswer>> {
+        match self.strategy(width, height, bag) {
+            Strategy::Impossible => None,
+            Strategy::Exhaustive => self.exhaustive.try_solve_observed(width, height, bag, observer),
+            Strategy::RectPacking => self.rect_packing.try_solve_observed(width, height, bag, observer),
+            Strategy::Greedy => self.greedy.try_solve_observed(width, height, bag, observer),
         }
     }
 }
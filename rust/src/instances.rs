@@ -0,0 +1,191 @@
+// Generation of random instances (questions), shared by the tools and the local clients, so that
+// they all follow the same rules as the challenge:
+//   - totems are picked uniformly at random among the 7 shapes,
+//   - level 1 (0-indexed level 0) is always a single "I", and level 'n' has 2^n totems.
+//
+// Instances can be filtered to only have an even number of 'T' shapes, since a rectangle can't be
+// packed perfectly with an odd number of them (see the README). Shapes can also be drawn with
+// other weights than uniform, e.g. to stress a solver on hard shapes.
+//
+// Generation is deterministic for a given RNG. Use 'seeded_rng' to get independent streams from
+// a seed (e.g. one per trial), so that instances only depend on the seed, whatever the order in
+// which they are generated.
+
+use crate::game_interface::{Question, Totem, TotemBag, TotemQuestion, TOTEMS, TOTEM_COUNT};
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    rngs::SmallRng,
+    seq::SliceRandom,
+    Rng,
+    SeedableRng,
+};
+use std::convert::TryFrom;
+
+#[derive(Clone, Debug)]
+pub enum ShapeDistribution {
+    // Like the challenge.
+    Uniform,
+    // Relative weight of each shape, indexed like 'TOTEMS'.
+    Weighted(WeightedIndex<f64>),
+}
+
+impl ShapeDistribution {
+    pub fn weighted(weights: [f64; TOTEM_COUNT]) -> Result<Self, String> {
+        WeightedIndex::new(weights)
+            .map(ShapeDistribution::Weighted)
+            .map_err(|e| format!("invalid shape weights: {}", e))
+    }
+
+    // Parses comma-separated "SHAPE=WEIGHT" pairs, e.g. "I=2,T=0". Shapes not listed have a
+    // weight of 1. "uniform" (or an empty spec) gives the uniform distribution.
+    pub fn from_spec(spec: &str) -> Result<Self, String> {
+        if spec.is_empty() || spec == "uniform" {
+            return Ok(ShapeDistribution::Uniform);
+        }
+        let mut weights = [1f64; TOTEM_COUNT];
+        for (shape, weight) in parse_counts(spec)? {
+            weights[shape] = weight.parse::<f64>()
+                .ok()
+                .filter(|weight| *weight >= 0f64 && weight.is_finite())
+                .ok_or_else(|| format!("weight of {:?} should be a non-negative number", shape))?;
+        }
+        Self::weighted(weights)
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Totem {
+        match self {
+            ShapeDistribution::Uniform => *TOTEMS.choose(rng).unwrap(),
+            ShapeDistribution::Weighted(index) => TOTEMS[index.sample(rng)],
+        }
+    }
+}
+
+// Splits "I=2,T=0" in [(I, "2"), (T, "0")].
+fn parse_counts(spec: &str) -> Result<Vec<(Totem, &str)>, String> {
+    spec.split(',')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (shape, value) = pair.split_once('=').ok_or_else(|| format!("'{}' should be SHAPE=VALUE", pair))?;
+            let mut chars = shape.trim().chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok((Totem::try_from(c)?, value.trim())),
+                _ => Err(format!("'{}' is not a totem, expected one of IJLOSTZ", shape)),
+            }
+        })
+        .collect()
+}
+
+// Parses an explicit bag, either from the letters of its shapes (e.g. "IJJLOSTZ"), or from
+// comma-separated counts per shape (e.g. "I=1,J=2,T=4").
+pub fn parse_bag(spec: &str) -> Result<TotemBag, String> {
+    if !spec.contains('=') {
+        return Question::from_shapes(spec).map(|question| question.get_totem_bag());
+    }
+    let mut bag = TotemBag::new();
+    for (shape, count) in parse_counts(spec)? {
+        bag[shape] = count.parse().map_err(|_| format!("count of {:?} should be a non-negative integer", shape))?;
+    }
+    if bag.is_empty() {
+        return Err(String::from("no totems given"));
+    }
+    Ok(bag)
+}
+
+pub fn question_from_bag(bag: &TotemBag) -> Question {
+    Question { totems: bag.expand().map(|shape| TotemQuestion { shape }).collect() }
+}
+
+// SplitMix64 finalizer, to turn nearby values in unrelated ones.
+// https://prng.di.unimi.it/splitmix64.c
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// RNG of one of the independent streams of a seed. Seed and stream are mixed, so that e.g. stream
+// 'n + 1' of a seed is unrelated to stream 'n' of the next seed.
+pub fn seeded_rng(seed: u64, stream: u64) -> SmallRng {
+    SmallRng::seed_from_u64(mix(seed ^ mix(stream)))
+}
+
+#[derive(Clone, Debug)]
+pub struct InstanceGenerator {
+    distribution: ShapeDistribution,
+    allow_odd_t_shapes: bool,
+}
+
+impl Default for InstanceGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InstanceGenerator {
+    // Generates instances like the challenge.
+    pub fn new() -> Self {
+        Self::with_options(ShapeDistribution::Uniform, /*allow_odd_t_shapes=*/true)
+    }
+
+    pub fn with_options(distribution: ShapeDistribution, allow_odd_t_shapes: bool) -> Self {
+        Self { distribution, allow_odd_t_shapes }
+    }
+
+    pub fn question<R: Rng>(&self, num_totems: usize, rng: &mut R) -> Question {
+        loop {
+            let question = Question {
+                totems: (0..num_totems).map(|_| TotemQuestion { shape: self.distribution.sample(rng) }).collect()
+            };
+            // Odd 'T's can't be packed perfectly, regenerate if we don't want them.
+            if self.allow_odd_t_shapes || question.get_totem_bag()[Totem::T] % 2 == 0 {
+                return question;
+            }
+        }
+    }
+
+    pub fn bag<R: Rng>(&self, num_totems: usize, rng: &mut R) -> TotemBag {
+        self.question(num_totems, rng).get_totem_bag()
+    }
+
+    // Question of a level (0-indexed), as the challenge would give it.
+    pub fn level<R: Rng>(&self, level: usize, rng: &mut R) -> Question {
+        if level == 0 {  // Level 1 is always "I".
+            return Question { totems: vec![TotemQuestion { shape: Totem::I }] };
+        }
+        self.question(1 << level, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generation_follows_options() {
+        let generator = InstanceGenerator::with_options(ShapeDistribution::from_spec("T=3,O=0").unwrap(), false);
+        let mut rng = seeded_rng(42, 0);
+        for _ in 0..20 {
+            let bag = generator.bag(16, &mut rng);
+            assert_eq!(bag.total(), 16);
+            assert_eq!(bag[Totem::O], 0);
+            assert!(bag[Totem::T] % 2 == 0);
+        }
+        assert_eq!(generator.level(0, &mut rng).get_totem_bag(), TotemBag::from_iter([Totem::I]));
+        // Same seed and stream, same instance.
+        assert_eq!(InstanceGenerator::new().bag(64, &mut seeded_rng(7, 3)),
+                   InstanceGenerator::new().bag(64, &mut seeded_rng(7, 3)));
+        // Streams of nearby seeds don't overlap.
+        assert_ne!(InstanceGenerator::new().bag(64, &mut seeded_rng(7, 4)),
+                   InstanceGenerator::new().bag(64, &mut seeded_rng(8, 3)));
+    }
+
+    #[test]
+    fn parses_bag_specs() {
+        let expected = TotemBag::from_iter([Totem::I, Totem::J, Totem::J, Totem::T]);
+        assert_eq!(parse_bag("IJJT"), Ok(expected.clone()));
+        assert_eq!(parse_bag("i=1, j=2,T=1"), Ok(expected));
+        assert!(parse_bag("I=1,X=2").is_err());
+        assert!(parse_bag("I=0").is_err());
+    }
+}
@@ -1,12 +1,30 @@
+pub mod answer_table;
+pub mod certificates;
+pub mod cli;
+pub mod dead_regions;
+pub mod decomposition;
+pub mod difficulty;
+pub mod free_space;
 pub mod game_interface;
+pub mod instances;
+pub mod logging;
 pub mod max_rects;
+pub mod planted;
 pub mod rect_inventory;
+pub mod rect_selection;
+pub mod render;
 pub mod scoring;
 pub mod shape_info;
+pub mod solution_cache;
 pub mod solver;
+pub mod solver_config;
+pub mod stats;
 pub mod subset_sum;
+pub mod trace;
+pub mod transform;
 
 // Solvers
+pub mod block_solver;
 pub mod dlx_solver;
 pub mod exhaustive_solver;
 pub mod greedy_solver;
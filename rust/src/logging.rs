@@ -0,0 +1,36 @@
+// Logging setup for the binaries. The library logs with 'tracing' macros ('info!', 'debug!'...),
+// with spans around the solving phases (dims selection, subset sum, MCTS, conversion...).
+// Logs go to stderr, so that stdout stays usable for the output of tools.
+//
+// Configured at runtime with environment variables:
+//   - RUST_LOG: levels, e.g. "debug" or "warn,application::rect_packing_solver=debug". Each binary
+//     has its own default level (e.g. "info" for the game client, "warn" for evaluation tools).
+//   - LOG_FORMAT=json: one JSON object per line, instead of human readable text.
+//   - LOG_SPANS=1: also log when spans close, with their busy/idle time, to get per-phase timings.
+// Disabled events and spans only cost a cached check, nothing is formatted or allocated. Still,
+// nothing is logged from the inner loops of the solvers.
+
+use std::env;
+use tracing::{level_filters::LevelFilter, Level};
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+pub fn init(default_level: Level) {
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::from_level(default_level).into())
+        .from_env_lossy();
+    let span_events = match env::var("LOG_SPANS") {
+        Ok(value) if value != "0" => FmtSpan::CLOSE,
+        _ => FmtSpan::NONE,
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(span_events)
+        .with_writer(std::io::stderr);
+    let result = if env::var("LOG_FORMAT").is_ok_and(|format| format == "json") {
+        builder.json().try_init()
+    } else {
+        builder.try_init()
+    };
+    // Only fails if a logger was already set, which is fine.
+    result.ok();
+}
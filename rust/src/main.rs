@@ -2,19 +2,20 @@ use std::env;
 
 mod client;
 
-use application::hybrid_solver;
+use application::{hybrid_solver, logging};
 use client::{LocalGameClient, WebSocketGameClient};
 
 type SelectedSolver = hybrid_solver::HybridSolver;
 
 #[tokio::main]
 async fn main() {
-    println!(
-        "[BUILD ENV]\nPROFILE={}\nOPT_LEVEL={}\nTARGET={}\nTARGET_FEATURE={}\n",
-        env!("PROFILE"),
-        env!("OPT_LEVEL"),
-        env!("TARGET"),
-        env!("CARGO_CFG_TARGET_FEATURE"),
+    logging::init(tracing::Level::INFO);
+    tracing::info!(
+        profile = env!("PROFILE"),
+        opt_level = env!("OPT_LEVEL"),
+        target = env!("TARGET"),
+        target_feature = env!("CARGO_CFG_TARGET_FEATURE"),
+        "Build environment."
     );
 
     if let Ok(token) = env::var("TOKEN") {
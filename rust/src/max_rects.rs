@@ -12,56 +12,10 @@
 
 // Structure based on:
 // http://pds25.egloos.com/pds/201504/21/98/RectangleBinPack.pdf
+// which also describes the placement heuristics below, to pick which free rectangle to use.
 
-#[derive(Clone, Debug)]
-struct Rect {
-    x: usize,
-    y: usize,  // NOTE: bottom
-    w: usize,
-    h: usize,
-}
-
-impl Rect {
-    fn new(x: usize, y: usize, w: usize, h: usize) -> Self {
-        Rect {
-            x: x, y: y, w: w, h: h
-        }
-    }
-
-    fn top(&self) -> usize {
-        self.y + self.h
-    }
-
-    fn bottom(&self) -> usize {
-        self.y
-    }
-
-    fn left(&self) -> usize {
-        self.x
-    }
-
-    fn right(&self) -> usize {
-        self.x + self.w
-    }
-
-    fn fits(&self, width: usize, height: usize) -> bool {
-        width <= self.w && height <= self.h
-    }
-
-    fn fully_contains(&self, other: &Rect) -> bool {
-        other.left() >= self.left() &&
-        other.right() <= self.right() &&
-        other.bottom() >= self.bottom() &&
-        other.top() <= self.top()
-    }
-
-    fn intersects(&self, other: &Rect) -> bool {
-        let no_intersect =
-            self.right() <= other.left() || self.left() >= other.right() ||
-            self.bottom() >= other.top() || self.top() <= other.bottom();
-        !no_intersect
-    }
-}
+use crate::free_space::{free_rect_score, FreeSpace, PlacementHeuristic, Rect};
+use crate::game_interface::Point;
 
 #[derive(Clone)]
 pub struct MaxRects {
@@ -69,14 +23,33 @@ pub struct MaxRects {
     height: usize,
     // Free spaces. Note that each space is maximimal, so they can overlap.
     free: Vec<Rect>,
+    // Placed rectangles, used by the contact point heuristic.
+    used: Vec<Rect>,
+    // Changes made to 'free' so far, to be able to undo placements.
+    journal: Vec<FreeSpaceChange>,
+    // Length of the journal before each placement in 'used'.
+    checkpoints: Vec<usize>,
+}
+
+// Change made to the list of free rectangles, with enough information to exactly revert it
+// (including the ordering of the free rectangles).
+#[derive(Clone, Debug)]
+enum FreeSpaceChange {
+    Pushed,
+    // Rectangle that was at the given index before a swap_remove.
+    SwapRemoved(usize, Rect),
 }
 
 impl MaxRects {
-    pub fn new(width: usize, height: usize) -> Self {
-        MaxRects {
-            width: width, height: height,
-            free: vec![Rect::new(0, 0, width, height)],
-        }
+    fn push_free(&mut self, rect: Rect) {
+        self.free.push(rect);
+        self.journal.push(FreeSpaceChange::Pushed);
+    }
+
+    fn swap_remove_free(&mut self, index: usize) -> Rect {
+        let rect = self.free.swap_remove(index);
+        self.journal.push(FreeSpaceChange::SwapRemoved(index, rect.clone()));
+        rect
     }
 
     fn split_at(&mut self, index: usize, splitter_width: usize, splitter_height: usize) {
@@ -99,26 +72,26 @@ impl MaxRects {
             if self.free[i].intersects(not_free) {
                 let to_split = self.free[i].clone();
                 if not_free.left() > to_split.left() {  // left split
-                    self.free.push(Rect::new(to_split.left(), to_split.bottom(),
+                    self.push_free(Rect::new(to_split.left(), to_split.bottom(),
                         not_free.left() - to_split.left(), to_split.h));
                     added_splits += 1;
                 }
                 if not_free.right() < to_split.right() {  // right split
-                    self.free.push(Rect::new(not_free.right(), to_split.bottom(),
+                    self.push_free(Rect::new(not_free.right(), to_split.bottom(),
                         to_split.right() - not_free.right(), to_split.h));
                     added_splits += 1;
                 }
                 if not_free.top() < to_split.top() {  // top split
-                    self.free.push(Rect::new(to_split.left(), not_free.top(),
+                    self.push_free(Rect::new(to_split.left(), not_free.top(),
                         to_split.w, to_split.top() - not_free.top()));
                     added_splits += 1;
                 }
                 if not_free.bottom() > to_split.bottom() {  // bottom split
-                    self.free.push(Rect::new(to_split.left(), to_split.bottom(),
+                    self.push_free(Rect::new(to_split.left(), to_split.bottom(),
                         to_split.w, not_free.bottom() - to_split.bottom()));
                     added_splits += 1;
                 }
-                self.free.swap_remove(i);
+                self.swap_remove_free(i);
                 if added_splits > 0 {
                     added_splits -= 1;
                     // We just swapped with a created split, we can skip it, it won't intersect.
@@ -140,9 +113,9 @@ impl MaxRects {
             while j < self.free.len() {
                 if self.free[i].fully_contains(&self.free[j]) {
                     // Do not move 'j' forward, free[j] is a different max rect.
-                    self.free.swap_remove(j);
+                    self.swap_remove_free(j);
                 } else if self.free[j].fully_contains(&self.free[i]) {
-                    self.free.swap_remove(i);
+                    self.swap_remove_free(i);
                     // Start over with 'j', we just placed a different rect at free[i].
                     j = i + 1;
                 } else {
@@ -152,33 +125,90 @@ impl MaxRects {
             i += 1;
         }
     }
+}
+
+impl MaxRects {
+    // Marks the rectangle at (x, y) as used, without picking a spot. Useful to replay placements
+    // made elsewhere (e.g. by another free space tracker). Can be undone, like 'insert'.
+    pub fn place_at(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        self.checkpoints.push(self.journal.len());
+        self.update_overlaps(&Rect::new(x, y, width, height));
+        self.remove_redundancy();
+        self.used.push(Rect::new(x, y, width, height));
+    }
 
-    // If target fits, returns (x, y) position of the bottom-left corner where it would fit.
-    // Will pick the lowest left-aligned fit.
-    pub fn bottom_left_insert(&mut self, width: usize, height: usize) -> Option<(usize, usize)> {
-        let mut best_index = None;
+    // Current free rectangles, as (x, y, width, height).
+    pub fn free_rects(&self) -> Vec<(usize, usize, usize, usize)> {
+        self.free.iter().map(|r| (r.x, r.y, r.w, r.h)).collect()
+    }
+}
+
+impl FreeSpace for MaxRects {
+    fn new(width: usize, height: usize) -> Self {
+        MaxRects {
+            width, height,
+            free: vec![Rect::new(0, 0, width, height)],
+            used: Vec::new(),
+            journal: Vec::new(),
+            checkpoints: Vec::new(),
+        }
+    }
+
+    fn candidates(&self, width: usize, height: usize) -> Vec<Point> {
+        self.free.iter().filter(|r| r.fits(width, height)).map(|r| (r.x, r.y)).collect()
+    }
+
+    fn insert(&mut self, width: usize, height: usize, heuristic: PlacementHeuristic) -> Option<Point> {
+        let mut best: Option<(usize, (isize, isize))> = None;
         for (i, rect) in self.free.iter().enumerate() {
             if rect.fits(width, height) {
-                let better_candidate = match best_index {
+                let score = free_rect_score(rect, width, height, heuristic, &self.used, self.width, self.height);
+                let better_candidate = match best {
                     None => true,
-                    Some(index) => {
-                        let best_rect: &Rect = &self.free[index];
-                        rect.y < best_rect.y || (rect.y == best_rect.y && rect.x < best_rect.x)
-                    }
+                    Some((_, best_score)) => score < best_score,
                 };
                 if better_candidate {
-                    best_index = Some(i);
+                    best = Some((i, score));
                 }
             }
         }
-        let best_index = best_index?;
+        let (best_index, _) = best?;
         let rect = &self.free[best_index];
         let (x, y) = (rect.x, rect.y);
+        self.checkpoints.push(self.journal.len());
         self.split_at(best_index, width, height);
+        self.used.push(Rect::new(x, y, width, height));
         Some((x, y))
     }
-}
 
+    fn num_placed(&self) -> usize {
+        self.used.len()
+    }
+
+    fn undo(&mut self) -> bool {
+        let checkpoint = match self.checkpoints.pop() {
+            Some(checkpoint) => checkpoint,
+            None => return false,
+        };
+        self.used.pop();
+        while self.journal.len() > checkpoint {
+            match self.journal.pop().unwrap() {
+                FreeSpaceChange::Pushed => {
+                    self.free.pop();
+                }
+                FreeSpaceChange::SwapRemoved(index, rect) => {
+                    if index == self.free.len() {
+                        self.free.push(rect);
+                    } else {
+                        let moved = std::mem::replace(&mut self.free[index], rect);
+                        self.free.push(moved);
+                    }
+                }
+            }
+        }
+        true
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -204,4 +234,51 @@ mod tests {
         assert_eq!((x, y), (0, 0));
         assert_eq!(m.free.len(), 2);
     }
+
+    #[test]
+    fn undo_restores_free_space() {
+        let mut m = MaxRects::new(10, 20);
+        m.bottom_left_insert(2, 3).unwrap();
+        let before: Vec<(usize, usize, usize, usize)> = m.free.iter().map(|r| (r.x, r.y, r.w, r.h)).collect();
+        m.bottom_left_insert(4, 4).unwrap();
+        m.bottom_left_insert(8, 2).unwrap();
+        assert!(m.undo());
+        assert!(m.undo());
+        let after: Vec<(usize, usize, usize, usize)> = m.free.iter().map(|r| (r.x, r.y, r.w, r.h)).collect();
+        assert_eq!(before, after);
+        assert_eq!(m.num_placed(), 1);
+        assert!(m.undo());
+        assert!(!m.undo());
+    }
+
+    #[test]
+    fn place_at_splits_around_rect() {
+        let mut m = MaxRects::new(10, 10);
+        m.place_at(4, 4, 2, 2);
+        let mut free = m.free_rects();
+        free.sort();
+        assert_eq!(free, vec![(0, 0, 4, 10), (0, 0, 10, 4), (0, 6, 10, 4), (6, 0, 4, 10)]);
+        assert!(m.undo());
+        assert_eq!(m.free_rects(), vec![(0, 0, 10, 10)]);
+    }
+
+    #[test]
+    fn best_area_fit_picks_tightest() {
+        let mut m = MaxRects::new(10, 10);
+        m.bottom_left_insert(6, 8).unwrap();
+        // Free: 4x10 on the right, 10x2 on top. The 10x2 leaves the least area unused.
+        let (x, y) = m.insert(2, 2, PlacementHeuristic::BestAreaFit).unwrap();
+        assert_eq!((x, y), (0, 8));
+    }
+
+    #[test]
+    fn contact_point_prefers_corners() {
+        let mut m = MaxRects::new(10, 10);
+        m.bottom_left_insert(10, 2).unwrap();
+        let (x, y) = m.insert(3, 3, PlacementHeuristic::ContactPoint).unwrap();
+        assert_eq!((x, y), (0, 2));
+        // Next to the previous one and the bottom rectangle.
+        let (x, y) = m.insert(3, 3, PlacementHeuristic::ContactPoint).unwrap();
+        assert_eq!((x, y), (3, 2));
+    }
 }
\ No newline at end of file
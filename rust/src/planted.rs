@@ -0,0 +1,174 @@
+// Generates "planted" instances: bags for which a perfect packing of a given width x height is known
+// to exist. Random bags of the perfect packing levels are not always solvable (odd 'T' shapes are
+// one known cause, but not the only one), so success rates on them mix solver weakness with
+// infeasibility. On planted instances, every failure is the solver's.
+//
+// The board is first cut randomly into blocks of at most 'MAX_BLOCK_AREA' cells (guillotine cuts,
+// keeping areas multiple of 4), since backtracking gets lost on big boards. Each block is then tiled
+// randomly: the first empty cell (lowest, then leftmost) is covered by a random totem and rotation
+// that fits, backtracking on dead ends. Shapes are tried in a random order, then their rotations,
+// so that shapes with more rotations aren't favored. Attempts that backtrack too much are restarted.
+// Note that the resulting bags are not distributed like the challenge's: shapes that tile easily
+// are more frequent, there is always an even number of 'T' shapes, and no totem crosses a block
+// boundary.
+
+use crate::{
+    game_interface::{TotemAnswer, TotemBag, TOTEMS},
+    shape_info::ShapeVariant,
+};
+use rand::{seq::SliceRandom, Rng};
+
+const MAX_RESTARTS: usize = 100;
+const MAX_BLOCK_AREA: usize = 128;
+// Blocks are at least this wide and high (unless the board isn't), so that they're easy to tile.
+const MIN_BLOCK_SIDE: usize = 4;
+// Backtracks allowed per attempt, per cell of the board.
+const BACKTRACKS_PER_CELL: usize = 4;
+
+pub struct PlantedInstance {
+    pub bag: TotemBag,
+    // Perfect packing of 'bag', placed at (0, 0).
+    pub solution: Vec<TotemAnswer>,
+}
+
+struct Tiler<'a, R: Rng> {
+    width: usize,
+    height: usize,
+    filled: Vec<bool>,  // Indexed by y * width + x.
+    placed: Vec<TotemAnswer>,
+    backtracks_left: usize,
+    rng: &'a mut R,
+}
+
+impl<'a, R: Rng> Tiler<'a, R> {
+    fn new(width: usize, height: usize, rng: &'a mut R) -> Self {
+        Tiler {
+            width, height,
+            filled: vec![false; width * height],
+            placed: Vec::with_capacity(width * height / 4),
+            backtracks_left: BACKTRACKS_PER_CELL * width * height,
+            rng,
+        }
+    }
+
+    // Coordinates of 'variant' placed with its anchor on (x, y), if it fits.
+    fn placement(&self, variant: &ShapeVariant, x: usize, y: usize) -> Option<TotemAnswer> {
+        let (anchor_x, anchor_y) = variant.anchor();
+        if x < anchor_x || x - anchor_x + variant.width > self.width || y + variant.height > self.height {
+            return None;
+        }
+        let totem = TotemAnswer::new(variant.shape, variant.coords).offset_by(x - anchor_x, y - anchor_y);
+        let fits = totem.coordinates.iter().all(|&(x, y)| !self.filled[y * self.width + x]);
+        if fits { Some(totem) } else { None }
+    }
+
+    fn set(&mut self, totem: &TotemAnswer, filled: bool) {
+        for &(x, y) in &totem.coordinates {
+            self.filled[y * self.width + x] = filled;
+        }
+    }
+
+    // Tiles the board from the first empty cell at or after 'cell'. Gives up (returns false)
+    // once out of backtracks.
+    fn fill(&mut self, cell: usize) -> bool {
+        let cell = match (cell..self.filled.len()).find(|&i| !self.filled[i]) {
+            Some(cell) => cell,
+            None => return true,
+        };
+        let (x, y) = (cell % self.width, cell / self.width);
+        let mut shapes = TOTEMS;
+        shapes.shuffle(self.rng);
+        for shape in &shapes {
+            let mut variants: Vec<&ShapeVariant> = ShapeVariant::get_rotations(shape).iter().collect();
+            variants.shuffle(self.rng);
+            for variant in variants {
+                if let Some(totem) = self.placement(variant, x, y) {
+                    self.set(&totem, true);
+                    self.placed.push(totem);
+                    if self.fill(cell + 1) {
+                        return true;
+                    }
+                    let totem = self.placed.pop().unwrap();
+                    self.set(&totem, false);
+                    if self.backtracks_left == 0 {
+                        return false;
+                    }
+                    self.backtracks_left -= 1;
+                }
+            }
+        }
+        false
+    }
+}
+
+// Cuts the (x, y, width, height) rectangle in blocks, in 'blocks'. Always cuts across the longest
+// side, at a random position that keeps both areas multiple of 4.
+fn cut_blocks<R: Rng>(rect: (usize, usize, usize, usize), rng: &mut R, blocks: &mut Vec<(usize, usize, usize, usize)>) {
+    let (x, y, width, height) = rect;
+    let (long, short) = if width >= height { (width, height) } else { (height, width) };
+    let cuts: Vec<usize> = (MIN_BLOCK_SIDE..=long.saturating_sub(MIN_BLOCK_SIDE))
+        .filter(|cut| (cut * short) % 4 == 0)
+        .collect();
+    match cuts.choose(rng) {
+        Some(&cut) if width * height > MAX_BLOCK_AREA => {
+            if width >= height {
+                cut_blocks((x, y, cut, height), rng, blocks);
+                cut_blocks((x + cut, y, width - cut, height), rng, blocks);
+            } else {
+                cut_blocks((x, y, width, cut), rng, blocks);
+                cut_blocks((x, y + cut, width, height - cut), rng, blocks);
+            }
+        }
+        _ => blocks.push(rect),
+    }
+}
+
+fn tile<R: Rng>(width: usize, height: usize, rng: &mut R) -> Option<Vec<TotemAnswer>> {
+    for _ in 0..MAX_RESTARTS {
+        let mut tiler = Tiler::new(width, height, rng);
+        if tiler.fill(0) {
+            return Some(tiler.placed);
+        }
+    }
+    None
+}
+
+// Randomly tiles a width x height board. Returns None if no tiling was found (e.g. if the area is
+// not a multiple of 4).
+pub fn generate<R: Rng>(width: usize, height: usize, rng: &mut R) -> Option<PlantedInstance> {
+    if width == 0 || height == 0 || (width * height) % 4 != 0 {
+        return None;
+    }
+    let mut blocks = Vec::new();
+    cut_blocks((0, 0, width, height), rng, &mut blocks);
+    let mut solution = Vec::with_capacity(width * height / 4);
+    for (x, y, block_width, block_height) in blocks {
+        let totems = tile(block_width, block_height, rng)?;
+        solution.extend(totems.iter().map(|totem| totem.offset_by(x, y)));
+    }
+    let bag = TotemBag::from_iter(solution.iter().map(|totem| totem.shape));
+    Some(PlantedInstance { bag, solution })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game_interface::Answer,
+        instances::{question_from_bag, seeded_rng},
+        scoring::{answer_score, score},
+    };
+
+    #[test]
+    fn planted_solution_is_perfect() {
+        let mut rng = seeded_rng(42, 0);
+        for (width, height) in [(4, 4), (6, 10), (23, 32)] {
+            let instance = generate(width, height, &mut rng).unwrap();
+            assert_eq!(instance.bag.total() * 4, width * height);
+            let question = question_from_bag(&instance.bag);
+            let answer = Answer::new(instance.solution);
+            assert_eq!(answer_score(&question, &answer), Ok(score(instance.bag.total(), width, height)));
+        }
+        assert!(generate(3, 5, &mut rng).is_none());
+    }
+}
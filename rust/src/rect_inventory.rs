@@ -4,13 +4,16 @@
 
 use crate::{
     dlx_solver,
-    game_interface::{Question, TotemAnswer, TotemBag, TotemQuestion, TOTEMS},
+    game_interface::{Point, Question, TotemAnswer, TotemBag, TotemQuestion, TOTEMS},
     solver::Solver,
+    transform::{Transform, TRANSFORMS},
 };
 use itertools::Itertools;
-use serde::{Deserialize, Serialize};
-use std::io::{self, prelude::*};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashSet;
+use std::io::prelude::*;
 use std::fs::{self, File};
+use tracing::info;
 
 #[derive(Serialize, Deserialize, Clone, Copy)]
 pub struct Dims {
@@ -28,33 +31,160 @@ impl Dims {
 pub struct Rectangle {
     pub dims: Dims,
     pub cost: TotemBag,
-    pub totems: Vec<TotemAnswer>,
+    // Distinct ways to tile this rectangle with exactly 'cost' (up to the symmetries that keep the
+    // same dims and cost). There is always at least one, the first one being the one DLX found.
+    // Inventories saved before several tilings were kept have a single one, as 'totems'.
+    #[serde(alias = "totems", deserialize_with = "deserialize_tilings")]
+    pub tilings: Vec<Vec<TotemAnswer>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedTilings {
+    Many(Vec<Vec<TotemAnswer>>),
+    One(Vec<TotemAnswer>),
+}
+
+fn deserialize_tilings<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Vec<TotemAnswer>>, D::Error> {
+    Ok(match SavedTilings::deserialize(deserializer)? {
+        SavedTilings::Many(tilings) => tilings,
+        SavedTilings::One(totems) => vec![totems],
+    })
 }
 
 impl Rectangle {
-    fn new(dims: Dims, cost: TotemBag, totems: Vec<TotemAnswer>) -> Self {
-        Rectangle { dims, cost, totems }
+    fn new(dims: Dims, cost: TotemBag, tilings: Vec<Vec<TotemAnswer>>) -> Self {
+        Rectangle { dims, cost, tilings }
+    }
+
+    // Totems of the default tiling.
+    pub fn totems(&self) -> &[TotemAnswer] {
+        &self.tilings[0]
+    }
+
+    pub fn num_tilings(&self) -> usize {
+        self.tilings.len()
+    }
+
+    // Applies a symmetry to the rectangle and all its tilings. Note that mirrors change the cost
+    // (J and L are swapped, as are S and Z).
+    pub fn transform(&self, transform: Transform) -> Rectangle {
+        let (width, height) = transform.dims(self.dims.width, self.dims.height);
+        let tilings = self.tilings.iter().map(|tiling| {
+            tiling.iter().map(|totem| transform.apply_totem(totem, self.dims.width, self.dims.height)).collect()
+        }).collect();
+        Rectangle::new(Dims::new(width, height), transform.apply_bag(&self.cost), tilings)
     }
 
     // Rotates a rectangle 90 degrees clockwise.
     pub fn rotate(&self) -> Rectangle {
-        // Flip width/height.
-        let dims = Dims::new(/*width=*/self.dims.height, /*height=*/self.dims.width);  
-        let mut totems = self.totems.clone();
-        for totem in &mut totems {
-            for coord in &mut totem.coordinates {
-                let (x, y) = *coord;
-                *coord = (y, self.dims.width - 1 - x);
+        self.transform(Transform::Rotate90)
+    }
+
+    // Totems of the tiling at 'tiling_index', after applying 'transform', without copying the
+    // other tilings.
+    pub fn variant(&self, tiling_index: usize, transform: Transform) -> Vec<TotemAnswer> {
+        self.tilings[tiling_index].iter()
+            .map(|totem| transform.apply_totem(totem, self.dims.width, self.dims.height))
+            .collect()
+    }
+}
+
+// Key identifying a tiling regardless of the order of its totems.
+fn tiling_key(tiling: &[TotemAnswer]) -> Vec<(usize, [Point; 4])> {
+    let mut key: Vec<(usize, [Point; 4])> = tiling.iter().map(|totem| {
+        let mut coords = totem.coordinates;
+        coords.sort_unstable();
+        (totem.shape as usize, coords)
+    }).collect();
+    key.sort_unstable();
+    key
+}
+
+// Key that is the same for all tilings that are symmetries of each other, for symmetries that keep
+// the same dims and cost.
+fn canonical_tiling_key(width: usize, height: usize, cost: &TotemBag,
+                        tiling: &[TotemAnswer]) -> Vec<(usize, [Point; 4])> {
+    TRANSFORMS.iter()
+        .filter(|t| t.dims(width, height) == (width, height) && t.apply_bag(cost).0 == cost.0)
+        .map(|t| {
+            let transformed: Vec<TotemAnswer> = tiling.iter().map(|totem| t.apply_totem(totem, width, height)).collect();
+            tiling_key(&transformed)
+        })
+        .min()
+        .unwrap()  // Identity is always there.
+}
+
+// Fill the lowest, then leftmost empty cell with each totem rotation that fits, recursively. Each
+// tiling is found exactly once (unlike DLX, which distinguishes between copies of the same totem).
+// Returns false when we should stop searching.
+fn enumerate_tilings(grid: &mut [Vec<bool>], bag: &mut TotemBag, placed: &mut Vec<TotemAnswer>,
+                     on_tiling: &mut dyn FnMut(&[TotemAnswer]) -> bool) -> bool {
+    let (width, height) = (grid[0].len(), grid.len());
+    let empty = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+        .find(|&(x, y)| !grid[y][x]);
+    let (x, y) = match empty {
+        Some(cell) => cell,
+        None => return on_tiling(placed),
+    };
+    for totem in TOTEMS.iter() {
+        if bag[totem] == 0 {
+            continue;
+        }
+        for variant in totem.get_rotations() {
+            // The variant's lowest, leftmost cell must land on (x, y).
+            let &(anchor_x, anchor_y) = variant.coords.iter().min_by_key(|(cx, cy)| (*cy, *cx)).unwrap();
+            if anchor_x > x || x - anchor_x + variant.width > width || y + variant.height - anchor_y > height {
+                continue;
+            }
+            let shape = variant.offset_by(x - anchor_x, y - anchor_y);
+            if shape.coords.iter().any(|&(cx, cy)| grid[cy][cx]) {
+                continue;
+            }
+            for &(cx, cy) in &shape.coords {
+                grid[cy][cx] = true;
+            }
+            bag[totem] -= 1;
+            placed.push(TotemAnswer::new(*totem, shape.coords));
+            let keep_going = enumerate_tilings(grid, bag, placed, on_tiling);
+            placed.pop();
+            bag[totem] += 1;
+            for &(cx, cy) in &shape.coords {
+                grid[cy][cx] = false;
+            }
+            if !keep_going {
+                return false;
             }
         }
-        Rectangle::new(dims, self.cost.clone(), totems)
     }
+    true
 }
 
+// Find up to 'max_tilings' distinct tilings of a 'width' x 'height' rectangle that use exactly 'bag',
+// starting with 'first'.
+fn distinct_tilings(width: usize, height: usize, bag: &TotemBag, first: Vec<TotemAnswer>,
+                    max_tilings: usize) -> Vec<Vec<TotemAnswer>> {
+    let mut seen = HashSet::new();
+    seen.insert(canonical_tiling_key(width, height, bag, &first));
+    let mut tilings = vec![first];
+    if tilings.len() >= max_tilings {
+        return tilings;
+    }
+    let mut grid = vec![vec![false; width]; height];
+    let mut remaining = bag.clone();
+    enumerate_tilings(&mut grid, &mut remaining, &mut Vec::new(), &mut |tiling| {
+        if seen.insert(canonical_tiling_key(width, height, bag, tiling)) {
+            tilings.push(tiling.to_vec());
+        }
+        tilings.len() < max_tilings
+    });
+    tilings
+}
 
-/// Get all shape combinations that give full rectangles with area up to 'max_area'.
+/// Get all shape combinations that give full rectangles with area up to 'max_area', keeping up to
+/// 'max_tilings' distinct tilings for each.
 /// Rectangles are returned with (w, h) dimensions such that w <= h.
-fn get_all_packings(max_area: usize) -> Vec<Rectangle> {
+fn get_all_packings(max_area: usize, max_tilings: usize) -> Vec<Rectangle> {
     let solver = dlx_solver::DlxSolver::new();
     let mut rectangles = Vec::new();
     for w in 1..=max_area {
@@ -63,10 +193,9 @@ fn get_all_packings(max_area: usize) -> Vec<Rectangle> {
             if (w * h) % 4 != 0 {
                 continue;
             }
-            print!("  Finding shapes that make up {}x{} rectangles...  ", w, h);
-            io::stdout().flush().unwrap();
             let num_shapes = w * h / 4;
             let mut num_found = 0;
+            let mut num_tilings = 0;
             for totems in TOTEMS.iter().combinations_with_replacement(num_shapes) {
                 let question = Question {
                     totems: totems.iter().map(|&t| TotemQuestion { shape: *t }).collect()
@@ -74,14 +203,12 @@ fn get_all_packings(max_area: usize) -> Vec<Rectangle> {
                 let bag = question.get_totem_bag();
                 if let Some(sln) = solver.try_solve(w, h, &bag) {
                     num_found += 1;
-                    rectangles.push(Rectangle {
-                        dims: Dims { width: w, height: h },
-                        cost: bag,
-                        totems: sln,
-                    });
+                    let tilings = distinct_tilings(w, h, &bag, sln, max_tilings);
+                    num_tilings += tilings.len();
+                    rectangles.push(Rectangle::new(Dims::new(w, h), bag, tilings));
                 }
             }
-            println!("{} found.", num_found);
+            info!(width = w, height = h, shapes = num_found, tilings = num_tilings, "Found shapes making up rectangle.");
         }
     }
     rectangles
@@ -120,18 +247,18 @@ pub struct RectangleInventory {
 }
 
 impl RectangleInventory {
-    fn new(rectangles: &Vec<Rectangle>) -> Self {
+    fn new(rectangles: PrecomputedRectangles) -> Self {
         let mut metadata = Vec::with_capacity(rectangles.len());
         for (idx, rect) in rectangles.iter().enumerate() {
             let meta = RectangleMetadata { dims: rect.dims, index: idx };
             metadata.push(meta);
         }
-        RectangleInventory { rectangles: rectangles.clone(), metadata: metadata }
+        RectangleInventory { rectangles, metadata }
     }
 
     pub fn from_precomputed(filename: &String) -> RectangleInventory {
         // TODO: make sure we can load.
-        println!("Loading precomputed rectangle inventory...");
+        info!(filename = %filename, "Loading precomputed rectangle inventory...");
         let saved = match fs::read_to_string(filename) {
             Err(err) => {
                 panic!("Failed to read precomputed rectangles at {}: {}. \
@@ -141,17 +268,17 @@ impl RectangleInventory {
             Ok(s) => s,
         };
         let rectangles: PrecomputedRectangles = serde_json::from_str(&saved).unwrap();
-        println!("Loaded {} rectangles.", rectangles.len());
-        Self::new(&rectangles)
+        info!(rectangles = rectangles.len(), "Loaded rectangle inventory.");
+        Self::new(rectangles)
     }
 
-    pub fn from_scratch(max_area: usize) -> RectangleInventory {
-        println!("Generating all rectangles that can be made up to area {}...", max_area);
-        Self::new(&get_all_packings(max_area))
+    pub fn from_scratch(max_area: usize, max_tilings: usize) -> RectangleInventory {
+        info!(max_area, "Generating all rectangles that can be made...");
+        Self::new(get_all_packings(max_area, max_tilings))
     }
 
     pub fn save(&self, filename: &std::path::Path) -> std::io::Result<()> {
-        println!("Saving precomputed rectangles to {}...", filename.display());
+        info!(filename = %filename.display(), "Saving precomputed rectangles...");
         let json = serde_json::to_string(&self.rectangles).unwrap();
         let mut f = File::create(filename)?;
         f.write_all(json.as_bytes())?;
@@ -165,11 +292,59 @@ impl RectangleInventory {
     // Returns all rectangles that could be used with the given bag.
     pub fn available_rectangles(&self, bag: &TotemBag) -> Vec<&RectangleMetadata> {
         self.metadata.iter().filter(|m| {
-            bag.can_afford(&self.get_cost(m))
+            bag.can_afford(self.get_cost(m))
         }).collect()
     }
 
     pub fn get_rectangle(&self, metadata: &RectangleMetadata) -> &Rectangle {
         &self.rectangles[metadata.index]
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Checks that the tiling exactly covers the rectangle using its cost.
+    fn assert_valid_tiling(dims: Dims, cost: &TotemBag, tiling: &[TotemAnswer]) {
+        let mut covered = vec![vec![false; dims.width]; dims.height];
+        for totem in tiling {
+            for &(x, y) in &totem.coordinates {
+                assert!(!covered[y][x]);
+                covered[y][x] = true;
+            }
+        }
+        assert!(covered.iter().all(|row| row.iter().all(|&c| c)));
+        assert_eq!(TotemBag::from_iter(tiling.iter().map(|t| t.shape)).0, cost.0);
+    }
+
+    #[test]
+    fn tilings_are_valid_and_distinct() {
+        let inventory = RectangleInventory::from_scratch(/*max_area=*/16, /*max_tilings=*/8);
+        let mut has_multiple_tilings = false;
+        for meta in &inventory.metadata {
+            let rect = inventory.get_rectangle(meta);
+            has_multiple_tilings |= rect.num_tilings() > 1;
+            let keys: HashSet<_> = rect.tilings.iter().map(|tiling| {
+                canonical_tiling_key(rect.dims.width, rect.dims.height, &rect.cost, tiling)
+            }).collect();
+            assert_eq!(keys.len(), rect.num_tilings());
+            for transform in TRANSFORMS.iter() {
+                let transformed = rect.transform(*transform);
+                for tiling in &transformed.tilings {
+                    assert_valid_tiling(transformed.dims, &transformed.cost, tiling);
+                }
+            }
+        }
+        assert!(has_multiple_tilings);
+    }
+
+    #[test]
+    fn loads_single_tiling_rectangles() {
+        let saved = r#"[{"dims":{"width":1,"height":4},"cost":[1,0,0,0,0,0,0],
+                        "totems":[{"shape":"I","coordinates":[[0,0],[0,1],[0,2],[0,3]]}]}]"#;
+        let rectangles: PrecomputedRectangles = serde_json::from_str(saved).unwrap();
+        assert_eq!(rectangles[0].num_tilings(), 1);
+        assert_eq!(rectangles[0].totems().len(), 1);
+    }
+}
@@ -17,21 +17,32 @@
 // A rectangle "move" here is deterministically made based on a common bin packing heuristic:
 // Bottom-Left, where the lowest possible placement is chosen, left-aligned. Simulations just pick
 // rectangles at random and also places them with this heuristic.
+// Other placement heuristics can be configured (see 'free_space::PlacementHeuristic'), in which case
+// each packing attempt cycles through them. The free space tracker (MaxRects by default) can be
+// configured too (see 'free_space::FreeSpaceKind').
+// Attempts can be given a deadline ('try_solve_until', used by the hybrid solver): it is checked
+// between the moves of the MCTS, and between packing attempts. So is the flag set when another
+// solver racing on the same problem found a solution (see 'set_stop_flag').
 
 use crate::{
+    decomposition::{BagDecomposer, DecompositionBias},
+    free_space::{FreeSpace, FreeSpaceKind, Guillotine, PlacementHeuristic, Skyline},
     game_interface::{TotemAnswer, TotemBag},
-    max_rects,
+    max_rects::MaxRects,
     rect_inventory::{RectangleInventory, RectangleMetadata},
-    solver::Solver,
-    subset_sum,
+    rect_selection,
+    solver::{Solver, StopFlag},
+    trace::{NoopObserver, SolverEvent, SolverObserver},
+    transform::Transform,
 };
 use rand::{
     self,
-    seq::SliceRandom,
     distributions::Distribution,
     Rng,
     SeedableRng,
 };
+use tracing::{debug, debug_span};
+use std::{cmp, time::Instant};
 
 #[derive(Clone)]
 struct Placement {
@@ -40,15 +51,15 @@ struct Placement {
     rect: RectangleMetadata,
 }
 
-#[derive(Clone)]
-struct State {
-    width: usize,
-    height: usize,
-
-    free_space: max_rects::MaxRects,
+// Search state. Instead of cloning it to explore moves, moves are done then undone in place.
+struct State<F: FreeSpace> {
+    free_space: F,
+    heuristic: PlacementHeuristic,
     placements: Vec<Placement>,
     // Index of rectangles that are left to place.
     indices_to_place: Vec<usize>,
+    // For each placement, the (index in indices_to_place, value) that was removed, to undo it.
+    removed_indices: Vec<(usize, usize)>,
 }
 
 struct SimulationResult {
@@ -57,47 +68,64 @@ struct SimulationResult {
     solution: Option<Vec<Placement>>,
 }
 
-impl State {
-    fn new(width: usize, height: usize, rectangles: &Vec<RectangleMetadata>) -> Self {
+impl<F: FreeSpace> State<F> {
+    fn new(width: usize, height: usize, rectangles: &[RectangleMetadata], heuristic: PlacementHeuristic) -> Self {
         State {
-            width: width, height: height,
-            free_space: max_rects::MaxRects::new(width, height),
+            free_space: F::new(width, height),
+            heuristic,
             placements: Vec::new(),
             // All rectangles should be placed -- they sum up to our totem bag.
             indices_to_place: (0..rectangles.len()).collect(),
+            removed_indices: Vec::new(),
         }
     }
 
-    // Place a rectangle based on the Bottom-Left heuristic, at its lowest possible position, left-aligned.
-    fn bottom_left_place(&mut self, indices_to_place_idx: usize, rect: &RectangleMetadata) -> Option<()> {
-        let (x, y) = self.free_space.bottom_left_insert(rect.dims.width, rect.dims.height)?;
-        self.placements.push(Placement { x: x, y: y, rect: *rect });
-        self.indices_to_place.swap_remove(indices_to_place_idx);
+    // Place a rectangle based on our placement heuristic (e.g. Bottom-Left, at its lowest possible position,
+    // left-aligned).
+    fn place(&mut self, indices_to_place_idx: usize, rect: &RectangleMetadata) -> Option<()> {
+        let (x, y) = self.free_space.insert(rect.dims.width, rect.dims.height, self.heuristic)?;
+        self.placements.push(Placement { x, y, rect: *rect });
+        let rect_index = self.indices_to_place.swap_remove(indices_to_place_idx);
+        self.removed_indices.push((indices_to_place_idx, rect_index));
         Some(())
     }
 
+    // Undo the last 'place', restoring the exact previous state.
+    fn unplace(&mut self) {
+        self.placements.pop();
+        self.free_space.undo();
+        let (index, rect_index) = self.removed_indices.pop().unwrap();
+        if index == self.indices_to_place.len() {
+            self.indices_to_place.push(rect_index);
+        } else {
+            let moved = std::mem::replace(&mut self.indices_to_place[index], rect_index);
+            self.indices_to_place.push(moved);
+        }
+    }
+
     fn random_legal_move(&mut self, rng: &mut rand::rngs::SmallRng,
-                         rectangles: &Vec<RectangleMetadata>) -> Option<()> {
+                         rectangles: &[RectangleMetadata]) -> Option<()> {
         let idx_dist = rand::distributions::Uniform::from(0..self.indices_to_place.len());
         let i = idx_dist.sample(rng);
         let rect_index = self.indices_to_place[i];
-        let mut rect = rectangles[rect_index].clone();
+        let mut rect = rectangles[rect_index];
         if !rect.is_square() && rng.gen::<bool>() {  // Rotate the initial rectangle.
             rect.rotate();
         }
-        if let Some(()) = self.bottom_left_place(i, &rect) {
+        if let Some(()) = self.place(i, &rect) {
             Some(())
         } else if !rect.is_square() {  // Try the other rotation, too.
             rect.rotate();
-            self.bottom_left_place(i, &rect)  // If it fails again, we can't place this rectangle at all.
+            self.place(i, &rect)  // If it fails again, we can't place this rectangle at all.
         } else {
             None
         }
     }
 
     // Place a random rectangle until we are stuck (or find a solution!)
+    // The placements are undone afterwards, leaving the state unchanged.
     fn simulate(&mut self, rng: &mut rand::rngs::SmallRng,
-                rectangles: &Vec<RectangleMetadata>) -> SimulationResult {
+                rectangles: &[RectangleMetadata]) -> SimulationResult {
         let mut depth = 0;
         while !self.indices_to_place.is_empty() {
             if let Some(()) = self.random_legal_move(rng, rectangles) {
@@ -106,49 +134,70 @@ impl State {
                 break;
             }
         }
-        SimulationResult {
-            depth: depth,
-            solution: if self.placements.len() == rectangles.len() { Some(self.placements.clone()) } else { None }
+        let solution = if self.placements.len() == rectangles.len() { Some(self.placements.clone()) } else { None };
+        for _ in 0..depth {
+            self.unplace();
         }
+        SimulationResult { depth, solution }
     }
 }
 
-// From our placed rectangles, recover the individual totems and their coordinates.
-fn convert_solution(placements: &Vec<Placement>, inventory: &RectangleInventory) -> Vec<TotemAnswer> {
+// From our placed rectangles, recover the individual totems and their coordinates. Rectangles with
+// several tilings use one at random, so that answers vary.
+fn convert_solution<R: Rng>(placements: &[Placement], inventory: &RectangleInventory,
+                            rng: &mut R) -> Vec<TotemAnswer> {
     let mut answer = Vec::new();
     for placement in placements {
-        let mut rect = inventory.get_rectangle(&placement.rect).clone();
-        if placement.rect.dims.width != rect.dims.width {  // rectangle is rotated
-            rect = rect.rotate();
-        }
-        for totem in &rect.totems {
+        let rect = inventory.get_rectangle(&placement.rect);
+        let transform = if placement.rect.dims.width != rect.dims.width {  // rectangle is rotated
+            Transform::Rotate90
+        } else {
+            Transform::Identity
+        };
+        let tiling = rng.gen_range(0..rect.num_tilings());
+        for totem in rect.variant(tiling, transform) {
             answer.push(totem.offset_by(placement.x, placement.y));
         }
     }
     answer
 }
 
-// Monte carlo tree search for rectangle packing.
+fn report_placement<O: SolverObserver>(observer: &mut O, placement: &Placement) {
+    observer.on_event(SolverEvent::PlaceRect {
+        x: placement.x, y: placement.y,
+        width: placement.rect.dims.width, height: placement.rect.dims.height,
+    });
+}
+
+// Monte carlo tree search for rectangle packing. Gives up once 'should_stop'.
 // Based on:
 // From https://www.researchgate.net/publication/343895750_Monte_carlo_tree_search_on_perfect_rectangle_packing_problem_instances
-fn mcts_packing(width: usize, height: usize, rectangles: &Vec<RectangleMetadata>, n_rolls: usize) -> Option<Vec<Placement>> {
-    let mut state = State::new(width, height, rectangles);
+fn mcts_packing<F: FreeSpace, O: SolverObserver>(width: usize, height: usize, rectangles: &[RectangleMetadata],
+                                                  n_rolls: usize, heuristic: PlacementHeuristic,
+                                                  should_stop: &dyn Fn() -> bool, observer: &mut O) -> Option<Vec<Placement>> {
+    let mut state = State::<F>::new(width, height, rectangles, heuristic);
     let mut rng = rand::rngs::SmallRng::from_entropy();
     loop {
-        let mut best_state: Option<State> = None;
+        if should_stop() {
+            return None;
+        }
+        let mut best_move: Option<(usize, RectangleMetadata)> = None;
         let mut best_score = 0f32;
         for i in 0..state.indices_to_place.len() {
             let rect_index = state.indices_to_place[i];
             let mut rect = rectangles[rect_index];
             let rotations = if rect.is_square() { 1 } else { 2 };
             for _ in 0..rotations {
-                let mut current_state = state.clone();
-                if let Some(()) = current_state.bottom_left_place(i, &rect) {
+                if let Some(()) = state.place(i, &rect) {
                     let mut depths = Vec::new();
                     for _ in 0..n_rolls {
-                        let mut state_sim = current_state.clone();
-                        let result = state_sim.simulate(&mut rng, rectangles);
+                        let result = state.simulate(&mut rng, rectangles);
+                        observer.on_event(SolverEvent::Rollout { depth: result.depth, total: rectangles.len() });
                         if let Some(solution) = result.solution {
+                            // Only the moves committed to so far were reported, report the rest.
+                            for placement in &solution[state.placements.len() - 1..] {
+                                report_placement(observer, placement);
+                            }
                             return Some(solution);
                         }
                         depths.push(result.depth);
@@ -163,52 +212,157 @@ fn mcts_packing(width: usize, height: usize, rectangles: &Vec<RectangleMetadata>
 
                         if score > best_score {
                             best_score = score;
-                            best_state = Some(current_state.clone());
+                            best_move = Some((i, rect));
                         }
                     }
+                    state.unplace();
                 }
                 rect.rotate();
             }
         }
-        if let Some(best_state) = best_state {
-            state = best_state;
+        if let Some((i, rect)) = best_move {
+            state.place(i, &rect).unwrap();
+            report_placement(observer, state.placements.last().unwrap());
         } else {
             return None;
         }
     }
 }
 
+fn is_past(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|deadline| Instant::now() > deadline)
+}
+
+// Bags with up to this many totems have their decompositions in rectangles solved exactly.
+const MAX_SMALL_BAG_TOTAL: usize = 12;
+
 #[derive(Clone)]
 pub struct RectPackingSolver {
     inventory: RectangleInventory,
+    decomposer: BagDecomposer,
+    free_space: FreeSpaceKind,
+    // Placement heuristics to cycle through, one per packing attempt.
+    heuristics: Vec<PlacementHeuristic>,
+    // Number of MCTS simulations per candidate move.
+    n_rolls: usize,
+    stop: StopFlag,
 }
 
-impl Solver for RectPackingSolver {
-    fn new() -> Self {
-        Self {
-            inventory: RectangleInventory::from_precomputed(
-                &"src/precomputed_area_32.rects".to_string()),
+impl RectPackingSolver {
+    pub fn with_options(free_space: FreeSpaceKind, heuristics: Vec<PlacementHeuristic>, n_rolls: usize) -> Self {
+        assert!(!heuristics.is_empty(), "Need at least one placement heuristic.");
+        assert!(n_rolls > 0, "Need at least one simulation per move.");
+        Self { free_space, heuristics, n_rolls, ..Self::new() }
+    }
+
+    // Stops the attempts in progress once 'stop' is.
+    pub fn set_stop_flag(&mut self, stop: StopFlag) {
+        self.stop = stop;
+    }
+
+    fn pack<O: SolverObserver>(&self, width: usize, height: usize, rectangles: &[RectangleMetadata],
+                               heuristic: PlacementHeuristic, deadline: Option<Instant>,
+                               observer: &mut O) -> Option<Vec<Placement>> {
+        let n_rolls = self.n_rolls;
+        let should_stop = || is_past(deadline) || self.stop.is_stopped();
+        match self.free_space {
+            FreeSpaceKind::MaxRects =>
+                mcts_packing::<MaxRects, O>(width, height, rectangles, n_rolls, heuristic, &should_stop, observer),
+            FreeSpaceKind::Skyline =>
+                mcts_packing::<Skyline, O>(width, height, rectangles, n_rolls, heuristic, &should_stop, observer),
+            FreeSpaceKind::Guillotine =>
+                mcts_packing::<Guillotine, O>(width, height, rectangles, n_rolls, heuristic, &should_stop, observer),
         }
     }
 
-    fn try_solve(&self, width: usize, height: usize, bag: &TotemBag) -> Option<Vec<TotemAnswer>> {
+    // Like 'try_solve', but gives up past 'deadline'.
+    pub fn try_solve_until(&self, width: usize, height: usize, bag: &TotemBag,
+                           deadline: Instant) -> Option<Vec<TotemAnswer>> {
+        self.attempt(width, height, bag, Some(deadline), &mut NoopObserver)
+    }
+
+    // Reports the rectangles committed to by the MCTS and its simulation results, then the
+    // totems of the solution once found.
+    fn attempt<O: SolverObserver>(&self, width: usize, height: usize, bag: &TotemBag, deadline: Option<Instant>,
+                                  observer: &mut O) -> Option<Vec<TotemAnswer>> {
+        if self.decomposer.is_ruled_out(bag) {
+            return None;
+        }
         let mut rng = rand::rngs::SmallRng::from_entropy();
-        let mut all_rectangles = self.inventory.available_rectangles(bag);
+        // Alternate between decompositions in big rectangles (~8 totems each, like 4x8s), and
+        // unconstrained ones.
+        let biases = [
+            DecompositionBias { target_rectangles: Some(cmp::max(bag.total() / 8, 1)), ..Default::default() },
+            DecompositionBias::default(),
+        ];
         let mut had_slns = false;
-        for _ in 0..5 {  // Try a couple of times, shuffling rectangles can help with the subset sum.
-            all_rectangles.shuffle(&mut rng);
-            let it = subset_sum::MultiDimSubsetSumIterator::new(bag, &self.inventory,
-                &all_rectangles, /*max_backtracks=*/50000);
-            for rectangles_sln in it.take(5) {  // Check a few rectangle combinations
+        let mut num_attempts = 0;
+        for bias in &biases {
+            let mut it = self.decomposer.decompositions(bag, bias, &mut rng, /*max_restarts=*/100);
+            let (mut num_drawn, mut num_packed) = (0, 0);
+            while num_packed < 12 && num_drawn < 24 {  // Check a few rectangle combinations
+                let batch: Vec<Vec<RectangleMetadata>> =
+                    debug_span!("subset_sum").in_scope(|| it.by_ref().take(6).collect());
+                if batch.is_empty() {
+                    break;
+                }
+                num_drawn += batch.len();
                 had_slns = true;
-                if let Some(sln) = mcts_packing(width, height, &rectangles_sln, /*n_rolls=*/7) {
-                    return Some(convert_solution(&sln, &self.inventory));
+                // Only pack the combinations that could tile the grid, most promising first.
+                for rectangles_sln in rect_selection::select_packable(width, height, batch) {
+                    if is_past(deadline) {
+                        debug!(attempts = num_attempts, "Out of time for rectangle packing attempts.");
+                        return None;
+                    }
+                    if self.stop.is_stopped() {
+                        debug!(attempts = num_attempts, "Stopped, another solver found a solution.");
+                        return None;
+                    }
+                    let heuristic = self.heuristics[num_attempts % self.heuristics.len()];
+                    if num_attempts > 0 {
+                        observer.on_event(SolverEvent::Restart);
+                    }
+                    num_attempts += 1;
+                    let packing = debug_span!("mcts", rectangles = rectangles_sln.len(), heuristic = ?heuristic)
+                        .in_scope(|| self.pack(width, height, &rectangles_sln, heuristic, deadline, observer));
+                    if let Some(sln) = packing {
+                        let answer = debug_span!("conversion").in_scope(|| convert_solution(&sln, &self.inventory, &mut rng));
+                        for totem in &answer {
+                            observer.on_event(SolverEvent::Place { totem: totem.clone() });
+                        }
+                        return Some(answer);
+                    }
+                    num_packed += 1;
                 }
             }
         }
         if had_slns {
-            println!("Had candidates, none could be packed.");
+            debug!(attempts = num_attempts, "Had candidates, none could be packed.");
+        }
+        None
+    }
+}
+
+impl Solver for RectPackingSolver {
+    fn new() -> Self {
+        let inventory = RectangleInventory::from_precomputed(
+            &"src/precomputed_area_32.rects".to_string());
+        let decomposer = BagDecomposer::new(&inventory, MAX_SMALL_BAG_TOTAL);
+        Self {
+            inventory, decomposer,
+            free_space: FreeSpaceKind::MaxRects,
+            heuristics: vec![PlacementHeuristic::BottomLeft],
+            n_rolls: 7,
+            stop: StopFlag::default(),
         }
-    None
+    }
+
+    fn try_solve(&self, width: usize, height: usize, bag: &TotemBag) -> Option<Vec<TotemAnswer>> {
+        self.try_solve_observed(width, height, bag, &mut NoopObserver)
+    }
+
+    fn try_solve_observed<O: SolverObserver>(&self, width: usize, height: usize, bag: &TotemBag,
+                                             observer: &mut O) -> Option<Vec<TotemAnswer>> {
+        self.attempt(width, height, bag, None, observer)
     }
 }
@@ -0,0 +1,109 @@
+// Geometric checks on a set of rectangles before trying to pack them in a width x height grid.
+// The decomposition of a bag in rectangles only looks at costs, so it can give sets that can't
+// possibly tile the grid. Packing those with MCTS is a waste of time, so we filter them out first,
+// then rank the others by how easy we expect them to be to pack.
+//
+// The main filter is based on "strips": in a perfect packing, any horizontal line crosses
+// rectangles whose widths sum up exactly to the grid width (and similarly for vertical lines).
+// So each rectangle must have an orientation for which the rest of the grid width (and height)
+// can be made up of sides of the other rectangles.
+
+use crate::rect_inventory::RectangleMetadata;
+use std::cmp;
+
+// Sums (up to 'max_sum') that can be made by picking either side (or none) of each rectangle,
+// ignoring the rectangle at index 'skip'.
+fn side_sums(rectangles: &[RectangleMetadata], skip: usize, max_sum: usize) -> Vec<bool> {
+    let mut reachable = vec![false; max_sum + 1];
+    reachable[0] = true;
+    for (i, rect) in rectangles.iter().enumerate() {
+        if i == skip {
+            continue;
+        }
+        let (w, h) = (rect.dims.width, rect.dims.height);
+        for sum in (0..=max_sum).rev() {
+            if reachable[sum] {
+                continue;
+            }
+            reachable[sum] = (sum >= w && reachable[sum - w]) || (sum >= h && reachable[sum - h]);
+        }
+    }
+    reachable
+}
+
+// Whether the rectangles could perfectly tile a 'width' x 'height' grid, based on necessary
+// conditions (area, fit, strips). Passing does not guarantee that a packing exists.
+pub fn is_geometrically_feasible(width: usize, height: usize, rectangles: &[RectangleMetadata]) -> bool {
+    let area: usize = rectangles.iter().map(|r| r.dims.width * r.dims.height).sum();
+    if area != width * height {
+        return false;
+    }
+    let max_side = cmp::max(width, height);
+    for (i, rect) in rectangles.iter().enumerate() {
+        let sums = side_sums(rectangles, i, max_side);
+        let (w, h) = (rect.dims.width, rect.dims.height);
+        let fits = |a: usize, b: usize| a <= width && b <= height && sums[width - a] && sums[height - b];
+        if !fits(w, h) && !fits(h, w) {
+            return false;
+        }
+    }
+    true
+}
+
+// Heuristic estimate of how easy a (feasible) set of rectangles is to pack. Higher is better.
+//   - rectangles with a side that divides the grid width or height can be stacked in strips;
+//   - fewer rectangles means fewer decisions to get right;
+//   - big rectangles are hard to fit late in the packing.
+pub fn packability_score(width: usize, height: usize, rectangles: &[RectangleMetadata]) -> f32 {
+    if rectangles.is_empty() {
+        return 0f32;
+    }
+    let divides = |side: usize| width % side == 0 || height % side == 0;
+    let num_dividing = rectangles.iter()
+        .filter(|r| divides(r.dims.width) || divides(r.dims.height))
+        .count();
+    let dividing_ratio = num_dividing as f32 / rectangles.len() as f32;
+    let pieces_ratio = rectangles.len() as f32 / (width * height) as f32;
+    let largest_area = rectangles.iter().map(|r| r.dims.width * r.dims.height).max().unwrap();
+    let largest_ratio = largest_area as f32 / (width * height) as f32;
+    dividing_ratio - pieces_ratio - largest_ratio
+}
+
+// Drops rectangle sets that can't tile the grid, and sorts the others from most to least packable.
+pub fn select_packable(width: usize, height: usize,
+                       candidates: Vec<Vec<RectangleMetadata>>) -> Vec<Vec<RectangleMetadata>> {
+    let mut scored: Vec<(f32, Vec<RectangleMetadata>)> = candidates.into_iter()
+        .filter(|rectangles| is_geometrically_feasible(width, height, rectangles))
+        .map(|rectangles| (packability_score(width, height, &rectangles), rectangles))
+        .collect();
+    scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+    scored.into_iter().map(|(_, rectangles)| rectangles).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rect_inventory::Dims;
+
+    fn rects(dims: &[(usize, usize)]) -> Vec<RectangleMetadata> {
+        dims.iter().map(|&(width, height)| RectangleMetadata { dims: Dims { width, height }, index: 0 }).collect()
+    }
+
+    #[test]
+    fn strips_feasible() {
+        // 8x8 made of four 4x4, or of 4x8 + two 4x4.
+        assert!(is_geometrically_feasible(8, 8, &rects(&[(4, 4), (4, 4), (4, 4), (4, 4)])));
+        assert!(is_geometrically_feasible(8, 8, &rects(&[(4, 8), (4, 4), (4, 4)])));
+    }
+
+    #[test]
+    fn too_long_infeasible() {
+        assert!(!is_geometrically_feasible(6, 6, &rects(&[(1, 8), (4, 7)])));
+    }
+
+    #[test]
+    fn strips_infeasible() {
+        // Same area as 8x8, but nothing can complete the 6x5 rectangle's strips.
+        assert!(!is_geometrically_feasible(8, 8, &rects(&[(3, 3), (5, 5), (6, 5)])));
+    }
+}
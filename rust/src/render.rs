@@ -0,0 +1,281 @@
+// Renders an 'Answer' as an image, to visually check solutions (and make write-up images).
+// Always renders to SVG. PNG is also supported with the "png" feature, rasterized by hand, since
+// everything is made of grid-aligned squares anyway.
+//
+// Each totem is coloured by shape, with an outline around each piece. Cells covered by more than
+// one totem are highlighted with a red cross, and empty cells are shown in gray.
+// Like the rest of the code, y points up: (0, 0) is the bottom-left cell.
+
+use crate::game_interface::{Answer, Totem, TOTEM_COUNT};
+use crate::scoring::Dims;
+use std::{fmt::Write, fs, io, path::Path};
+use tracing::warn;
+
+// RGB colour per totem, indexed like 'TOTEMS'.
+const TOTEM_COLORS: [(u8, u8, u8); TOTEM_COUNT] = [
+    (0x00, 0xbc, 0xd4),  // I: cyan
+    (0x3f, 0x51, 0xb5),  // J: blue
+    (0xff, 0x98, 0x00),  // L: orange
+    (0xff, 0xeb, 0x3b),  // O: yellow
+    (0x4c, 0xaf, 0x50),  // S: green
+    (0x9c, 0x27, 0xb0),  // T: purple
+    (0xf4, 0x43, 0x36),  // Z: red
+];
+const EMPTY_COLOR: (u8, u8, u8) = (0xe0, 0xe0, 0xe0);
+const OVERLAP_COLOR: (u8, u8, u8) = (0x21, 0x21, 0x21);
+const OVERLAP_MARK_COLOR: (u8, u8, u8) = (0xff, 0x17, 0x44);
+const OUTLINE_COLOR: (u8, u8, u8) = (0x21, 0x21, 0x21);
+const BACKGROUND_COLOR: (u8, u8, u8) = (0xff, 0xff, 0xff);
+
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    // Size of a grid cell, in pixels.
+    pub cell_size: usize,
+    // Margin around the grid, in pixels.
+    pub margin: usize,
+    // Minimum dimensions of the grid to render, e.g. the target dimensions, so that empty cells
+    // past the last totem are shown. By default, only the bounding box of the totems is rendered.
+    pub min_dims: Option<Dims>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions { cell_size: 24, margin: 8, min_dims: None }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Cell {
+    Empty,
+    // Index of the totem in the answer, and its shape.
+    Covered(usize, Totem),
+    Overlap,
+}
+
+// Grid of cells covered by the answer, shared by all output formats.
+struct Layout {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,  // indexed by y * width + x
+}
+
+impl Layout {
+    fn new(answer: &Answer, options: &RenderOptions) -> Self {
+        let coords = answer.totems.iter().flat_map(|t| t.coordinates.iter());
+        let (mut width, mut height) = coords.fold((0, 0), |(w, h), (x, y)| (w.max(x + 1), h.max(y + 1)));
+        if let Some((min_width, min_height)) = options.min_dims {
+            width = width.max(min_width);
+            height = height.max(min_height);
+        }
+        let mut cells = vec![Cell::Empty; width * height];
+        for (i, totem) in answer.totems.iter().enumerate() {
+            for (x, y) in &totem.coordinates {
+                let cell = &mut cells[y * width + x];
+                *cell = match *cell {
+                    Cell::Empty => Cell::Covered(i, totem.shape),
+                    _ => Cell::Overlap,
+                };
+            }
+        }
+        Layout { width, height, cells }
+    }
+
+    fn cell(&self, x: usize, y: usize) -> Cell {
+        self.cells[y * self.width + x]
+    }
+
+    // Whether there should be an outline between two cells (None being outside of the grid).
+    fn is_border(a: Option<Cell>, b: Option<Cell>) -> bool {
+        match (a, b) {
+            (Some(Cell::Covered(i, _)), Some(Cell::Covered(j, _))) => i != j,
+            (Some(Cell::Covered(..)), _) | (_, Some(Cell::Covered(..))) => true,
+            _ => false,
+        }
+    }
+
+    // Outline segments between cells, in grid units: (x1, y1, x2, y2), with y pointing up.
+    fn outline(&self) -> Vec<(usize, usize, usize, usize)> {
+        let mut segments = Vec::new();
+        for y in 0..self.height {
+            for x in 0..=self.width {  // Vertical edges, on the left of cell (x, y).
+                let left = if x > 0 { Some(self.cell(x - 1, y)) } else { None };
+                let right = if x < self.width { Some(self.cell(x, y)) } else { None };
+                if Self::is_border(left, right) {
+                    segments.push((x, y, x, y + 1));
+                }
+            }
+        }
+        for x in 0..self.width {
+            for y in 0..=self.height {  // Horizontal edges, below cell (x, y).
+                let below = if y > 0 { Some(self.cell(x, y - 1)) } else { None };
+                let above = if y < self.height { Some(self.cell(x, y)) } else { None };
+                if Self::is_border(below, above) {
+                    segments.push((x, y, x + 1, y));
+                }
+            }
+        }
+        segments
+    }
+
+    fn color(cell: Cell) -> (u8, u8, u8) {
+        match cell {
+            Cell::Empty => EMPTY_COLOR,
+            Cell::Covered(_, shape) => totem_color(shape),
+            Cell::Overlap => OVERLAP_COLOR,
+        }
+    }
+}
+
+// RGB colour used for a totem, e.g. to draw it elsewhere with the same colours.
+pub fn totem_color(shape: Totem) -> (u8, u8, u8) {
+    TOTEM_COLORS[shape as usize]
+}
+
+fn hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+pub fn render_svg(answer: &Answer, options: &RenderOptions) -> String {
+    let layout = Layout::new(answer, options);
+    let (cell, margin) = (options.cell_size, options.margin);
+    let image_width = layout.width * cell + 2 * margin;
+    let image_height = layout.height * cell + 2 * margin;
+    // Top-left pixel of a grid point (flipping y, since SVG's y points down).
+    let px = |x: usize| margin + x * cell;
+    let py = |y: usize| margin + (layout.height - y) * cell;
+
+    let mut svg = String::new();
+    // Note: writing to a String can't fail.
+    writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+             image_width, image_height, image_width, image_height).unwrap();
+    writeln!(svg, r#"<rect width="100%" height="100%" fill="{}"/>"#, hex(BACKGROUND_COLOR)).unwrap();
+    for y in 0..layout.height {
+        for x in 0..layout.width {
+            let c = layout.cell(x, y);
+            writeln!(svg, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                     px(x), py(y + 1), cell, cell, hex(Layout::color(c))).unwrap();
+            if c == Cell::Overlap {
+                let (left, top, right, bottom) = (px(x), py(y + 1), px(x + 1), py(y));
+                writeln!(svg, r#"<path d="M{} {}L{} {}M{} {}L{} {}" stroke="{}" stroke-width="2"/>"#,
+                         left, top, right, bottom, left, bottom, right, top, hex(OVERLAP_MARK_COLOR)).unwrap();
+            }
+        }
+    }
+    let mut path = String::new();
+    for (x1, y1, x2, y2) in layout.outline() {
+        write!(path, "M{} {}L{} {}", px(x1), py(y1), px(x2), py(y2)).unwrap();
+    }
+    writeln!(svg, r#"<path d="{}" stroke="{}" stroke-width="2" stroke-linecap="square" fill="none"/>"#,
+             path, hex(OUTLINE_COLOR)).unwrap();
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// RGB image, row by row from the top.
+#[cfg(feature = "png")]
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+#[cfg(feature = "png")]
+impl Image {
+    fn fill_rect(&mut self, left: usize, top: usize, width: usize, height: usize, (r, g, b): (u8, u8, u8)) {
+        for y in top..(top + height).min(self.height) {
+            for x in left..(left + width).min(self.width) {
+                let i = (y * self.width + x) * 3;
+                self.pixels[i..i + 3].copy_from_slice(&[r, g, b]);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "png")]
+pub fn render_png(answer: &Answer, options: &RenderOptions) -> Vec<u8> {
+    let layout = Layout::new(answer, options);
+    let (cell, margin) = (options.cell_size, options.margin);
+    let (width, height) = (layout.width * cell + 2 * margin, layout.height * cell + 2 * margin);
+    let (r, g, b) = BACKGROUND_COLOR;
+    let mut image = Image { width, height, pixels: [r, g, b].repeat(width * height) };
+    let px = |x: usize| margin + x * cell;
+    let py = |y: usize| margin + (layout.height - y) * cell;
+    for y in 0..layout.height {
+        for x in 0..layout.width {
+            let c = layout.cell(x, y);
+            image.fill_rect(px(x), py(y + 1), cell, cell, Layout::color(c));
+            if c == Cell::Overlap {
+                for i in 0..cell {  // Both diagonals, 2 pixels wide.
+                    image.fill_rect(px(x) + i, py(y + 1) + i, 2, 1, OVERLAP_MARK_COLOR);
+                    image.fill_rect(px(x) + i, py(y) - 1 - i, 2, 1, OVERLAP_MARK_COLOR);
+                }
+            }
+        }
+    }
+    for (x1, y1, x2, y2) in layout.outline() {
+        // Lines are 2 pixels wide, centered on the grid lines.
+        let (left, top) = (px(x1).saturating_sub(1), py(y1.max(y2)).saturating_sub(1));
+        let (width, height) = (px(x2) - px(x1) + 2, py(y1.min(y2)) - py(y1.max(y2)) + 2);
+        image.fill_rect(left, top, width, height, OUTLINE_COLOR);
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&image.pixels).unwrap();
+    writer.finish().unwrap();
+    png
+}
+
+// Saves the rendered answer, as a PNG if the path ends with ".png" (needs the "png" feature),
+// or as an SVG otherwise.
+pub fn save(answer: &Answer, path: &Path, options: &RenderOptions) -> io::Result<()> {
+    let is_png = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+    if is_png {
+        #[cfg(feature = "png")]
+        return fs::write(path, render_png(answer, options));
+        #[cfg(not(feature = "png"))]
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "PNG rendering needs the \"png\" feature"));
+    }
+    fs::write(path, render_svg(answer, options))
+}
+
+// Saves the answer in the directory given by the RENDER_DIR environment variable, if set.
+// Used by the clients, to be able to look at the answers that were sent.
+pub fn save_if_requested(answer: &Answer, name: &str) {
+    if let Ok(dir) = std::env::var("RENDER_DIR") {
+        let path = Path::new(&dir).join(format!("{}.svg", name));
+        if let Err(e) = fs::create_dir_all(&dir).and_then(|_| save(answer, &path, &RenderOptions::default())) {
+            warn!(path = %path.display(), error = %e, "Could not render answer.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_interface::TotemAnswer;
+
+    #[test]
+    fn outlines_pieces_and_highlights_overlaps() {
+        // Two O totems side by side, and an I overlapping the first one.
+        let answer = Answer::new(vec![
+            TotemAnswer::new(Totem::O, [(0, 0), (1, 0), (0, 1), (1, 1)]),
+            TotemAnswer::new(Totem::O, [(2, 0), (3, 0), (2, 1), (3, 1)]),
+            TotemAnswer::new(Totem::I, [(0, 1), (0, 2), (0, 3), (0, 4)]),
+        ]);
+        let layout = Layout::new(&answer, &RenderOptions { min_dims: Some((5, 5)), ..Default::default() });
+        assert_eq!((layout.width, layout.height), (5, 5));
+        assert_eq!(layout.cell(0, 1), Cell::Overlap);
+        assert_eq!(layout.cell(4, 4), Cell::Empty);
+        let outline = layout.outline();
+        // Edge between the two O totems.
+        assert!(outline.contains(&(2, 0, 2, 1)));
+        // No edge within a totem.
+        assert!(!outline.contains(&(1, 0, 1, 1)));
+        let svg = render_svg(&answer, &RenderOptions::default());
+        assert!(svg.starts_with("<svg") && svg.contains(&hex(OVERLAP_MARK_COLOR)));
+    }
+}
@@ -1,6 +1,11 @@
+use crate::{
+    game_interface::{Answer, Point, Question, Totem, TotemBag, TOTEM_COUNT},
+    shape_info::ShapeVariant,
+};
 use ordered_float::OrderedFloat;
 use std::cmp;
 use std::collections::HashSet;
+use std::fmt;
 use std::iter::FromIterator;
 
 pub fn score(num_totems: usize, width: usize, height: usize) -> f32 {
@@ -43,7 +48,6 @@ pub struct OptimalDimensions {
 impl OptimalDimensions {
     pub fn new() -> Self {
         let level_dims = (0..10_usize)
-            .into_iter()
             .map(|level| {
                 let num_totems = 1 << level;
                 let mut all_dims = get_all_dims(num_totems);
@@ -59,4 +63,92 @@ impl OptimalDimensions {
     pub fn level_dims(&self, level: usize) -> &[Dims] {
         &self.level_dims[level]
     }
+
+    // Best score that can be obtained on a given level (NOTE: 0-indexed!).
+    pub fn level_optimal_score(&self, level: usize) -> f32 {
+        if level == 0 {
+            1.5  // Best that can be done for "I" (always) is 1x4.
+        } else {
+            let num_totems = 1 << level;
+            let (w, h) = self.level_dims(level)[0];
+            score(num_totems, w, h)
+        }
+    }
+}
+
+impl Default for OptimalDimensions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Reasons why the server would reject an answer.
+#[derive(Debug, PartialEq, Eq)]
+pub enum InvalidAnswer {
+    DuplicateCoordinate(Point),
+    MissingOrigin,
+    // The coordinates of the totem at that index don't form its shape.
+    MalformedTotem { index: usize, shape: Totem },
+    ShapeMismatch { got: [usize; TOTEM_COUNT], expected: [usize; TOTEM_COUNT] },
+}
+
+impl fmt::Display for InvalidAnswer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvalidAnswer::DuplicateCoordinate(coord) => write!(f, "{{Dupe coords}}  {:?} appears more than once.", coord),
+            InvalidAnswer::MissingOrigin => write!(f, "{{Missing (0,0)}}  (0,0) missing from answer."),
+            InvalidAnswer::MalformedTotem { index, shape } => {
+                write!(f, "{{Malformed totem}}  Totem {} is not a {:?}.", index, shape)
+            }
+            InvalidAnswer::ShapeMismatch { got, expected } => {
+                write!(f, "{{Incorrect shapes}}  Shapes mismatch. Got {:?}, expected {:?}.", got, expected)
+            }
+        }
+    }
+}
+
+// Checks that 'answer' is a valid answer to 'question', and returns its score.
+pub fn answer_score(question: &Question, answer: &Answer) -> Result<f32, InvalidAnswer> {
+    let mut totems = TotemBag::new();
+    let mut coords = HashSet::with_capacity(question.totems.len() * 4);
+    for (index, totem) in answer.totems.iter().enumerate() {
+        if ShapeVariant::find_rotation(&totem.shape, &totem.coordinates).is_none() {
+            return Err(InvalidAnswer::MalformedTotem { index, shape: totem.shape });
+        }
+        for coord in totem.coordinates {
+            if !coords.insert(coord) {
+                return Err(InvalidAnswer::DuplicateCoordinate(coord));
+            }
+        }
+        totems[totem.shape] += 1;
+    }
+    if !coords.contains(&(0, 0)) {
+        return Err(InvalidAnswer::MissingOrigin);
+    }
+    let expected = question.get_totem_bag();
+    if totems.0 != expected.0 {
+        return Err(InvalidAnswer::ShapeMismatch { got: totems.0, expected: expected.0 });
+    }
+    let width = coords.iter().map(|p| p.0).max().unwrap() + 1;
+    let height = coords.iter().map(|p| p.1).max().unwrap() + 1;
+    Ok(score(question.totems.len(), width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_interface::{TotemAnswer, TotemQuestion};
+
+    #[test]
+    fn validates_totem_shapes() {
+        let question = Question { totems: vec![TotemQuestion { shape: Totem::I }, TotemQuestion { shape: Totem::O }] };
+        let i = TotemAnswer::new(Totem::I, [(0, 0), (1, 0), (2, 0), (3, 0)]);
+        let o = TotemAnswer::new(Totem::O, [(0, 1), (1, 1), (0, 2), (1, 2)]);
+        assert_eq!(answer_score(&question, &Answer::new(vec![i.clone(), o])), Ok(score(2, 4, 3)));
+        // Same cells as an 'O', but labeled a 'T'.
+        let question = Question { totems: vec![TotemQuestion { shape: Totem::I }, TotemQuestion { shape: Totem::T }] };
+        let t = TotemAnswer::new(Totem::T, [(0, 1), (1, 1), (0, 2), (1, 2)]);
+        assert_eq!(answer_score(&question, &Answer::new(vec![i, t])),
+                   Err(InvalidAnswer::MalformedTotem { index: 1, shape: Totem::T }));
+    }
 }
@@ -320,6 +320,26 @@ impl ShapeVariant {
         dims
     }
 
+    // Lowest, then leftmost cell of the shape: the one covering the first empty cell of a board
+    // (scanning rows from the bottom) when placed there.
+    pub fn anchor(&self) -> Point {
+        *self.coords.iter().min_by_key(|(x, y)| (*y, *x)).unwrap()
+    }
+
+    // Index in 'get_rotations' of the rotation that 'coords' are an offset of (in any order), if
+    // they form a 'totem' at all.
+    pub fn find_rotation(totem: &Totem, coords: &[Point; 4]) -> Option<usize> {
+        let x = coords.iter().map(|p| p.0).min().unwrap();
+        let y = coords.iter().map(|p| p.1).min().unwrap();
+        let mut coords = coords.map(|(cx, cy)| (cx - x, cy - y));
+        coords.sort_unstable();
+        ShapeVariant::get_rotations(totem).iter().position(|variant| {
+            let mut variant_coords = variant.coords;
+            variant_coords.sort_unstable();
+            variant_coords == coords
+        })
+    }
+
     pub fn offset_by(&self, x: usize, y: usize) -> ShapeVariant {
         let mut coords = self.coords.clone();
         for (dx, dy) in &mut coords {
@@ -0,0 +1,161 @@
+// Cache of solutions, keyed by bag and dimensions, persisted to disk across games.
+// Small levels repeat the same bags very often (level 2 only has 28 possible bags, level 3 has 210),
+// so there is no need to solve them again every game. Bags of more than 'MAX_CACHED_TOTEMS' are
+// not cached: they rarely repeat.
+//
+// Both packings found and proven failures (see 'certificates') are cached. Failures without a
+// proof are not, since another attempt could succeed.
+// The file has one JSON entry per line, appended as they are found, so that it is cheap to keep
+// up to date and survives crashes: a line cut short is skipped when loading. The solution of an
+// infeasible entry is null.
+
+use crate::{
+    game_interface::{TotemAnswer, TotemBag, TOTEM_COUNT},
+    subset_sum::{hash_bag, TotemBagHash},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tracing::warn;
+
+pub const MAX_CACHED_TOTEMS: usize = 8;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CachedResult {
+    Solved(Vec<TotemAnswer>),
+    Infeasible,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    bag: [usize; TOTEM_COUNT],
+    width: usize,
+    height: usize,
+    solution: Option<Vec<TotemAnswer>>,
+}
+
+type Key = (TotemBagHash, usize, usize);
+
+pub struct SolutionCache {
+    // Where new entries are appended, None to only cache in memory.
+    path: Option<PathBuf>,
+    entries: Mutex<HashMap<Key, CachedResult>>,
+}
+
+impl SolutionCache {
+    pub fn in_memory() -> Self {
+        SolutionCache { path: None, entries: Mutex::new(HashMap::new()) }
+    }
+
+    // Loads the entries of 'path', if it exists. New entries are appended to it.
+    // Malformed lines (e.g. cut short by a crash) are skipped.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut entries = HashMap::new();
+        if path.exists() {
+            for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+                let line = line?;
+                if line.is_empty() {
+                    continue;
+                }
+                let entry: Entry = match serde_json::from_str(&line) {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        warn!(path = %path.display(), line = index + 1, error = %e, "Skipping malformed cache entry.");
+                        continue;
+                    }
+                };
+                let result = match entry.solution {
+                    Some(solution) => CachedResult::Solved(solution),
+                    None => CachedResult::Infeasible,
+                };
+                entries.insert((hash_bag(&TotemBag(entry.bag)), entry.width, entry.height), result);
+            }
+        }
+        Ok(SolutionCache { path: Some(path.to_path_buf()), entries: Mutex::new(entries) })
+    }
+
+    pub fn is_cacheable(bag: &TotemBag) -> bool {
+        bag.total() <= MAX_CACHED_TOTEMS
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, bag: &TotemBag, width: usize, height: usize) -> Option<CachedResult> {
+        self.entries.lock().unwrap().get(&(hash_bag(bag), width, height)).cloned()
+    }
+
+    // Caches 'result' (if the bag is cacheable), and appends it to the file. Failing to write is
+    // only logged, the entry is still cached in memory.
+    pub fn insert(&self, bag: &TotemBag, width: usize, height: usize, result: CachedResult) {
+        if !Self::is_cacheable(bag) {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.get(&(hash_bag(bag), width, height)) == Some(&result) {
+            return;
+        }
+        if let Some(path) = &self.path {
+            let solution = match &result {
+                CachedResult::Solved(solution) => Some(solution.clone()),
+                CachedResult::Infeasible => None,
+            };
+            let entry = Entry { bag: bag.0, width, height, solution };
+            if let Err(e) = append_entry(path, &entry) {
+                warn!(path = %path.display(), error = %e, "Could not save solution to the cache.");
+            }
+        }
+        entries.insert((hash_bag(bag), width, height), result);
+    }
+}
+
+fn append_entry(path: &Path, entry: &Entry) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry).map_err(io::Error::from)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_interface::Totem;
+
+    #[test]
+    fn entries_persist() {
+        let path = std::env::temp_dir().join(format!("solution_cache_test_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let bag = TotemBag::from_iter([Totem::O, Totem::O]);
+        let solution = vec![
+            TotemAnswer::new(Totem::O, [(0, 0), (1, 0), (0, 1), (1, 1)]),
+            TotemAnswer::new(Totem::O, [(2, 0), (3, 0), (2, 1), (3, 1)]),
+        ];
+        let cache = SolutionCache::open(&path).unwrap();
+        cache.insert(&bag, 4, 2, CachedResult::Solved(solution.clone()));
+        cache.insert(&bag, 3, 3, CachedResult::Infeasible);
+        cache.insert(&bag, 3, 3, CachedResult::Infeasible);
+        // Too big to be cached.
+        let big_bag = TotemBag([MAX_CACHED_TOTEMS + 1, 0, 0, 0, 0, 0, 0]);
+        cache.insert(&big_bag, 4, 9, CachedResult::Infeasible);
+        // A line cut short by a crash, then appended to.
+        append_entry(&path, &Entry { bag: bag.0, width: 2, height: 4, solution: None }).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, &contents[..contents.len() - 10]).unwrap();
+        cache.insert(&bag, 5, 5, CachedResult::Infeasible);
+
+        let reloaded = SolutionCache::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reloaded.len(), 2);
+        assert_eq!(reloaded.get(&bag, 4, 2), Some(CachedResult::Solved(solution)));
+        assert_eq!(reloaded.get(&bag, 3, 3), Some(CachedResult::Infeasible));
+        assert_eq!(reloaded.get(&bag, 2, 4), None);
+    }
+}
@@ -1,24 +1,56 @@
-use crate::game_interface::{Answer, Question, TotemAnswer, TotemBag};
-use std::cmp;
+use crate::{
+    game_interface::{Answer, Question, TotemAnswer, TotemBag},
+    trace::{SolverEvent, SolverObserver},
+};
+use std::{
+    cmp,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+// Time to find an answer in (answers are due in 1s). The hybrid solver splits it across the
+// dimensions it tries.
+pub const DEFAULT_TIME_BUDGET: Duration = Duration::from_millis(850);
+
+// Shared by solvers racing on the same problem in parallel, so that the first one to find a
+// solution stops the others (see 'hybrid_solver::multithread_solver'). The default one is never
+// stopped.
+#[derive(Clone, Default)]
+pub struct StopFlag(Option<Arc<AtomicBool>>);
+
+impl StopFlag {
+    pub fn new() -> Self {
+        Self(Some(Arc::new(AtomicBool::new(false))))
+    }
 
+    pub fn stop(&self) {
+        if let Some(flag) = &self.0 {
+            flag.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.as_ref().is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
+}
 
 #[allow(clippy::single_component_path_imports)]
 pub mod macros {
     macro_rules! solver_boilerplate {
         ($($tt:tt)*) => {
-            #[cfg(feature = "timing")]
             let now = std::time::Instant::now();
 
             let answer: crate::game_interface::Answer = { $($tt)* };
 
-            #[cfg(feature = "timing")]
-            let delta = now.elapsed().as_millis();
+            let elapsed_ms = now.elapsed().as_millis() as u64;
 
             #[cfg(feature = "visualize")]
             crate::solver::visualize(&answer);
 
-            #[cfg(feature = "timing")]
-            println!("Took: {}ms", delta);
+            tracing::info!(elapsed_ms, "Solved.");
 
             answer
         };
@@ -33,7 +65,7 @@ pub trait Solver {
     // By default, use the simple solver.
     fn solve(&self, question: &Question) -> Answer {
         let num_totems = question.totems.len();
-        println!("Received question with {} totems.", num_totems);
+        tracing::info!(totems = num_totems, "Received question.");
         macros::solver_boilerplate! {
             Answer::new(self.simple_solver(question))
         }
@@ -41,6 +73,17 @@ pub trait Solver {
 
     fn try_solve(&self, width: usize, height: usize, bag: &TotemBag) -> Option<Vec<TotemAnswer>>;
 
+    // Like 'try_solve', but also reports search events to 'observer' (see 'trace').
+    // By default, only the placements of the solution found are reported.
+    fn try_solve_observed<O: SolverObserver>(&self, width: usize, height: usize, bag: &TotemBag,
+                                             observer: &mut O) -> Option<Vec<TotemAnswer>> {
+        let sln = self.try_solve(width, height, bag);
+        for totem in sln.iter().flatten() {
+            observer.on_event(SolverEvent::Place { totem: totem.clone() });
+        }
+        sln
+    }
+
     // Loop that tries the smallest possible square, then grows by 1 until solved.
     fn simple_solver(&self, question: &Question) -> Vec<TotemAnswer> {
         let bag = question.get_totem_bag();
@@ -48,7 +91,7 @@ pub trait Solver {
         let n_squares = num_totems * 4;
         let mut side = cmp::max((n_squares as f64).sqrt().ceil() as usize, 4);
         loop {
-            println!("Trying {0}x{0}...", side);
+            tracing::debug!(width = side, height = side, "Trying dims.");
             if let Some(sln) = self.try_solve(side, side, &bag) {
                 return sln;
             }
@@ -0,0 +1,197 @@
+// Solver picked from a textual spec, for tools that let the user choose (and compare) solvers.
+// A spec is a solver name, optionally followed by ':' and comma-separated key=value options, e.g.:
+//   hybrid
+//   rect:free_space=skyline,heuristics=bottom_left+contact_point,rolls=10
+//
+// Options per solver:
+//   - rect: 'free_space' (max_rects, skyline, guillotine), 'heuristics' ('+'-separated, among
+//     bottom_left, best_short_side_fit, best_long_side_fit, best_area_fit, contact_point) and 'rolls'.
+//   - greedy: weights of the placement features (see 'GreedyParams'): 'touchpoints', 'border',
+//     'origin', 'holes', 'height_increase', 'column_parity' and 'ties'. Unset ones keep their default.
+//     Also 'attempts', the number of packings attempted (see 'GreedySolver::set_max_attempts').
+//   - others: none.
+
+use crate::{
+    block_solver::BlockSolver,
+    dlx_solver::DlxSolver,
+    exhaustive_solver::ExhaustiveSolver,
+    free_space::{FreeSpaceKind, PlacementHeuristic},
+    game_interface::{Answer, Question, TotemAnswer, TotemBag},
+    greedy_solver::{GreedyParams, GreedySolver},
+    hybrid_solver::HybridSolver,
+    rect_packing_solver::RectPackingSolver,
+    solver::Solver,
+    trace::SolverObserver,
+};
+
+pub const SOLVER_NAMES: [&str; 6] = ["hybrid", "greedy", "exhaustive", "rect", "dlx", "blocks"];
+
+pub enum ConfiguredSolver {
+    Hybrid(Box<HybridSolver>),
+    Greedy(GreedySolver),
+    Exhaustive(ExhaustiveSolver),
+    RectPacking(Box<RectPackingSolver>),
+    Dlx(DlxSolver),
+    Blocks(BlockSolver),
+}
+
+fn parse_free_space(value: &str) -> Result<FreeSpaceKind, String> {
+    match value {
+        "max_rects" => Ok(FreeSpaceKind::MaxRects),
+        "skyline" => Ok(FreeSpaceKind::Skyline),
+        "guillotine" => Ok(FreeSpaceKind::Guillotine),
+        _ => Err(format!("unknown free space '{}'", value)),
+    }
+}
+
+fn parse_heuristic(value: &str) -> Result<PlacementHeuristic, String> {
+    match value {
+        "bottom_left" => Ok(PlacementHeuristic::BottomLeft),
+        "best_short_side_fit" => Ok(PlacementHeuristic::BestShortSideFit),
+        "best_long_side_fit" => Ok(PlacementHeuristic::BestLongSideFit),
+        "best_area_fit" => Ok(PlacementHeuristic::BestAreaFit),
+        "contact_point" => Ok(PlacementHeuristic::ContactPoint),
+        _ => Err(format!("unknown placement heuristic '{}'", value)),
+    }
+}
+
+// Splits "a=1,b=2" in [("a", "1"), ("b", "2")].
+fn parse_options(options: &str) -> Result<Vec<(&str, &str)>, String> {
+    options.split(',')
+        .filter(|option| !option.is_empty())
+        .map(|option| option.split_once('=').ok_or_else(|| format!("option '{}' should be key=value", option)))
+        .collect()
+}
+
+impl ConfiguredSolver {
+    // 'multithreading' is only used by the hybrid solver.
+    pub fn from_spec(spec: &str, multithreading: bool) -> Result<Self, String> {
+        let (name, options) = spec.split_once(':').unwrap_or((spec, ""));
+        let options = parse_options(options)?;
+        if name != "rect" && name != "greedy" {
+            if let Some((key, _)) = options.first() {
+                return Err(format!("solver '{}' has no option '{}'", name, key));
+            }
+        }
+        match name {
+            "hybrid" => Ok(ConfiguredSolver::Hybrid(Box::new(HybridSolver::with_options(multithreading)))),
+            "greedy" => {
+                let mut params = GreedyParams::default();
+                let mut max_attempts = None;
+                for (key, value) in options {
+                    if key == "attempts" {
+                        max_attempts = match value.parse() {
+                            Ok(attempts) if attempts > 0 => Some(attempts),
+                            _ => return Err(String::from("attempts must be a positive integer")),
+                        };
+                        continue;
+                    }
+                    let weight = match key {
+                        "touchpoints" => &mut params.touchpoints,
+                        "border" => &mut params.border,
+                        "origin" => &mut params.origin,
+                        "holes" => &mut params.holes,
+                        "height_increase" => &mut params.height_increase,
+                        "column_parity" => &mut params.column_parity,
+                        "ties" => &mut params.ties,
+                        _ => return Err(format!("solver 'greedy' has no option '{}'", key)),
+                    };
+                    *weight = value.parse().map_err(|_| format!("{} must be a number", key))?;
+                }
+                if params.ties < 0.0 {
+                    return Err(String::from("ties must be non-negative"));
+                }
+                let mut solver = GreedySolver::with_params(params);
+                if let Some(max_attempts) = max_attempts {
+                    solver.set_max_attempts(max_attempts);
+                }
+                Ok(ConfiguredSolver::Greedy(solver))
+            }
+            "exhaustive" => Ok(ConfiguredSolver::Exhaustive(ExhaustiveSolver::new())),
+            "dlx" => Ok(ConfiguredSolver::Dlx(DlxSolver::new())),
+            "blocks" => Ok(ConfiguredSolver::Blocks(BlockSolver::new())),
+            "rect" => {
+                let mut free_space = FreeSpaceKind::MaxRects;
+                let mut heuristics = vec![PlacementHeuristic::BottomLeft];
+                let mut n_rolls = 7;
+                for (key, value) in options {
+                    match key {
+                        "free_space" => free_space = parse_free_space(value)?,
+                        "heuristics" => {
+                            heuristics = value.split('+').map(parse_heuristic).collect::<Result<_, _>>()?;
+                        }
+                        "rolls" => {
+                            n_rolls = match value.parse() {
+                                Ok(n_rolls) if n_rolls > 0 => n_rolls,
+                                _ => return Err(String::from("rolls must be a positive integer")),
+                            };
+                        }
+                        _ => return Err(format!("solver 'rect' has no option '{}'", key)),
+                    }
+                }
+                Ok(ConfiguredSolver::RectPacking(Box::new(RectPackingSolver::with_options(free_space, heuristics, n_rolls))))
+            }
+            _ => Err(format!("unknown solver '{}', expected one of {:?}", name, SOLVER_NAMES)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConfiguredSolver::Hybrid(_) => "hybrid",
+            ConfiguredSolver::Greedy(_) => "greedy",
+            ConfiguredSolver::Exhaustive(_) => "exhaustive",
+            ConfiguredSolver::RectPacking(_) => "rect",
+            ConfiguredSolver::Dlx(_) => "dlx",
+            ConfiguredSolver::Blocks(_) => "blocks",
+        }
+    }
+
+    // Name of the strategy used on the given problem. Only the hybrid solver has more than one.
+    pub fn strategy(&self, width: usize, height: usize, bag: &TotemBag) -> &'static str {
+        match self {
+            ConfiguredSolver::Hybrid(solver) => solver.strategy(width, height, bag).name(),
+            ConfiguredSolver::RectPacking(_) => "rect_packing",
+            other => other.name(),
+        }
+    }
+}
+
+impl Solver for ConfiguredSolver {
+    fn new() -> Self {
+        ConfiguredSolver::Hybrid(Box::new(HybridSolver::new()))
+    }
+
+    fn solve(&self, question: &Question) -> Answer {
+        match self {
+            ConfiguredSolver::Hybrid(solver) => solver.solve(question),
+            ConfiguredSolver::Greedy(solver) => solver.solve(question),
+            ConfiguredSolver::Exhaustive(solver) => solver.solve(question),
+            ConfiguredSolver::RectPacking(solver) => solver.solve(question),
+            ConfiguredSolver::Dlx(solver) => solver.solve(question),
+            ConfiguredSolver::Blocks(solver) => solver.solve(question),
+        }
+    }
+
+    fn try_solve(&self, width: usize, height: usize, bag: &TotemBag) -> Option<Vec<TotemAnswer>> {
+        match self {
+            ConfiguredSolver::Hybrid(solver) => solver.try_solve(width, height, bag),
+            ConfiguredSolver::Greedy(solver) => solver.try_solve(width, height, bag),
+            ConfiguredSolver::Exhaustive(solver) => solver.try_solve(width, height, bag),
+            ConfiguredSolver::RectPacking(solver) => solver.try_solve(width, height, bag),
+            ConfiguredSolver::Dlx(solver) => solver.try_solve(width, height, bag),
+            ConfiguredSolver::Blocks(solver) => solver.try_solve(width, height, bag),
+        }
+    }
+
+    fn try_solve_observed<O: SolverObserver>(&self, width: usize, height: usize, bag: &TotemBag,
+                                             observer: &mut O) -> Option<Vec<TotemAnswer>> {
+        match self {
+            ConfiguredSolver::Hybrid(solver) => solver.try_solve_observed(width, height, bag, observer),
+            ConfiguredSolver::Greedy(solver) => solver.try_solve_observed(width, height, bag, observer),
+            ConfiguredSolver::Exhaustive(solver) => solver.try_solve_observed(width, height, bag, observer),
+            ConfiguredSolver::RectPacking(solver) => solver.try_solve_observed(width, height, bag, observer),
+            ConfiguredSolver::Dlx(solver) => solver.try_solve_observed(width, height, bag, observer),
+            ConfiguredSolver::Blocks(solver) => solver.try_solve_observed(width, height, bag, observer),
+        }
+    }
+}
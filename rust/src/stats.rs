@@ -0,0 +1,132 @@
+// Statistics helpers for the offline evaluation tools.
+
+use std::collections::BTreeMap;
+
+// Returns 95% confidence interval for the success probability given a given amount of 'successes'
+// over a given amount of 'trials'.
+// https://en.wikipedia.org/wiki/Binomial_proportion_confidence_interval
+// Using the Wilson score interval.
+pub fn binomial_confidence_interval(successes: u64, trials: u64) -> (f64, f64) {
+    let n = trials as f64;
+    let p_hat = successes as f64 / n;
+    let z: f64 = 1.96;  // alpha = 1 - 0.95 = 0.05 for 95% confidence, 1-alpha/2=0.975 => z=1.96
+    // Give names to variables for the general form of a +- b * c.sqrt(), to make it (a bit) easier to follow.
+    let a = (p_hat + z * z / (2f64 * n)) / (1f64 + z * z / n);
+    let b = z / (1f64 + z * z / n);
+    let c = p_hat * (1f64 - p_hat) / n + z * z / (4f64 * n * n);
+    let lower = a - b * c.sqrt();
+    (if lower >= 0f64 { lower } else { 0f64 }, a + b * c.sqrt())
+}
+
+// Two-sided p-value of the exact McNemar test, given the discordant pairs: how many instances only
+// A solved ('a_wins') and how many only B solved ('b_wins').
+// https://en.wikipedia.org/wiki/McNemar%27s_test
+// Under the null hypothesis, each discordant pair is a coin flip, so this is a binomial test.
+pub fn mcnemar_p_value(a_wins: u64, b_wins: u64) -> f64 {
+    let n = a_wins + b_wins;
+    if n == 0 {
+        return 1f64;
+    }
+    let k = a_wins.min(b_wins);
+    // Sum the binomial(n, 0.5) probabilities of [0, k] in log space to avoid underflows.
+    let ln_half_n = n as f64 * 0.5f64.ln();
+    let mut ln_choose = 0f64;  // ln(n choose i)
+    let mut tail = 0f64;
+    for i in 0..=k {
+        if i > 0 {
+            ln_choose += ((n - i + 1) as f64).ln() - (i as f64).ln();
+        }
+        tail += (ln_choose + ln_half_n).exp();
+    }
+    (2f64 * tail).min(1f64)
+}
+
+// Mean and 95% confidence half-width of a list of values.
+pub fn mean_with_margin(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    if values.len() < 2 {
+        return (mean, f64::INFINITY);
+    }
+    let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / (n - 1f64);
+    (mean, 1.96 * (variance / n).sqrt())
+}
+
+// Scores are bucketed to this precision, so that equal sums of scores are merged.
+const SCORE_SCALE: f64 = 1000f64;
+
+// Discrete probability distribution of a score, e.g. on one level, or over a full round of levels.
+#[derive(Clone, Debug)]
+pub struct ScoreDistribution {
+    // (score * SCORE_SCALE, probability), sorted by score.
+    outcomes: Vec<(i64, f64)>,
+}
+
+impl ScoreDistribution {
+    // Empirical distribution of the given samples.
+    pub fn from_samples(samples: &[f64]) -> Self {
+        let mut counts = BTreeMap::new();
+        for sample in samples {
+            *counts.entry((sample * SCORE_SCALE).round() as i64).or_insert(0f64) += 1f64;
+        }
+        let n = samples.len() as f64;
+        ScoreDistribution { outcomes: counts.into_iter().map(|(score, count)| (score, count / n)).collect() }
+    }
+
+    // Distribution of the sum of two independent scores.
+    pub fn sum(&self, other: &ScoreDistribution) -> Self {
+        let mut probabilities = BTreeMap::new();
+        for (score, p) in &self.outcomes {
+            for (other_score, other_p) in &other.outcomes {
+                *probabilities.entry(score + other_score).or_insert(0f64) += p * other_p;
+            }
+        }
+        ScoreDistribution { outcomes: probabilities.into_iter().collect() }
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.outcomes.iter().fold(0f64, |total, (score, p)| total + *score as f64 / SCORE_SCALE * p)
+    }
+
+    // Smallest score 's' such that P(score <= s) >= q.
+    pub fn percentile(&self, q: f64) -> f64 {
+        let mut cumulative = 0f64;
+        for (score, p) in &self.outcomes {
+            cumulative += p;
+            // Small tolerance for rounding errors in the sums of probabilities.
+            if cumulative >= q - 1e-12 {
+                return *score as f64 / SCORE_SCALE;
+            }
+        }
+        self.outcomes.last().map_or(0f64, |(score, _)| *score as f64 / SCORE_SCALE)
+    }
+
+    // P(score >= min_score).
+    pub fn probability_at_least(&self, min_score: f64) -> f64 {
+        let min_score = (min_score * SCORE_SCALE).round() as i64;
+        self.outcomes.iter().filter(|(score, _)| *score >= min_score).fold(0f64, |total, (_, p)| total + p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mcnemar_exact() {
+        // 1 vs 11 discordant pairs: 2 * (1 + 12) / 2^12.
+        assert!((mcnemar_p_value(1, 11) - 26f64 / 4096f64).abs() < 1e-12);
+        assert_eq!(mcnemar_p_value(5, 5), 1f64);
+        assert_eq!(mcnemar_p_value(0, 0), 1f64);
+    }
+
+    #[test]
+    fn score_distribution_sum() {
+        let coin = ScoreDistribution::from_samples(&[0f64, 1.5f64]);
+        let two_coins = coin.sum(&coin);
+        assert!((two_coins.mean() - 1.5f64).abs() < 1e-9);
+        assert!((two_coins.probability_at_least(3f64) - 0.25f64).abs() < 1e-9);
+        assert_eq!(two_coins.percentile(0.5f64), 1.5f64);
+        assert_eq!(two_coins.percentile(1f64), 3f64);
+    }
+}
@@ -12,10 +12,10 @@ use crate::{
 };
 use std::collections::HashSet;
 
-type TotemBagHash = u64;
+pub(crate) type TotemBagHash = u64;
 
 // Encode a distribution of 7 totems as a u64 (assuming MAX 512 qty per totem).
-fn hash_bag(bag: &TotemBag) -> TotemBagHash {
+pub(crate) fn hash_bag(bag: &TotemBag) -> TotemBagHash {
     let mut h = 0u64;
     let mut mult = 1u64;
     for totem in TOTEMS.iter() {
@@ -145,7 +145,7 @@ mod tests {
     fn simple_rectangles() {
         //                  I, J, L, O, S, T, Z
         let bag = TotemBag([4, 6, 0, 2, 0, 0, 0]);
-        let inventory = RectangleInventory::from_scratch(/*max_area=*/20);
+        let inventory = RectangleInventory::from_scratch(/*max_area=*/20, /*max_tilings=*/1);
         let rectangles = inventory.available_rectangles(&bag);
         let it = MultiDimSubsetSumIterator::new(&bag, &inventory, &rectangles, /*max_backtracks=*/10000000);
         let rect_sums: Vec<Vec<RectangleMetadata>> = it.collect();
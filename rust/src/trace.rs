@@ -0,0 +1,190 @@
+// Events reported by solvers while they search, to be able to step through a solve (e.g. to see
+// why a greedy run got stuck, or which moves the MCTS committed to).
+// Solvers report events to a 'SolverObserver' through 'Solver::try_solve_observed'. When not
+// tracing, 'NoopObserver' is used and everything gets compiled away.
+//
+// A trace is meant to be replayed in order by maintaining a board: 'Place' adds a totem,
+// 'Backtrack' removes the last one, and 'Restart' clears the board (e.g. a new greedy attempt).
+// Note that the DLX solver can't report its search (the 'dlx' crate doesn't expose it), so only
+// its solution is reported.
+
+use crate::{
+    game_interface::{TotemAnswer, TotemBag},
+    solver::Solver,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SolverEvent {
+    // Start of an attempt to pack the bag in the given dimensions.
+    TryDims { width: usize, height: usize, bag: TotemBag },
+    // Totem placed on the board.
+    Place { totem: TotemAnswer },
+    // Last totem placed is removed from the board.
+    Backtrack,
+    // Board is cleared, to start over.
+    Restart,
+    // Rectangle (of totems) committed to by the rectangle packing search.
+    PlaceRect { x: usize, y: usize, width: usize, height: usize },
+    // Result of a random simulation of the rectangle packing search: how many rectangles out of
+    // 'total' could be placed before getting stuck.
+    Rollout { depth: usize, total: usize },
+    // End of the attempt started by the last 'TryDims'.
+    Finish { solved: bool },
+}
+
+pub trait SolverObserver {
+    fn on_event(&mut self, event: SolverEvent);
+}
+
+// Observer that ignores everything, for regular solves.
+pub struct NoopObserver;
+
+impl SolverObserver for NoopObserver {
+    #[inline(always)]
+    fn on_event(&mut self, _event: SolverEvent) {}
+}
+
+// Observer that keeps all events, to be saved to disk and replayed.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct TraceRecorder {
+    pub events: Vec<SolverEvent>,
+}
+
+impl SolverObserver for TraceRecorder {
+    fn on_event(&mut self, event: SolverEvent) {
+        self.events.push(event);
+    }
+}
+
+impl TraceRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(writer, self).map_err(io::Error::from)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        serde_json::from_reader(reader).map_err(io::Error::from)
+    }
+}
+
+// State of the search after some events of a trace, e.g. to step through it in a viewer.
+#[derive(Clone, Debug, Default)]
+pub struct TraceState {
+    pub width: usize,
+    pub height: usize,
+    // Bag of the current attempt, and what is left of it to place.
+    pub bag: TotemBag,
+    pub bag_left: TotemBag,
+    pub totems: Vec<TotemAnswer>,
+    // Rectangles committed to by the rectangle packing search, as (x, y, width, height).
+    pub rects: Vec<(usize, usize, usize, usize)>,
+    // Number of restarts in the current attempt.
+    pub restarts: usize,
+    // Last rollout result, as (depth, total), if any since the last restart.
+    pub last_rollout: Option<(usize, usize)>,
+    // Set once the attempt is finished.
+    pub solved: Option<bool>,
+}
+
+impl TraceState {
+    pub fn apply(&mut self, event: &SolverEvent) {
+        match event {
+            SolverEvent::TryDims { width, height, bag } => {
+                *self = TraceState {
+                    width: *width, height: *height,
+                    bag: bag.clone(), bag_left: bag.clone(),
+                    ..Default::default()
+                };
+            }
+            SolverEvent::Place { totem } => {
+                self.bag_left[totem.shape] = self.bag_left[totem.shape].saturating_sub(1);
+                self.totems.push(totem.clone());
+            }
+            SolverEvent::Backtrack => {
+                if let Some(totem) = self.totems.pop() {
+                    self.bag_left[totem.shape] += 1;
+                }
+            }
+            SolverEvent::Restart => {
+                self.bag_left = self.bag.clone();
+                self.totems.clear();
+                self.rects.clear();
+                self.restarts += 1;
+                self.last_rollout = None;
+            }
+            SolverEvent::PlaceRect { x, y, width, height } => self.rects.push((*x, *y, *width, *height)),
+            SolverEvent::Rollout { depth, total } => self.last_rollout = Some((*depth, *total)),
+            SolverEvent::Finish { solved } => self.solved = Some(*solved),
+        }
+    }
+
+    // State after applying 'events[..=index]'. Only replays what's needed, since the last
+    // 'TryDims' (and 'Restart', past which only the number of restarts matters).
+    pub fn at(events: &[SolverEvent], index: usize) -> Self {
+        let events = &events[..=index];
+        let start = events.iter().rposition(|e| matches!(e, SolverEvent::TryDims { .. })).unwrap_or(0);
+        let last_restart = events[start..].iter().rposition(|e| *e == SolverEvent::Restart).map(|i| start + i);
+        let mut state = TraceState::default();
+        state.apply(&events[start]);
+        if let Some(last_restart) = last_restart {
+            state.restarts = events[start..last_restart].iter().filter(|e| **e == SolverEvent::Restart).count();
+            state.apply(&events[last_restart]);
+        }
+        for event in &events[last_restart.unwrap_or(start) + 1..] {
+            state.apply(event);
+        }
+        state
+    }
+}
+
+// 'Solver::try_solve_observed', wrapped in 'TryDims' and 'Finish' events.
+pub fn observed_attempt<S: Solver, O: SolverObserver>(solver: &S, width: usize, height: usize, bag: &TotemBag,
+                                                      observer: &mut O) -> Option<Vec<TotemAnswer>> {
+    observer.on_event(SolverEvent::TryDims { width, height, bag: bag.clone() });
+    let sln = solver.try_solve_observed(width, height, bag, observer);
+    observer.on_event(SolverEvent::Finish { solved: sln.is_some() });
+    sln
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{exhaustive_solver::ExhaustiveSolver, game_interface::Totem};
+
+    #[test]
+    fn exhaustive_trace_replays_to_solution() {
+        let bag = TotemBag::from_iter([Totem::L, Totem::J, Totem::O, Totem::I]);
+        let mut recorder = TraceRecorder::new();
+        let sln = observed_attempt(&ExhaustiveSolver::new(), 4, 4, &bag, &mut recorder).unwrap();
+
+        // Replaying the placements and backtracks should give back the solution.
+        let mut board: Vec<TotemAnswer> = Vec::new();
+        for event in &recorder.events[1..recorder.events.len() - 1] {
+            match event {
+                SolverEvent::Place { totem } => board.push(totem.clone()),
+                SolverEvent::Backtrack => { board.pop().unwrap(); }
+                other => panic!("Unexpected event: {:?}", other),
+            }
+        }
+        let coords = |totems: &[TotemAnswer]| totems.iter().map(|t| t.coordinates).collect::<Vec<_>>();
+        assert_eq!(coords(&board), coords(&sln));
+        assert_eq!(recorder.events.last(), Some(&SolverEvent::Finish { solved: true }));
+
+        let state = TraceState::at(&recorder.events, recorder.events.len() - 1);
+        assert_eq!(coords(&state.totems), coords(&sln));
+        assert_eq!(state.bag_left.total(), 0);
+        assert_eq!(state.solved, Some(true));
+    }
+}
@@ -0,0 +1,216 @@
+// Symmetries of a rectangle (the dihedral group D4): the 4 rotations and the 4 mirrors.
+// Used to reuse a tiling of a w x h rectangle in other orientations.
+// Note that mirroring a totem changes its identity: a mirrored J is an L, and a mirrored S is a Z.
+// Full answers can be transformed too, e.g. to reuse a w x h answer as a h x w one: rotations keep
+// the bag (unlike mirrors) and the score, which only depends on the dimensions up to rotation.
+
+use crate::{
+    game_interface::{Answer, Point, TotemAnswer, TotemBag},
+    scoring::Dims,
+};
+use serde::{Deserialize, Serialize};
+
+pub const TRANSFORM_COUNT: usize = 8;
+
+// Transforms that keep the shape of every totem.
+pub const ROTATIONS: [Transform; 4] = [
+    Transform::Identity,
+    Transform::Rotate180,
+    Transform::Rotate90,
+    Transform::Rotate270,
+];
+
+pub const TRANSFORMS: [Transform; TRANSFORM_COUNT] = [
+    Transform::Identity,
+    Transform::Rotate90,
+    Transform::Rotate180,
+    Transform::Rotate270,
+    Transform::MirrorX,
+    Transform::MirrorY,
+    Transform::Transpose,
+    Transform::AntiTranspose,
+];
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub enum Transform {
+    Identity,
+    // Rotations are clockwise (positive y is up).
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    // Flip left-right (x = width - 1 - x).
+    MirrorX,
+    // Flip bottom-top (y = height - 1 - y).
+    MirrorY,
+    // Swap x and y (mirror along the bottom-left to top-right diagonal).
+    Transpose,
+    // Mirror along the top-left to bottom-right diagonal.
+    AntiTranspose,
+}
+
+impl Transform {
+    // Whether this transform flips the orientation, turning J into L and S into Z.
+    pub fn is_mirror(&self) -> bool {
+        matches!(self, Transform::MirrorX | Transform::MirrorY | Transform::Transpose | Transform::AntiTranspose)
+    }
+
+    pub fn swaps_dims(&self) -> bool {
+        matches!(self, Transform::Rotate90 | Transform::Rotate270 | Transform::Transpose | Transform::AntiTranspose)
+    }
+
+    // (width, height) of a 'width' x 'height' rectangle after the transform.
+    pub fn dims(&self, width: usize, height: usize) -> (usize, usize) {
+        if self.swaps_dims() { (height, width) } else { (width, height) }
+    }
+
+    // Transform that undoes this one.
+    pub fn inverse(&self) -> Transform {
+        match self {
+            Transform::Rotate90 => Transform::Rotate270,
+            Transform::Rotate270 => Transform::Rotate90,
+            other => *other,
+        }
+    }
+
+    // Moves a point of a 'width' x 'height' rectangle to where it lands after the transform.
+    pub fn apply(&self, point: Point, width: usize, height: usize) -> Point {
+        let (x, y) = point;
+        match self {
+            Transform::Identity => (x, y),
+            Transform::Rotate90 => (y, width - 1 - x),
+            Transform::Rotate180 => (width - 1 - x, height - 1 - y),
+            Transform::Rotate270 => (height - 1 - y, x),
+            Transform::MirrorX => (width - 1 - x, y),
+            Transform::MirrorY => (x, height - 1 - y),
+            Transform::Transpose => (y, x),
+            Transform::AntiTranspose => (height - 1 - y, width - 1 - x),
+        }
+    }
+
+    // Transforms a totem placed within a 'width' x 'height' rectangle, fixing its shape if mirrored.
+    pub fn apply_totem(&self, totem: &TotemAnswer, width: usize, height: usize) -> TotemAnswer {
+        let mut coords = totem.coordinates;
+        for coord in &mut coords {
+            *coord = self.apply(*coord, width, height);
+        }
+        let shape = if self.is_mirror() { totem.shape.mirrored() } else { totem.shape };
+        TotemAnswer::new(shape, coords)
+    }
+
+    // Transforms every totem of an answer placed within a 'width' x 'height' rectangle.
+    pub fn apply_answer(&self, totems: &[TotemAnswer], width: usize, height: usize) -> Vec<TotemAnswer> {
+        totems.iter().map(|totem| self.apply_totem(totem, width, height)).collect()
+    }
+
+    // Cost of a set of totems after the transform.
+    pub fn apply_bag(&self, bag: &TotemBag) -> TotemBag {
+        if self.is_mirror() { bag.mirrored() } else { bag.clone() }
+    }
+}
+
+// (width, height) of the smallest rectangle from (0, 0) that contains every totem.
+pub fn answer_dims(totems: &[TotemAnswer]) -> Dims {
+    let coords = totems.iter().flat_map(|totem| totem.coordinates.iter());
+    coords.fold((0, 0), |(w, h), &(x, y)| (w.max(x + 1), h.max(y + 1)))
+}
+
+// Moves the totems so that their lowest row and leftmost column are at 0.
+pub fn translate_to_origin(totems: &[TotemAnswer]) -> Vec<TotemAnswer> {
+    let coords = || totems.iter().flat_map(|totem| totem.coordinates.iter());
+    let (min_x, min_y) = match (coords().map(|p| p.0).min(), coords().map(|p| p.1).min()) {
+        (Some(min_x), Some(min_y)) => (min_x, min_y),
+        _ => return Vec::new(),
+    };
+    totems.iter().map(|totem| TotemAnswer::new(totem.shape, totem.coordinates.map(|(x, y)| (x - min_x, y - min_y))))
+        .collect()
+}
+
+// Applies the first of 'transforms' after which an answer placed within a 'width' x 'height'
+// rectangle covers (0, 0), as the game requires. Gives the transformed answer and its dimensions,
+// or None if no transform does. Pass 'ROTATIONS' (or some of them) to keep the bag.
+pub fn cover_origin(totems: &[TotemAnswer], width: usize, height: usize,
+                    transforms: &[Transform]) -> Option<(Vec<TotemAnswer>, Dims)> {
+    transforms.iter().find_map(|transform| {
+        let (new_width, new_height) = transform.dims(width, height);
+        // Cell that lands on (0, 0).
+        let origin = transform.inverse().apply((0, 0), new_width, new_height);
+        totems.iter().any(|totem| totem.coordinates.contains(&origin))
+            .then(|| (transform.apply_answer(totems, width, height), (new_width, new_height)))
+    })
+}
+
+impl Answer {
+    // The answer transformed within its own dimensions (see 'answer_dims').
+    pub fn transformed(&self, transform: Transform) -> Answer {
+        let (width, height) = answer_dims(&self.totems);
+        Answer::new(transform.apply_answer(&self.totems, width, height))
+    }
+
+    // The answer moved so that it starts at row and column 0.
+    pub fn translated_to_origin(&self) -> Answer {
+        Answer::new(translate_to_origin(&self.totems))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_interface::Totem;
+
+    #[test]
+    fn inverse_undoes_transform() {
+        let (w, h) = (3, 5);
+        for transform in TRANSFORMS.iter() {
+            let (tw, th) = transform.dims(w, h);
+            for x in 0..w {
+                for y in 0..h {
+                    let moved = transform.apply((x, y), w, h);
+                    assert!(moved.0 < tw && moved.1 < th);
+                    assert_eq!(transform.inverse().apply(moved, tw, th), (x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mirror_swaps_shapes() {
+        //  J
+        //  J
+        // JJ
+        let j = TotemAnswer::new(Totem::J, [(0, 0), (1, 0), (1, 1), (1, 2)]);
+        let mirrored = Transform::MirrorX.apply_totem(&j, 2, 3);
+        assert_eq!(mirrored.shape, Totem::L);
+        let rotated = Transform::Rotate180.apply_totem(&j, 2, 3);
+        assert_eq!(rotated.shape, Totem::J);
+    }
+
+    #[test]
+    fn rotations_cover_origin() {
+        // I at the top, O at the bottom right, (0, 0) left empty.
+        // IIII
+        // ..OO
+        // ..OO
+        let totems = vec![
+            TotemAnswer::new(Totem::I, [(0, 2), (1, 2), (2, 2), (3, 2)]),
+            TotemAnswer::new(Totem::O, [(2, 0), (3, 0), (2, 1), (3, 1)]),
+        ];
+        let (rotated, dims) = cover_origin(&totems, 4, 3, &ROTATIONS).unwrap();
+        assert!(rotated.iter().any(|totem| totem.coordinates.contains(&(0, 0))));
+        assert_eq!(answer_dims(&rotated), dims);
+        assert_eq!(dims, (4, 3));
+        let (_, dims) = cover_origin(&totems, 4, 3, &[Transform::Rotate90, Transform::Rotate270]).unwrap();
+        assert_eq!(dims, (3, 4));
+        assert_eq!(cover_origin(&totems, 4, 3, &[Transform::Identity]), None);
+
+        let shifted: Vec<_> = totems.iter().map(|totem| totem.offset_by(2, 1)).collect();
+        assert_eq!(translate_to_origin(&shifted), totems);
+
+        let answer = Answer::new(totems.clone());
+        for transform in TRANSFORMS.iter() {
+            let (width, height) = transform.dims(4, 3);
+            let back = answer.transformed(*transform).transformed(transform.inverse());
+            assert_eq!(answer_dims(&answer.transformed(*transform).totems), (width, height));
+            assert_eq!(back.totems, totems);
+        }
+    }
+}